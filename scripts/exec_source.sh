@@ -0,0 +1,54 @@
+#!/bin/sh
+# Reference program for the `exec` source, storing chunks as files in a folder.
+#
+# chunkdrive runs: <command> <args...> <operation> [descriptor]
+#   create            print a new descriptor on stdout
+#   get <descriptor>  write the chunk to stdout
+#   put <descriptor>  replace the chunk with stdin
+#   delete <descriptor>  remove the chunk
+# Exit with 0 on success, 2 when the chunk does not exist and anything else on other errors.
+# Whatever is written to stderr ends up in chunkdrive's error message.
+#
+# Usage in the config:
+#   type: exec
+#   command: sh
+#   args: [scripts/exec_source.sh, /path/to/folder]
+
+set -u
+folder="$1"
+operation="$2"
+descriptor="${3:-}"
+
+case "$descriptor" in
+*/* | .*) echo "invalid descriptor" >&2; exit 1 ;;
+esac
+
+case "$operation" in
+create)
+    mkdir -p "$folder" || exit 1
+    while :; do
+        descriptor=$(LC_ALL=C tr -dc 'A-Za-z0-9' </dev/urandom | head -c 24)
+        # noclobber makes the redirection fail if the name is taken
+        if (set -C; : >"$folder/$descriptor") 2>/dev/null; then
+            printf '%s' "$descriptor"
+            exit 0
+        fi
+    done
+    ;;
+get)
+    [ -f "$folder/$descriptor" ] || exit 2
+    cat "$folder/$descriptor"
+    ;;
+put)
+    [ -f "$folder/$descriptor" ] || exit 2
+    cat >"$folder/.$descriptor.tmp" && mv "$folder/.$descriptor.tmp" "$folder/$descriptor"
+    ;;
+delete)
+    [ -f "$folder/$descriptor" ] || exit 2
+    rm "$folder/$descriptor"
+    ;;
+*)
+    echo "unknown operation $operation" >&2
+    exit 1
+    ;;
+esac
@@ -13,38 +13,51 @@ use futures::stream::BoxStream;
 use serde::{Deserialize, Serialize};
 
 use super::{direct_block::DirectBlock, indirect_block::IndirectBlock, stored_block::StoredBlock};
-use crate::global::GlobalTrait;
+use crate::{error::Error, global::GlobalTrait, stored::Stored};
 
 #[async_trait]
 pub trait Block {
     async fn range<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &self,
         global: Arc<U>,
-    ) -> Result<Range<usize>, String>;
+    ) -> Result<Range<u64>, Error>;
     fn get<'a, U: GlobalTrait + std::marker::Send + std::marker::Sync + 'a>(
         &'a self,
         global: Arc<U>,
-        range: Range<usize>,
-    ) -> BoxStream<'a, Result<Vec<u8>, String>>;
+        range: Range<u64>,
+    ) -> BoxStream<'a, Result<Vec<u8>, Error>>;
+    // Writes `data` at `range` into new chunks, the old ones may still be used by a snapshot or the saved file.
+    // Returns the chunks it replaced, to delete once nothing uses them anymore.
     async fn put<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &mut self,
         global: Arc<U>,
         data: Vec<u8>,
-        range: Range<usize>,
-    ) -> Result<(), String>;
+        range: Range<u64>,
+    ) -> Result<Vec<Stored>, Error>;
+    // Cuts the data off at `end`, copy-on-write like put
+    async fn truncate<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &mut self,
+        global: Arc<U>,
+        end: u64,
+    ) -> Result<Vec<Stored>, Error>;
     async fn delete<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &self,
         global: Arc<U>,
-    ) -> Result<(), String>;
+    ) -> Result<(), Error>;
+    // every chunk the block uses, for pinning them in snapshots
+    async fn chunks<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &self,
+        global: Arc<U>,
+    ) -> Result<Vec<Stored>, Error>;
     async fn create<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         global: Arc<U>,
         data: Vec<u8>,
-        start: usize,
-    ) -> Result<BlockType, String>;
+        start: u64,
+    ) -> Result<BlockType, Error>;
     fn to_enum(self) -> BlockType;
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BlockType {
     #[serde(rename = "d")]
     Direct(DirectBlock),
@@ -69,15 +82,15 @@ impl Block for BlockType {
     async fn range<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &self,
         global: Arc<U>,
-    ) -> Result<Range<usize>, String> {
+    ) -> Result<Range<u64>, Error> {
         match_method!(self, range, global).await
     }
 
     fn get<'a, U: GlobalTrait + std::marker::Send + std::marker::Sync + 'a>(
         &'a self,
         global: Arc<U>,
-        range: Range<usize>,
-    ) -> BoxStream<'a, Result<Vec<u8>, String>> {
+        range: Range<u64>,
+    ) -> BoxStream<'a, Result<Vec<u8>, Error>> {
         match_method!(self, get, global, range)
     }
 
@@ -85,23 +98,38 @@ impl Block for BlockType {
         &mut self,
         global: Arc<U>,
         data: Vec<u8>,
-        range: Range<usize>,
-    ) -> Result<(), String> {
+        range: Range<u64>,
+    ) -> Result<Vec<Stored>, Error> {
         match_method!(self, put, global, data, range).await
     }
 
+    async fn truncate<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &mut self,
+        global: Arc<U>,
+        end: u64,
+    ) -> Result<Vec<Stored>, Error> {
+        match_method!(self, truncate, global, end).await
+    }
+
     async fn delete<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &self,
         global: Arc<U>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         match_method!(self, delete, global).await
     }
 
+    async fn chunks<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &self,
+        global: Arc<U>,
+    ) -> Result<Vec<Stored>, Error> {
+        match_method!(self, chunks, global).await
+    }
+
     async fn create<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         global: Arc<U>,
         data: Vec<u8>,
-        start: usize,
-    ) -> Result<BlockType, String> {
+        start: u64,
+    ) -> Result<BlockType, Error> {
         IndirectBlock::create(global, data, start).await // we use indirect blocks, because they will fit any data size
     }
 
@@ -9,49 +9,88 @@ use serde::{Deserialize, Serialize};
 use std::{ops::Range, sync::Arc};
 
 use super::block::{Block, BlockType};
-use crate::global::{Descriptor, GlobalTrait};
+use crate::{
+    error::{Context, Error},
+    global::{Descriptor, GlobalTrait},
+    stored::Stored,
+};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DirectBlock {
     #[serde(rename = "b")]
     bucket: String,
     #[serde(rename = "d")]
     descriptor: Descriptor,
-    #[serde(rename = "r")]
-    range: Range<usize>,
+    #[serde(rename = "r", with = "super::range")]
+    range: Range<u64>,
 } // we use short names to reduce the size of the serialized data while allowing backwards compatibility
 
+impl DirectBlock {
+    fn stored(&self) -> Stored {
+        Stored::new(self.bucket.clone(), self.descriptor.clone())
+    }
+
+    // The whole chunk
+    pub async fn read<U: GlobalTrait>(&self, global: Arc<U>) -> Result<Vec<u8>, Error> {
+        let bucket = global
+            .get_bucket(&self.bucket)
+            .ok_or(Error::NotFound("Bucket not found".to_string()))?;
+        bucket.get(&self.descriptor).await
+    }
+
+    // Moves the block to a new chunk in the same bucket holding `data`, returns the old chunk
+    async fn rewrite<U: GlobalTrait>(
+        &mut self,
+        global: Arc<U>,
+        data: Vec<u8>,
+    ) -> Result<Stored, Error> {
+        let bucket = global
+            .get_bucket(&self.bucket)
+            .ok_or(Error::NotFound("Bucket not found".to_string()))?;
+        let descriptor = bucket
+            .create()
+            .await
+            .context("Could not create the descriptor")?;
+        if let Err(e) = bucket.put(&descriptor, data).await {
+            let _ = bucket.delete(&descriptor).await;
+            return Err(e.context("Could not put the data"));
+        }
+        let old = std::mem::replace(&mut self.descriptor, descriptor);
+        Ok(Stored::new(self.bucket.clone(), old))
+    }
+}
+
 #[async_trait]
 impl Block for DirectBlock {
     async fn range<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &self,
         _global: Arc<U>,
-    ) -> Result<Range<usize>, String> {
+    ) -> Result<Range<u64>, Error> {
         Ok(self.range.clone())
     }
 
     fn get<'a, U: GlobalTrait + std::marker::Send + std::marker::Sync + 'a>(
         &'a self,
         global: Arc<U>,
-        range: Range<usize>,
-    ) -> BoxStream<'a, Result<Vec<u8>, String>> {
+        range: Range<u64>,
+    ) -> BoxStream<'a, Result<Vec<u8>, Error>> {
         Box::pin(async_stream::stream! {
             if range.end <= self.range.start || range.start >= self.range.end {
                 return // the range is outside of the block, so we return an empty stream
             }
             let bucket = match global.get_bucket(&self.bucket) {
                 Some(bucket) => bucket,
-                None => Err("Bucket not found".to_string())?
+                None => Err(Error::NotFound("Bucket not found".to_string()))?
             };
             let data = match bucket.get(&self.descriptor).await {
                 Ok(data) => data,
-                Err(_) => Err("Could not get the data".to_string())?
+                Err(e) => Err(e.context("Could not get the data"))?
             };
 
             // calculate the data slice
             let start = std::cmp::max(range.start, self.range.start) - self.range.start;
             let end = std::cmp::min(range.end, self.range.end) - self.range.start;
-            let data = data[start..end].to_vec();
+            let data = data[start as usize..end as usize].to_vec();
             yield Ok(data);
         })
     }
@@ -61,69 +100,95 @@ impl Block for DirectBlock {
         &mut self,
         global: Arc<U>,
         data: Vec<u8>,
-        _range: Range<usize>,
-    ) -> Result<(), String> {
-        // put the data
-        let bucket = match global.get_bucket(&self.bucket) {
-            Some(bucket) => bucket,
-            None => return Err("Bucket not found".to_string()),
-        };
-        match bucket.put(&self.descriptor, data.clone()).await {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("Could not put the data: {}", e)),
+        range: Range<u64>,
+    ) -> Result<Vec<Stored>, Error> {
+        if range != self.range || data.len() as u64 != range.end - range.start {
+            return Err(Error::Invalid(
+                "Direct blocks can only be rewritten whole".to_string(),
+            ));
+        }
+        Ok(vec![self.rewrite(global, data).await?])
+    }
+
+    async fn truncate<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &mut self,
+        global: Arc<U>,
+        end: u64,
+    ) -> Result<Vec<Stored>, Error> {
+        if end >= self.range.end {
+            return Ok(Vec::new());
+        }
+        if end <= self.range.start {
+            return Err(Error::Invalid("Direct blocks can't be empty".to_string()));
         }
+        let mut data = self.read(global.clone()).await?;
+        data.truncate((end - self.range.start) as usize);
+        let old = self.rewrite(global, data).await?;
+        self.range.end = end;
+        Ok(vec![old])
     }
 
     async fn delete<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &self,
         global: Arc<U>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         let bucket = match global.get_bucket(&self.bucket) {
             Some(bucket) => bucket,
-            None => return Err("Bucket not found".to_string()),
+            None => return Err(Error::NotFound("Bucket not found".to_string())),
         };
+        // chunks a snapshot uses are freed with the last of those snapshots
+        if global.is_pinned(&self.stored()) {
+            return Ok(());
+        }
         bucket.delete(&self.descriptor).await
     }
 
+    async fn chunks<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &self,
+        _global: Arc<U>,
+    ) -> Result<Vec<Stored>, Error> {
+        Ok(vec![self.stored()])
+    }
+
     async fn create<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         global: Arc<U>,
         data: Vec<u8>,
-        start: usize,
-    ) -> Result<BlockType, String> {
+        start: u64,
+    ) -> Result<BlockType, Error> {
         // finding the buckets
         let bucket_name = global
             .random_bucket()
-            .ok_or("No buckets found".to_string())?;
+            .ok_or(Error::Internal("No buckets found".to_string()))?;
         let bucket = match global.get_bucket(bucket_name) {
             Some(bucket) => bucket,
-            None => Err("Bucket not found".to_string())?,
+            None => Err(Error::NotFound("Bucket not found".to_string()))?,
         };
 
         // slice the data
         let data = data[..std::cmp::min(data.len(), bucket.max_size())].to_vec();
         if data.is_empty() {
-            return Err("Data is empty".to_string());
+            return Err(Error::Invalid("Data is empty".to_string()));
         }
 
         // create descriptors
         let bucket = match global.get_bucket(bucket_name) {
             Some(bucket) => bucket,
-            None => Err("Bucket not found".to_string())?,
-        };
-        let descriptor = match bucket.create().await {
-            Ok(descriptor) => descriptor,
-            Err(e) => return Err(format!("Could not create the descriptor: {}", e)),
+            None => Err(Error::NotFound("Bucket not found".to_string()))?,
         };
+        let descriptor = bucket
+            .create()
+            .await
+            .context("Could not create the descriptor")?;
 
         // put the data
         let bucket = match global.get_bucket(bucket_name) {
             Some(bucket) => bucket,
-            None => Err("Bucket not found".to_string())?,
+            None => Err(Error::NotFound("Bucket not found".to_string()))?,
         };
         bucket.put(&descriptor, data.clone()).await?;
 
         Ok(BlockType::Direct(DirectBlock {
-            range: start..start + data.len(),
+            range: start..start + data.len() as u64,
             bucket: bucket_name.clone(),
             descriptor,
         }))
@@ -9,9 +9,9 @@ use super::{
     direct_block::DirectBlock,
     stored_block::StoredBlock,
 };
-use crate::global::GlobalTrait;
+use crate::{error::Error, global::GlobalTrait, stored::Stored};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndirectBlock {
     #[serde(rename = "b")]
     blocks: Vec<BlockType>, // we will make sure that these are in order
@@ -22,7 +22,7 @@ impl Block for IndirectBlock {
     async fn range<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &self,
         global: Arc<U>,
-    ) -> Result<Range<usize>, String> {
+    ) -> Result<Range<u64>, Error> {
         let first = match self.blocks.first() {
             Some(block) => block,
             None => return Ok(0..0),
@@ -41,8 +41,8 @@ impl Block for IndirectBlock {
     fn get<'a, U: GlobalTrait + std::marker::Send + std::marker::Sync + 'a>(
         &'a self,
         global: Arc<U>,
-        range: Range<usize>,
-    ) -> BoxStream<'a, Result<Vec<u8>, String>> {
+        range: Range<u64>,
+    ) -> BoxStream<'a, Result<Vec<u8>, Error>> {
         Box::pin(async_stream::stream! {
             for block in self.blocks.iter() {
                 let global_clone = global.clone();
@@ -55,57 +55,63 @@ impl Block for IndirectBlock {
         })
     }
 
+    // The blocks the write touches are rewritten into new chunks, the others are kept as they are.
+    // A write past the end goes into the last block, so appends keep filling its chunk.
     async fn put<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &mut self,
         global: Arc<U>,
         data: Vec<u8>,
-        range: Range<usize>,
-    ) -> Result<(), String> {
-        let start_offset = range.start;
-        let mut start = range.start;
-
-        for block in self.blocks.iter_mut() {
-            let block_range = block.range(global.clone()).await?;
-            if block_range.end <= start {
-                break;
+        range: Range<u64>,
+    ) -> Result<Vec<Stored>, Error> {
+        let mut blocks = self.blocks.clone();
+        let mut replaced = Vec::new();
+        let mut created = Vec::new(); // deleted again if a later part of the write fails
+        let result = write(
+            global.clone(),
+            &mut blocks,
+            &data,
+            range,
+            &mut replaced,
+            &mut created,
+        )
+        .await;
+        if let Err(err) = result {
+            for stored in created {
+                let _ = stored.delete(global.clone()).await;
             }
-            let slice_range = block_range.start - start_offset..block_range.end - start_offset;
-            let slice = data[slice_range].to_vec();
-            block.put(global.clone(), slice, block_range).await?;
-        }
-
-        start = match self.blocks.last() {
-            Some(block) => block.range(global.clone()).await?.end,
-            None => range.start,
-        };
-
-        // if data is left, we create new blocks just like we did in the create function
-        while start < range.end && self.blocks.len() < global.get_direct_block_count() {
-            let block = DirectBlock::create(
-                global.clone(),
-                data[(start - start_offset)..].to_vec(),
-                start,
-            )
-            .await?;
-            let range = block.range(global.clone()).await?;
-            start = range.end;
-            self.blocks.push(block.to_enum());
+            return Err(err);
         }
+        self.blocks = blocks;
+        Ok(replaced)
+    }
 
-        // if there is still data left, we create a stored block
-        if start < range.end {
-            let slice = data[start - start_offset..].to_vec();
-            let block = StoredBlock::create(global, slice, start).await?;
-            self.blocks.push(block.to_enum());
+    async fn truncate<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &mut self,
+        global: Arc<U>,
+        end: u64,
+    ) -> Result<Vec<Stored>, Error> {
+        let mut blocks = Vec::new();
+        let mut replaced = Vec::new();
+        for block in self.blocks.iter() {
+            let block_range = block.range(global.clone()).await?;
+            if block_range.start >= end {
+                replaced.extend(block.chunks(global.clone()).await?);
+                continue;
+            }
+            let mut block = block.clone();
+            if block_range.end > end {
+                replaced.extend(block.truncate(global.clone(), end).await?);
+            }
+            blocks.push(block);
         }
-
-        Ok(())
+        self.blocks = blocks;
+        Ok(replaced)
     }
 
     async fn delete<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &self,
         global: Arc<U>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         let mut errors = Vec::new();
         for block in self.blocks.iter() {
             match block.delete(global.clone()).await {
@@ -116,41 +122,30 @@ impl Block for IndirectBlock {
         if errors.is_empty() {
             Ok(())
         } else {
-            Err(errors.join(", "))
+            Err(Error::many(errors))
         }
     }
 
+    async fn chunks<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &self,
+        global: Arc<U>,
+    ) -> Result<Vec<Stored>, Error> {
+        let mut chunks = Vec::new();
+        for block in self.blocks.iter() {
+            chunks.extend(block.chunks(global.clone()).await?);
+        }
+        Ok(chunks)
+    }
+
     async fn create<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         global: Arc<U>,
         data: Vec<u8>,
-        start: usize,
-    ) -> Result<BlockType, String> {
+        start: u64,
+    ) -> Result<BlockType, Error> {
         let mut blocks = Vec::new(); // we will make sure that these are in order
-        let slice_offset = start;
-        let mut start = start;
-        let end = start + data.len();
-
-        let mut error: Option<String> = None;
-        while start < end && blocks.len() < global.get_direct_block_count() {
-            let block = DirectBlock::create(
-                global.clone(),
-                data[(start - slice_offset)..].to_vec(),
-                start,
-            )
-            .await?;
-            let range = match block.range(global.clone()).await {
-                Ok(range) => range,
-                Err(err) => {
-                    error = Some(err);
-                    break;
-                }
-            };
-            start = range.end;
-            blocks.push(block.to_enum());
-        }
 
         // if we encountered an error, we delete all the blocks we created
-        if let Some(err) = error {
+        if let Err(err) = fill(global.clone(), &mut blocks, &data, start).await {
             let mut errors = vec![err];
             for block in blocks.iter() {
                 match block.delete(global.clone()).await {
@@ -159,14 +154,7 @@ impl Block for IndirectBlock {
                 }
             }
 
-            return Err(errors.join(", "));
-        }
-
-        // if there is still data left, we create a stored block
-        if start < end {
-            let slice = data[start - slice_offset..].to_vec();
-            let block = StoredBlock::create(global, slice, start).await?;
-            blocks.push(block.to_enum());
+            return Err(Error::many(errors));
         }
 
         Ok(BlockType::Indirect(IndirectBlock { blocks }))
@@ -176,3 +164,125 @@ impl Block for IndirectBlock {
         BlockType::Indirect(self)
     }
 }
+
+// Adds blocks holding `data` from `start` on, direct ones up to the direct block count and a stored one for the rest
+async fn fill<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+    global: Arc<U>,
+    blocks: &mut Vec<BlockType>,
+    data: &[u8],
+    start: u64,
+) -> Result<(), Error> {
+    let slice_offset = start;
+    let mut start = start;
+    let end = start + data.len() as u64;
+
+    while start < end && blocks.len() < global.get_direct_block_count() {
+        let block = DirectBlock::create(
+            global.clone(),
+            data[(start - slice_offset) as usize..].to_vec(),
+            start,
+        )
+        .await?;
+        let range = block.range(global.clone()).await?;
+        start = range.end;
+        blocks.push(block.to_enum());
+    }
+
+    // if there is still data left, we create a stored block
+    if start < end {
+        let slice = data[(start - slice_offset) as usize..].to_vec();
+        let block = StoredBlock::create(global, slice, start).await?;
+        blocks.push(block.to_enum());
+    }
+    Ok(())
+}
+
+// See IndirectBlock::put, `created` gets the new chunks and `replaced` the ones they replace
+async fn write<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+    global: Arc<U>,
+    blocks: &mut Vec<BlockType>,
+    data: &[u8],
+    range: Range<u64>,
+    replaced: &mut Vec<Stored>,
+    created: &mut Vec<Stored>,
+) -> Result<(), Error> {
+    let count = blocks.len();
+    let mut index = 0;
+    while index < count {
+        let block_range = blocks[index].range(global.clone()).await?;
+        let last = index + 1 == count;
+        let start = std::cmp::max(range.start, block_range.start);
+        let end = match last {
+            true => range.end,
+            false => std::cmp::min(range.end, block_range.end),
+        };
+        if start >= end {
+            index += 1;
+            continue;
+        }
+        let part = &data[(start - range.start) as usize..(end - range.start) as usize];
+        let direct = match &blocks[index] {
+            BlockType::Direct(direct) => direct,
+            // stored blocks hold the rest of the file, they come last and nothing after them can fail
+            block => {
+                let mut block = block.clone();
+                replaced.extend(block.put(global.clone(), part.to_vec(), start..end).await?);
+                blocks[index] = block;
+                if last {
+                    return Ok(());
+                }
+                index += 1;
+                continue;
+            }
+        };
+        // the chunk with the write applied
+        let mut chunk = direct.read(global.clone()).await?;
+        let (from, to) = (
+            (start - block_range.start) as usize,
+            (end - block_range.start) as usize,
+        );
+        chunk.resize(std::cmp::max(chunk.len(), to), 0);
+        chunk[from..to].copy_from_slice(part);
+        if chunk.len() as u64 == block_range.end - block_range.start {
+            let mut block = blocks[index].clone();
+            replaced.extend(
+                block
+                    .put(global.clone(), chunk, block_range.clone())
+                    .await?,
+            );
+            created.extend(block.chunks(global.clone()).await?);
+            blocks[index] = block;
+        } else {
+            // the last chunk grew, it is split again like a new file's
+            replaced.extend(blocks[index].chunks(global.clone()).await?);
+            blocks.truncate(index);
+            let result = fill(global.clone(), blocks, &chunk, block_range.start).await;
+            for block in blocks[index..].iter() {
+                created.extend(block.chunks(global.clone()).await?);
+            }
+            result?;
+        }
+        index += 1;
+    }
+
+    // an empty block, or a write right after its end
+    let written = match blocks.last() {
+        Some(block) => block.range(global.clone()).await?.end,
+        None => range.start,
+    };
+    if written < range.end {
+        let index = blocks.len();
+        let result = fill(
+            global.clone(),
+            blocks,
+            &data[(written - range.start) as usize..],
+            written,
+        )
+        .await;
+        for block in blocks[index..].iter() {
+            created.extend(block.chunks(global.clone()).await?);
+        }
+        result?;
+    }
+    Ok(())
+}
@@ -1,4 +1,5 @@
 pub mod block;
 pub mod direct_block;
 pub mod indirect_block;
+pub mod range;
 pub mod stored_block;
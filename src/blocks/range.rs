@@ -0,0 +1,26 @@
+/*
+   How blocks store the byte range they hold: an explicit pair of u64 offsets, the same on every platform.
+   Blocks written before were serialized as a `Range<usize>`, a map with `start` and `end`. Those are still read,
+   and a block is written in the new form once it changes.
+*/
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::ops::Range;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stored {
+    Pair(u64, u64),
+    Map { start: u64, end: u64 }, // how Range<usize> serializes
+}
+
+pub fn serialize<S: Serializer>(range: &Range<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+    (range.start, range.end).serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Range<u64>, D::Error> {
+    Ok(match Stored::deserialize(deserializer)? {
+        Stored::Pair(start, end) => start..end,
+        Stored::Map { start, end } => start..end,
+    })
+}
@@ -9,11 +9,12 @@ use std::{ops::Range, sync::Arc};
 
 use crate::{
     blocks::block::{Block, BlockType},
+    error::Error,
     global::GlobalTrait,
     stored::Stored,
 };
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoredBlock {
     #[serde(rename = "s")]
     pub stored: Stored,
@@ -24,7 +25,7 @@ impl Block for StoredBlock {
     async fn range<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &self,
         global: Arc<U>,
-    ) -> Result<Range<usize>, String> {
+    ) -> Result<Range<u64>, Error> {
         self.stored
             .get::<BlockType, U>(global.clone())
             .await?
@@ -36,18 +37,28 @@ impl Block for StoredBlock {
         &mut self,
         global: Arc<U>,
         data: Vec<u8>,
-        range: Range<usize>,
-    ) -> Result<(), String> {
+        range: Range<u64>,
+    ) -> Result<Vec<Stored>, Error> {
         let mut block = self.stored.get::<BlockType, U>(global.clone()).await?;
-        block.put(global.clone(), data, range).await?;
-        self.stored.put(global, block).await
+        let replaced = block.put(global.clone(), data, range).await?;
+        self.replace(global, block, replaced).await
+    }
+
+    async fn truncate<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &mut self,
+        global: Arc<U>,
+        end: u64,
+    ) -> Result<Vec<Stored>, Error> {
+        let mut block = self.stored.get::<BlockType, U>(global.clone()).await?;
+        let replaced = block.truncate(global.clone(), end).await?;
+        self.replace(global, block, replaced).await
     }
 
     fn get<'a, U: GlobalTrait + std::marker::Send + std::marker::Sync + 'a>(
         &'a self,
         global: Arc<U>,
-        range: Range<usize>,
-    ) -> BoxStream<'a, Result<Vec<u8>, String>> {
+        range: Range<u64>,
+    ) -> BoxStream<'a, Result<Vec<u8>, Error>> {
         Box::pin(async_stream::stream! {
             let global = global.clone();
             let block = self.stored.get::<BlockType, U>(global.clone()).await?;
@@ -61,7 +72,7 @@ impl Block for StoredBlock {
     async fn delete<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &self,
         global: Arc<U>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         let mut errors = Vec::new();
         match self
             .stored
@@ -81,15 +92,25 @@ impl Block for StoredBlock {
         if errors.is_empty() {
             Ok(())
         } else {
-            Err(errors.join(", "))
+            Err(Error::many(errors))
         }
     }
 
+    async fn chunks<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &self,
+        global: Arc<U>,
+    ) -> Result<Vec<Stored>, Error> {
+        let block = self.stored.get::<BlockType, U>(global.clone()).await?;
+        let mut chunks = vec![self.stored.clone()];
+        chunks.extend(block.chunks(global).await?);
+        Ok(chunks)
+    }
+
     async fn create<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         global: Arc<U>,
         data: Vec<u8>,
-        start: usize,
-    ) -> Result<BlockType, String> {
+        start: u64,
+    ) -> Result<BlockType, Error> {
         let block = BlockType::create(global.clone(), data, start).await?;
         let stored = Stored::create(global.clone(), block).await?;
         Ok(BlockType::Stored(StoredBlock { stored }))
@@ -99,3 +120,18 @@ impl Block for StoredBlock {
         BlockType::Stored(self)
     }
 }
+
+impl StoredBlock {
+    // Stores the changed block in a new chunk, the old one joins the chunks the change replaced
+    async fn replace<U: GlobalTrait>(
+        &mut self,
+        global: Arc<U>,
+        block: BlockType,
+        mut replaced: Vec<Stored>,
+    ) -> Result<Vec<Stored>, Error> {
+        // the block's new chunks leak if this fails, the old block is still whole
+        let stored = Stored::create(global, block).await?;
+        replaced.push(std::mem::replace(&mut self.stored, stored));
+        Ok(replaced)
+    }
+}
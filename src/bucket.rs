@@ -7,6 +7,7 @@ use serde::Deserialize;
 
 use crate::{
     encryption::encryption::{Encryption, EncryptionType},
+    error::Error,
     global::Descriptor,
     sources::source::{Source, SourceType},
 };
@@ -33,28 +34,28 @@ impl Bucket {
         )
     }
 
-    // Takes a descriptor and returns a stream of data or an error (String)
-    pub async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, String> {
+    // Takes a descriptor and returns a stream of data or an error
+    pub async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
         let iv = descriptor.to_vec();
         let data = self.source.get(descriptor).await?;
         let decrypted = self.encryption.decrypt(data, iv)?;
         Ok(decrypted)
     }
 
-    // Takes a descriptor and data and uploads the data to the descriptor or returns an error (String)
-    pub async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), String> {
+    // Takes a descriptor and data and uploads the data to the descriptor or returns an error
+    pub async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error> {
         let iv = descriptor.to_vec();
         let encrypted = self.encryption.encrypt(data, iv)?;
         self.source.put(descriptor, encrypted).await
     }
 
-    // Takes a descriptor and deletes the data at the descriptor or returns an error (String)
-    pub async fn delete(&self, descriptor: &Descriptor) -> Result<(), String> {
+    // Takes a descriptor and deletes the data at the descriptor or returns an error
+    pub async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error> {
         self.source.delete(descriptor).await
     }
 
-    // Creates a new descriptor and returns it or returns an error (String)
-    pub async fn create(&self) -> Result<Descriptor, String> {
+    // Creates a new descriptor and returns it or returns an error
+    pub async fn create(&self) -> Result<Descriptor, Error> {
         self.source.create().await
     }
 }
@@ -6,6 +6,7 @@ use crypto::{
 use serde::Deserialize;
 
 use super::encryption::Encryption;
+use crate::error::Error;
 
 #[derive(Deserialize, Debug)]
 pub struct Aes {
@@ -28,7 +29,7 @@ pub enum AesType {
 }
 
 impl AesType {
-    fn to_enum(&self) -> KeySize {
+    fn key_size_enum(&self) -> KeySize {
         match self {
             AesType::Aes128 => KeySize::KeySize128,
             AesType::Aes192 => KeySize::KeySize192,
@@ -63,7 +64,7 @@ impl AesType {
 /* #endregion */
 
 // generate a key from a string by repeating it (if key was shorter, we also do some bit shifting in the repetions to make it more random)
-fn to_size(init_key: &Vec<u8>, size: usize) -> Vec<u8> {
+fn to_size(init_key: &[u8], size: usize) -> Vec<u8> {
     let mut key = init_key
         .iter()
         .cycle()
@@ -86,10 +87,10 @@ impl Encryption for Aes {
         (source_size / self.size.block_size()) * self.size.block_size()
     }
 
-    fn encrypt(&self, data: Vec<u8>, iv: Vec<u8>) -> Result<Vec<u8>, String> {
+    fn encrypt(&self, data: Vec<u8>, iv: Vec<u8>) -> Result<Vec<u8>, Error> {
         let mut encryptor = aes::cbc_encryptor(
-            self.size.to_enum(),
-            &to_size(&self.key.as_bytes().to_vec(), self.size.key_size()),
+            self.size.key_size_enum(),
+            &to_size(self.key.as_bytes(), self.size.key_size()),
             &to_size(&iv, self.size.iv_size()),
             blockmodes::PkcsPadding,
         );
@@ -102,7 +103,7 @@ impl Encryption for Aes {
         loop {
             let result = encryptor
                 .encrypt(&mut read_buffer, &mut write_buffer, true)
-                .map_err(|_| "Symmetric encryption failed")?;
+                .map_err(|_| Error::Internal("Symmetric encryption failed".to_string()))?;
             final_result.extend(
                 write_buffer
                     .take_read_buffer()
@@ -119,10 +120,10 @@ impl Encryption for Aes {
         Ok(final_result)
     }
 
-    fn decrypt(&self, data: Vec<u8>, iv: Vec<u8>) -> Result<Vec<u8>, String> {
+    fn decrypt(&self, data: Vec<u8>, iv: Vec<u8>) -> Result<Vec<u8>, Error> {
         let mut decryptor = aes::cbc_decryptor(
-            self.size.to_enum(),
-            &to_size(&self.key.as_bytes().to_vec(), self.size.key_size()),
+            self.size.key_size_enum(),
+            &to_size(self.key.as_bytes(), self.size.key_size()),
             &to_size(&iv, self.size.iv_size()),
             blockmodes::PkcsPadding,
         );
@@ -135,7 +136,7 @@ impl Encryption for Aes {
         loop {
             let result = decryptor
                 .decrypt(&mut read_buffer, &mut write_buffer, true)
-                .map_err(|_| "Symmetric decryption failed")?;
+                .map_err(|_| Error::Decryption("Symmetric decryption failed".to_string()))?;
             final_result.extend(
                 write_buffer
                     .take_read_buffer()
@@ -1,11 +1,12 @@
 use serde::Deserialize;
 
 use super::{aes::Aes, none::None};
+use crate::error::Error;
 
 pub trait Encryption {
     fn max_size(&self, source_size: usize) -> usize;
-    fn encrypt(&self, data: Vec<u8>, iv: Vec<u8>) -> Result<Vec<u8>, String>;
-    fn decrypt(&self, data: Vec<u8>, iv: Vec<u8>) -> Result<Vec<u8>, String>;
+    fn encrypt(&self, data: Vec<u8>, iv: Vec<u8>) -> Result<Vec<u8>, Error>;
+    fn decrypt(&self, data: Vec<u8>, iv: Vec<u8>) -> Result<Vec<u8>, Error>;
 }
 
 #[derive(Deserialize, Debug)]
@@ -47,11 +48,11 @@ impl Encryption for EncryptionType {
         match_method!(self, max_size, source_size)
     }
 
-    fn encrypt<'a>(&self, data: Vec<u8>, iv: Vec<u8>) -> Result<Vec<u8>, String> {
+    fn encrypt<'a>(&self, data: Vec<u8>, iv: Vec<u8>) -> Result<Vec<u8>, Error> {
         match_method!(self, encrypt, data, iv)
     }
 
-    fn decrypt<'a>(&self, data: Vec<u8>, iv: Vec<u8>) -> Result<Vec<u8>, String> {
+    fn decrypt<'a>(&self, data: Vec<u8>, iv: Vec<u8>) -> Result<Vec<u8>, Error> {
         match_method!(self, decrypt, data, iv)
     }
 }
@@ -1,3 +1,4 @@
 pub mod aes;
+#[allow(clippy::module_inception)]
 pub mod encryption;
 pub mod none;
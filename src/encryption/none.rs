@@ -1,6 +1,7 @@
 use serde::Deserialize;
 
 use super::encryption::Encryption;
+use crate::error::Error;
 
 #[derive(Deserialize, Debug)]
 pub struct None;
@@ -10,11 +11,11 @@ impl Encryption for None {
         source_size
     }
 
-    fn encrypt(&self, data: Vec<u8>, _iv: Vec<u8>) -> Result<Vec<u8>, String> {
+    fn encrypt(&self, data: Vec<u8>, _iv: Vec<u8>) -> Result<Vec<u8>, Error> {
         Ok(data)
     }
 
-    fn decrypt<'a>(&self, data: Vec<u8>, _iv: Vec<u8>) -> Result<Vec<u8>, String> {
+    fn decrypt<'a>(&self, data: Vec<u8>, _iv: Vec<u8>) -> Result<Vec<u8>, Error> {
         Ok(data)
     }
 }
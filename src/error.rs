@@ -0,0 +1,162 @@
+/*
+   The error every layer returns. The variant says what kind of failure it is, so callers can tell a missing
+   chunk from a source that can't be reached or data that doesn't decrypt: the HTTP service answers with a
+   matching status and only failures that may go away on their own are worth retrying.
+   `Context` adds where an error happened on top of it, `Caused` keeps the io, http or parsing error that
+   led to it, and `source()` walks back down the chain.
+*/
+
+use std::{fmt, sync::Arc};
+
+pub type Cause = Arc<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    NotFound(String),      // a chunk, an entry, a version, a snapshot...
+    AlreadyExists(String), // a name that is taken
+    Conflict(String), // doesn't fit the current state, like deleting a directory that isn't empty
+    Invalid(String),  // bad arguments, paths, ranges or config
+    Forbidden(String), // read-only mode, or outside of what is shared
+    Unavailable(String), // a source that can't be reached, timed out or failed on its side
+    Decryption(String),
+    Corrupted(String), // stored data that can't be read back
+    Internal(String),
+    Context(String, Box<Error>),
+    Caused(Box<Error>, Cause), // the kind, and the error it was made from
+    Many(Vec<Error>),          // several operations failed, like deleting the chunks of a file
+}
+
+impl Error {
+    pub fn context(self, message: impl Into<String>) -> Error {
+        Error::Context(message.into(), Box::new(self))
+    }
+
+    fn caused(self, cause: impl std::error::Error + Send + Sync + 'static) -> Error {
+        Error::Caused(Box::new(self), Arc::new(cause))
+    }
+
+    // The error below all the context
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Context(_, source) | Error::Caused(source, _) => source.root(),
+            error => error,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        match self.root() {
+            Error::NotFound(_) => true,
+            Error::Many(errors) => errors.iter().all(Error::is_not_found),
+            _ => false,
+        }
+    }
+
+    // Trying again later may work
+    pub fn is_transient(&self) -> bool {
+        match self.root() {
+            Error::Unavailable(_) => true,
+            Error::Many(errors) => errors.iter().any(Error::is_transient),
+            _ => false,
+        }
+    }
+
+    // One error stays as it is
+    pub fn many(mut errors: Vec<Error>) -> Error {
+        match errors.len() {
+            1 => errors.remove(0),
+            _ => Error::Many(errors),
+        }
+    }
+
+    pub fn io(message: impl fmt::Display, error: std::io::Error) -> Error {
+        use std::io::ErrorKind;
+        let message = message.to_string();
+        let kind = match error.kind() {
+            ErrorKind::NotFound => Error::NotFound(message),
+            ErrorKind::AlreadyExists => Error::AlreadyExists(message),
+            ErrorKind::InvalidInput => Error::Invalid(message),
+            ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe
+            | ErrorKind::WouldBlock => Error::Unavailable(message),
+            _ => Error::Internal(message),
+        };
+        kind.caused(error)
+    }
+
+    // Requests that never got an answer
+    pub fn http(message: impl fmt::Display, error: reqwest::Error) -> Error {
+        let text = message.to_string();
+        let kind = match error.status() {
+            Some(status) => Error::status(status, text),
+            None if error.is_decode() => Error::Corrupted(text),
+            None if error.is_builder() => Error::Invalid(text),
+            None => Error::Unavailable(text),
+        };
+        kind.caused(error)
+    }
+
+    // Answers that weren't a success
+    pub fn status(status: reqwest::StatusCode, message: impl Into<String>) -> Error {
+        let message = message.into();
+        match status.as_u16() {
+            404 | 410 => Error::NotFound(message),
+            409 | 412 => Error::Conflict(message),
+            429 | 500..=599 => Error::Unavailable(message),
+            _ => Error::Internal(message),
+        }
+    }
+
+    pub fn deserialize(
+        message: impl fmt::Display,
+        error: impl std::error::Error + Send + Sync + 'static,
+    ) -> Error {
+        Error::Corrupted(message.to_string()).caused(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound(message)
+            | Error::AlreadyExists(message)
+            | Error::Conflict(message)
+            | Error::Invalid(message)
+            | Error::Forbidden(message)
+            | Error::Unavailable(message)
+            | Error::Decryption(message)
+            | Error::Corrupted(message)
+            | Error::Internal(message) => write!(f, "{}", message),
+            Error::Context(message, source) => write!(f, "{}: {}", message, source),
+            Error::Caused(kind, cause) => write!(f, "{}: {}", kind, cause),
+            Error::Many(errors) => {
+                let messages = errors.iter().map(Error::to_string).collect::<Vec<_>>();
+                write!(f, "{}", messages.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Context(_, source) => Some(source.as_ref()),
+            Error::Caused(_, cause) => Some(cause.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+pub trait Context<T> {
+    fn context(self, message: impl Into<String>) -> Result<T, Error>;
+}
+
+impl<T> Context<T> for Result<T, Error> {
+    fn context(self, message: impl Into<String>) -> Result<T, Error> {
+        self.map_err(|error| error.context(message))
+    }
+}
@@ -1,16 +1,25 @@
 use delegate::delegate;
 use rand::seq::IteratorRandom;
-use rmp_serde::{Deserializer, Serializer};
-use rusoto_core::ByteStream;
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc};
+use rmp_serde::Deserializer;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 use tokio::runtime::Runtime;
 
 use crate::{
     bucket::Bucket,
-    inodes::directory::Directory,
-    s3::s3::{download_file, list_files_in_bucket, upload_file, S3Type},
+    error::Error,
+    inodes::{directory::Directory, file::VersionRetention, shards::ShardConfig},
+    locks::{DirectoryGuard, DirectoryLocks},
+    root_file,
+    s3::s3::{download_file, upload_file_if, PutOutcome, S3Type},
     services::service::{Service, ServiceType},
+    snapshots::{self, PinCache, Snapshot, Snapshots},
+    stored::Stored,
+    superblock::SuperblockConfig,
+    trash::{self, Trash, TrashConfig, TrashEntry},
 };
 
 pub type Descriptor = Vec<u8>;
@@ -24,11 +33,29 @@ pub struct Global {
 
     #[serde(default = "default_root_path")]
     root_path: String,
+    #[serde(default = "root_file::default_generations")]
+    root_generations: usize, // older roots kept next to root_path to recover from
+
+    #[serde(default)]
+    versions: VersionRetention, // old versions kept when a file is overwritten
+    #[serde(default)]
+    trash: TrashConfig,
+    #[serde(default)]
+    directories: ShardConfig, // when large directories spill into shards
 
     #[serde(default)]
     services: Vec<ServiceType>,
 
     s3: Option<S3Type>,
+    #[serde(skip)]
+    s3_base: Mutex<Option<(String, Directory)>>, // etag and content of the s3 root we last read or wrote
+
+    superblock: Option<SuperblockConfig>,
+
+    #[serde(skip)]
+    locks: DirectoryLocks,
+    #[serde(skip)]
+    pins: PinCache,
 }
 
 pub trait GlobalTrait {
@@ -37,23 +64,48 @@ pub trait GlobalTrait {
     fn list_buckets(&self) -> Vec<&String>;
     fn random_bucket(&self) -> Option<&String>;
     fn get_direct_block_count(&self) -> usize;
+    fn is_pinned(&self, stored: &Stored) -> bool;
+    fn get_version_retention(&self) -> &VersionRetention;
+    fn get_shard_config(&self) -> &ShardConfig;
 }
 
 #[derive(Debug)]
 enum GetS3RootError {
     CorruptedRoot(String),
-    DownloadFailed(String),
+    DownloadFailed(Error),
     MissingRoot,
-    CantListBucketContent(String),
     NoS3Config,
 }
 
+impl GetS3RootError {
+    fn into_error(self) -> Error {
+        match self {
+            GetS3RootError::CorruptedRoot(message) => Error::Corrupted(message),
+            GetS3RootError::DownloadFailed(err) => err.context("Error downloading s3 root"),
+            GetS3RootError::MissingRoot => Error::NotFound("No s3 root".to_string()),
+            GetS3RootError::NoS3Config => Error::Invalid("No s3 configured".to_string()),
+        }
+    }
+}
+
 const fn default_direct_block_count() -> usize {
     10
 }
 fn default_root_path() -> String {
     "./root.dat".to_string()
 }
+fn lock_folder(root_path: &str) -> std::path::PathBuf {
+    std::path::PathBuf::from(format!("{}.locks", root_path))
+}
+const S3_ROOT_ATTEMPTS: usize = 5;
+// entries deleted before this are past the retention
+fn trash_cutoff(config: &TrashConfig) -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        .saturating_sub(config.retention)
+}
 fn s3_root_file() -> String {
     "chunkdrive-root.dat".to_string()
 }
@@ -62,12 +114,31 @@ pub fn run_services(global: Arc<AsyncGlobal>) {
     for service in global.0.services.iter() {
         service.run(global.clone());
     }
+    if global.0.trash.retention > 0 {
+        purge_trash_periodically(global);
+    }
 }
 
+fn purge_trash_periodically(global: Arc<AsyncGlobal>) {
+    let interval = std::time::Duration::from_secs(global.0.trash.purge_interval.max(1));
+    std::thread::spawn(move || {
+        let rt = Runtime::new().unwrap();
+        loop {
+            match rt.block_on(global.purge_trash(false)) {
+                Ok(0) => (),
+                Ok(purged) => println!("purged {} entries from the trash", purged),
+                Err(err) => println!("failed to purge trash: {}", err),
+            }
+            std::thread::sleep(interval);
+        }
+    });
+}
+
+// the inner Global is shared so the superblock can hand it to Stored
 #[derive(Debug)]
-pub struct AsyncGlobal(Global);
+pub struct AsyncGlobal(Arc<Global>);
 #[derive(Debug)]
-pub struct BlockingGlobal(Global);
+pub struct BlockingGlobal(Arc<Global>);
 
 impl GlobalTrait for Global {
     fn get_bucket(&self, name: &str) -> Option<&Bucket> {
@@ -97,70 +168,122 @@ impl GlobalTrait for Global {
     fn get_direct_block_count(&self) -> usize {
         self.direct_block_count
     }
-}
 
-async fn save_s3_root(_s3: &Option<S3Type>, root: &Directory) {
-    if let Some(s3) = _s3 {
-        let mut buf = Vec::new();
-        let mut serializer = Serializer::new(&mut buf).with_struct_map(); // https://github.com/3Hren/msgpack-rust/issues/318
-        root.serialize(&mut serializer).unwrap();
-        match upload_file(s3, s3_root_file().as_str(), ByteStream::from(buf)).await {
-            Ok(_) => println!("root uploaded to s3 !"),
-            Err(err) => println!("failed to upload root to s3: {}", err),
-        }
-    } else {
-        eprintln!("No s3, can't save s3 root")
+    fn is_pinned(&self, stored: &Stored) -> bool {
+        self.pins.is_pinned(&self.root_path, stored)
+    }
+
+    fn get_version_retention(&self) -> &VersionRetention {
+        &self.versions
+    }
+
+    fn get_shard_config(&self) -> &ShardConfig {
+        &self.directories
     }
 }
 
-async fn get_s3_root(s3: &Option<S3Type>) -> Result<Directory, GetS3RootError> {
-    match s3 {
-        Some(s3) => {
-            let files = list_files_in_bucket(&s3).await;
-            match files {
-                Ok(files) => {
-                    let root_file = files.iter().find(|f| f.to_string() == s3_root_file());
-                    match root_file {
-                        Some(f) => match download_file(&s3, f).await {
-                            Ok(stream) => {
-                                let res = tokio::task::spawn_blocking(|| {
-                                    let mut de = Deserializer::new(stream.into_blocking_read());
-                                    let res: Result<Directory, rmp_serde::decode::Error> =
-                                        Deserialize::deserialize(&mut de);
-                                    res.map_err(|err| {
-                                        format!("deserialize error: {}", err.to_string())
-                                    })
-                                })
-                                .await;
-                                match res {
-                                    Ok(v) => v.map_err(|err| {
-                                        GetS3RootError::CorruptedRoot(err.to_string())
-                                    }),
-                                    Err(err) => Err(GetS3RootError::CorruptedRoot(err.to_string())),
-                                }
-                            }
-                            Err(err) => Err(GetS3RootError::DownloadFailed(err.to_string())),
-                        },
-                        None => Err(GetS3RootError::MissingRoot),
+// Uploads the root unless somebody else changed the s3 root since we last read or wrote it.
+// Then their root is downloaded, our changes are replayed on top of it and the upload is retried.
+// Returns the root that was uploaded, which includes their changes after a merge.
+async fn save_s3_root(global: &Arc<Global>, root: &Directory) -> Result<Directory, Error> {
+    let s3 = global
+        .s3
+        .as_ref()
+        .ok_or_else(|| GetS3RootError::NoS3Config.into_error())?;
+    let mut root = root.clone();
+    for _ in 0..S3_ROOT_ATTEMPTS {
+        let base = global.s3_base.lock().unwrap().clone();
+        let data = Stored::serialize(&root).map_err(|err| err.context("Error serializing root"))?;
+        let expected = base.as_ref().map(|(etag, _)| etag.as_str());
+        match upload_file_if(s3, &s3_root_file(), data, expected)
+            .await
+            .map_err(|err| err.context("Error uploading root to s3"))?
+        {
+            PutOutcome::Written(etag) => {
+                *global.s3_base.lock().unwrap() = Some((etag, root.clone()));
+                println!("root uploaded to s3 !");
+                return Ok(root);
+            }
+            PutOutcome::Conflict => {
+                println!("s3 root changed since it was read, merging...");
+                let base = base.map(|(_, base)| base).unwrap_or_else(Directory::new);
+                match get_s3_root(global).await {
+                    Ok(theirs) => {
+                        root = root_file::merge(global.clone(), &base, &root, &theirs)
+                            .await
+                            .map_err(|err| err.context("Error merging with the s3 root"))?;
+                    }
+                    Err(GetS3RootError::MissingRoot) => (), // deleted in the meantime, ours is all there is
+                    Err(err) => {
+                        return Err(err
+                            .into_error()
+                            .context("Error getting the s3 root to merge with"))
                     }
                 }
-                Err(err) => Err(GetS3RootError::CantListBucketContent(err.to_string())),
             }
         }
-        None => Err(GetS3RootError::NoS3Config),
     }
+    Err(Error::Conflict(format!(
+        "Gave up uploading root to s3 after {} conflicts",
+        S3_ROOT_ATTEMPTS
+    )))
+}
+
+// Also remembers the root as the base of the next save
+async fn get_s3_root(global: &Global) -> Result<Directory, GetS3RootError> {
+    let s3 = global.s3.as_ref().ok_or(GetS3RootError::NoS3Config)?;
+    let downloaded = download_file(s3, &s3_root_file())
+        .await
+        .map_err(GetS3RootError::DownloadFailed)?;
+    let (data, etag) = match downloaded {
+        Some(downloaded) => downloaded,
+        None => {
+            *global.s3_base.lock().unwrap() = None;
+            return Err(GetS3RootError::MissingRoot);
+        }
+    };
+    let root = Directory::deserialize(&mut Deserializer::new(&data[..]))
+        .map_err(|err| GetS3RootError::CorruptedRoot(format!("deserialize error: {}", err)))?;
+    *global.s3_base.lock().unwrap() = Some((etag, root.clone()));
+    Ok(root)
 }
 
 impl AsyncGlobal {
     pub fn new(global: Global) -> Self {
-        AsyncGlobal(global)
+        AsyncGlobal(Arc::new(global))
+    }
+
+    // Hold the guard from reading a directory (the root when None) until it is written back
+    pub async fn lock_directory(
+        &self,
+        directory: Option<&Stored>,
+    ) -> Result<DirectoryGuard, Error> {
+        self.0
+            .locks
+            .lock(lock_folder(&self.0.root_path), directory)
+            .await
     }
-    pub async fn get_root(&self) -> Directory {
+
+    pub async fn get_root(&self) -> Result<Directory, Error> {
+        if let Some(superblock) = &self.0.superblock {
+            match superblock.load(self.0.clone()).await {
+                Ok(Some((superblock, root))) => {
+                    println!(
+                        "async got root version {} from superblock",
+                        superblock.version
+                    );
+                    return Ok(root);
+                }
+                Ok(None) => println!("async no superblock yet, it is created on the next save"),
+                // any other copy may be older, saving it would replace the tree the superblock points at
+                Err(err) => return Err(err.context("Error loading root from superblock")),
+            }
+        }
         let mut should_save_to_s3 = false;
-        match get_s3_root(&self.0.s3).await {
+        match get_s3_root(&self.0).await {
             Ok(root) => {
                 println!("async got root from s3 !");
-                return root;
+                return Ok(root);
             }
             Err(err) => match err {
                 GetS3RootError::MissingRoot => {
@@ -172,50 +295,134 @@ impl AsyncGlobal {
                 }
             },
         }
-        match std::fs::File::open(&self.0.root_path) {
-            Ok(file) => {
-                let mut de = Deserializer::new(&file);
-                match Deserialize::deserialize(&mut de) {
-                    Ok(root) => {
-                        if should_save_to_s3 {
-                            println!("async no root in s3, saving current...");
-                            save_s3_root(&self.0.s3, &root).await;
-                        }
-                        root
-                    }
-                    Err(_) => {
-                        println!("async failed to deserialize local root");
-                        std::fs::remove_file(&self.0.root_path).unwrap();
-                        Directory::new()
+        match root_file::load(&self.0.root_path, self.0.root_generations) {
+            Some((_, root)) => {
+                if should_save_to_s3 {
+                    println!("async no root in s3, saving current...");
+                    if let Err(err) = save_s3_root(&self.0, &root).await {
+                        println!("async failed to save root to s3: {}", err);
                     }
                 }
+                Ok(root)
+            }
+            None => {
+                println!("async no readable local root");
+                Ok(Directory::new())
             }
-            Err(_) => {
-                println!("async failed to open local root");
-                Directory::new()
+        }
+    }
+
+    // s3 goes first, the other copies get its root in case it was merged with somebody else's changes.
+    // Only delete what the root no longer uses once this succeeded, a copy that failed may still use it.
+    pub async fn save_root(&self, root: &Directory) -> Result<(), Error> {
+        let root = &match self.0.s3 {
+            Some(_) => save_s3_root(&self.0, root).await?,
+            None => root.clone(),
+        };
+        root_file::save(&self.0.root_path, self.0.root_generations, root)
+            .map_err(|err| err.context("Error saving local root"))?;
+        if let Some(superblock) = &self.0.superblock {
+            superblock
+                .save(self.0.clone(), root)
+                .await
+                .map_err(|err| err.context("Error saving root to superblock"))?;
+        }
+        Ok(())
+    }
+
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>, Error> {
+        Ok(Snapshots::load(&self.0.root_path)?.list().to_vec())
+    }
+
+    pub async fn snapshot_root(&self, name: &str) -> Result<Directory, Error> {
+        let snapshot = Snapshots::load(&self.0.root_path)?.get(name)?.clone();
+        snapshots::root(self.0.clone(), &snapshot).await
+    }
+
+    pub fn trash(&self) -> Result<Vec<TrashEntry>, Error> {
+        Ok(Trash::load(&self.0.root_path)?.list().to_vec())
+    }
+
+    // Call once the tree was saved without `stored`, still holding the root's lock.
+    // `path` leads to where it was from the root, or from `top` when it was deleted through a shared directory.
+    pub async fn discard(
+        &self,
+        name: &str,
+        path: Vec<String>,
+        top: Option<Stored>,
+        stored: Stored,
+    ) -> Result<(), Error> {
+        match self.0.trash.retention {
+            0 => trash::destroy(self.0.clone(), vec![stored]).await,
+            _ => trash::add(&self.0.root_path, name, path, top, stored).map(|_| ()),
+        }
+    }
+
+    pub async fn restore_from_trash(&self, id: u64) -> Result<TrashEntry, Error> {
+        let _lock = self.lock_directory(None).await?;
+        let root = self.get_root().await?;
+        let (entry, commit) = trash::restore(self.0.clone(), &self.0.root_path, id, root).await?;
+        match &entry.top {
+            Some(stored) => {
+                stored
+                    .put(self.0.clone(), commit.top.clone().to_enum())
+                    .await?
             }
+            None => self.save_root(&commit.top).await?,
         }
+        commit.cleanup(self.0.clone()).await;
+        Ok(entry)
     }
 
-    pub async fn save_root(&self, root: &Directory) {
-        let mut file = std::fs::File::create(&self.0.root_path).unwrap();
-        let mut serializer = Serializer::new(&mut file).with_struct_map(); // https://github.com/3Hren/msgpack-rust/issues/318
-        root.serialize(&mut serializer).unwrap();
-        save_s3_root(&self.0.s3, root).await;
+    // Destroys the entries past the retention, or all of them. Returns how many there were.
+    pub async fn purge_trash(&self, all: bool) -> Result<usize, Error> {
+        let before = match all {
+            true => u64::MAX,
+            false => trash_cutoff(&self.0.trash),
+        };
+        let expired = {
+            let _lock = self.lock_directory(None).await?;
+            trash::take_expired(&self.0.root_path, before)?
+        };
+        let count = expired.len();
+        let stored = expired.into_iter().map(|entry| entry.stored).collect();
+        trash::destroy(self.0.clone(), stored).await?;
+        Ok(count)
     }
 }
 
 impl BlockingGlobal {
     pub fn new(global: Global) -> Self {
-        BlockingGlobal(global)
+        BlockingGlobal(Arc::new(global))
     }
-    pub fn get_root(&self) -> Directory {
-        let mut should_save_to_s3 = false;
+
+    // Hold the guard from reading a directory (the root when None) until it is written back
+    pub fn lock_directory(&self, directory: Option<&Stored>) -> Result<DirectoryGuard, Error> {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(self.0.locks.lock(lock_folder(&self.0.root_path), directory))
+    }
+
+    pub fn get_root(&self) -> Result<Directory, Error> {
         let rt = Runtime::new().unwrap();
-        match rt.block_on(async { get_s3_root(&self.0.s3).await }) {
+        if let Some(superblock) = &self.0.superblock {
+            match rt.block_on(superblock.load(self.0.clone())) {
+                Ok(Some((superblock, root))) => {
+                    println!(
+                        "blocking got root version {} from superblock",
+                        superblock.version
+                    );
+                    return Ok(root);
+                }
+                Ok(None) => println!("blocking no superblock yet, it is created on the next save"),
+                // any other copy may be older, saving it would replace the tree the superblock points at
+                Err(err) => return Err(err.context("Error loading root from superblock")),
+            }
+        }
+        let mut should_save_to_s3 = false;
+        match rt.block_on(async { get_s3_root(&self.0).await }) {
             Ok(root) => {
                 println!("blocking got root from s3 !");
-                return root;
+                return Ok(root);
             }
             Err(err) => match err {
                 GetS3RootError::MissingRoot => {
@@ -227,37 +434,138 @@ impl BlockingGlobal {
                 }
             },
         }
-        match std::fs::File::open(&self.0.root_path) {
-            Ok(file) => {
-                let mut de = Deserializer::new(&file);
-                match Deserialize::deserialize(&mut de) {
-                    Ok(root) => {
-                        if should_save_to_s3 {
-                            println!("blocking no root in s3, saving current...");
-                            let rt = Runtime::new().unwrap();
-                            rt.block_on(async {
-                                save_s3_root(&self.0.s3, &root).await;
-                            })
-                        }
-                        root
-                    }
-                    Err(_) => {
-                        std::fs::remove_file(&self.0.root_path).unwrap();
-                        Directory::new()
+        match root_file::load(&self.0.root_path, self.0.root_generations) {
+            Some((_, root)) => {
+                if should_save_to_s3 {
+                    println!("blocking no root in s3, saving current...");
+                    if let Err(err) = rt.block_on(save_s3_root(&self.0, &root)) {
+                        println!("blocking failed to save root to s3: {}", err);
                     }
                 }
+                Ok(root)
+            }
+            None => Ok(Directory::new()),
+        }
+    }
+    // See AsyncGlobal::save_root
+    pub fn save_root(&self, root: &Directory) -> Result<(), Error> {
+        let rt = Runtime::new().unwrap();
+        let root = &match self.0.s3 {
+            Some(_) => rt.block_on(save_s3_root(&self.0, root))?,
+            None => root.clone(),
+        };
+        root_file::save(&self.0.root_path, self.0.root_generations, root)
+            .map_err(|err| err.context("Error saving local root"))?;
+        if let Some(superblock) = &self.0.superblock {
+            rt.block_on(superblock.save(self.0.clone(), root))
+                .map_err(|err| err.context("Error saving root to superblock"))?;
+        }
+        Ok(())
+    }
+
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>, Error> {
+        Ok(Snapshots::load(&self.0.root_path)?.list().to_vec())
+    }
+
+    pub fn snapshot_root(&self, name: &str) -> Result<Directory, Error> {
+        let snapshot = Snapshots::load(&self.0.root_path)?.get(name)?.clone();
+        let rt = Runtime::new().unwrap();
+        rt.block_on(snapshots::root(self.0.clone(), &snapshot))
+    }
+
+    // Snapshots are taken, restored and deleted under the root's lock, like any other change to the tree
+    pub fn create_snapshot(&self, name: &str) -> Result<Snapshot, Error> {
+        let _lock = self.lock_directory(None)?;
+        let root = self.get_root()?;
+        let rt = Runtime::new().unwrap();
+        rt.block_on(snapshots::create(
+            self.0.clone(),
+            &self.0.root_path,
+            name,
+            &root,
+        ))
+    }
+
+    pub fn delete_snapshot(&self, name: &str) -> Result<(), Error> {
+        let _lock = self.lock_directory(None)?;
+        let root = self.get_root()?;
+        let trash = Trash::load(&self.0.root_path)?.as_directory();
+        let rt = Runtime::new().unwrap();
+        rt.block_on(snapshots::delete(
+            self.0.clone(),
+            &self.0.root_path,
+            name,
+            &[&root, &trash],
+        ))
+    }
+
+    // The current tree is replaced by the snapshot's, whatever only the current tree used is freed
+    pub fn restore_snapshot(&self, name: &str) -> Result<(), Error> {
+        let _lock = self.lock_directory(None)?;
+        let old = self.get_root()?;
+        let root = self.snapshot_root(name)?;
+        self.save_root(&root)?;
+        let rt = Runtime::new().unwrap();
+        rt.block_on(trash::forget_linked(
+            self.0.clone(),
+            &self.0.root_path,
+            &root,
+        ))?;
+        rt.block_on(snapshots::free_unreachable(self.0.clone(), &old, &root))
+    }
+
+    pub fn trash(&self) -> Result<Vec<TrashEntry>, Error> {
+        Ok(Trash::load(&self.0.root_path)?.list().to_vec())
+    }
+
+    // Call once the tree was saved without `stored`, still holding the root's lock, see AsyncGlobal::discard
+    pub fn discard(
+        &self,
+        name: &str,
+        path: Vec<String>,
+        top: Option<Stored>,
+        stored: Stored,
+    ) -> Result<(), Error> {
+        match self.0.trash.retention {
+            0 => {
+                let rt = Runtime::new().unwrap();
+                rt.block_on(trash::destroy(self.0.clone(), vec![stored]))
             }
-            Err(_) => Directory::new(),
+            _ => trash::add(&self.0.root_path, name, path, top, stored).map(|_| ()),
         }
     }
-    pub fn save_root(&self, root: &Directory) {
-        let mut file = std::fs::File::create(&self.0.root_path).unwrap();
-        let mut serializer = Serializer::new(&mut file).with_struct_map(); // https://github.com/3Hren/msgpack-rust/issues/318
-        root.serialize(&mut serializer).unwrap();
+
+    pub fn restore_from_trash(&self, id: u64) -> Result<TrashEntry, Error> {
+        let _lock = self.lock_directory(None)?;
+        let root = self.get_root()?;
+        let rt = Runtime::new().unwrap();
+        let (entry, commit) =
+            rt.block_on(trash::restore(self.0.clone(), &self.0.root_path, id, root))?;
+        match &entry.top {
+            Some(stored) => {
+                rt.block_on(stored.put(self.0.clone(), commit.top.clone().to_enum()))?
+            }
+            None => self.save_root(&commit.top)?,
+        }
+        rt.block_on(commit.cleanup(self.0.clone()));
+        Ok(entry)
+    }
+
+    // Destroys the entries past the retention, or all of them. Returns how many there were.
+    pub fn purge_trash(&self, all: bool) -> Result<usize, Error> {
+        let before = match all {
+            true => u64::MAX,
+            false => trash_cutoff(&self.0.trash),
+        };
+        let expired = {
+            let _lock = self.lock_directory(None)?;
+            trash::take_expired(&self.0.root_path, before)?
+        };
+        let count = expired.len();
+        let stored = expired.into_iter().map(|entry| entry.stored).collect();
         let rt = Runtime::new().unwrap();
-        rt.block_on(async {
-            save_s3_root(&self.0.s3, root).await;
-        })
+        rt.block_on(trash::destroy(self.0.clone(), stored))?;
+        Ok(count)
     }
 }
 
@@ -269,6 +577,9 @@ impl GlobalTrait for BlockingGlobal {
             fn list_buckets(&self) -> Vec<&String>;
             fn random_bucket(&self) -> Option<&String>;
             fn get_direct_block_count(&self) -> usize;
+            fn is_pinned(&self, stored: &Stored) -> bool;
+            fn get_version_retention(&self) -> &VersionRetention;
+            fn get_shard_config(&self) -> &ShardConfig;
         }
     }
 }
@@ -281,6 +592,9 @@ impl GlobalTrait for AsyncGlobal {
             fn list_buckets(&self) -> Vec<&String>;
             fn random_bucket(&self) -> Option<&String>;
             fn get_direct_block_count(&self) -> usize;
+            fn is_pinned(&self, stored: &Stored) -> bool;
+            fn get_version_retention(&self) -> &VersionRetention;
+            fn get_shard_config(&self) -> &ShardConfig;
         }
     }
 }
@@ -3,18 +3,26 @@ use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, sync::Arc};
 
 use super::{
-    inode::{Inode, InodeType},
+    file::{File, Version},
+    inode::{drop_link, Inode, InodeType},
     metadata::{Metadata, Size},
+    path::DirectoryPath,
+    shards::{hash, Shards},
 };
-use crate::{global::GlobalTrait, stored::Stored};
+use crate::{blocks::block::Block, error::Error, global::GlobalTrait, stored::Stored};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Directory {
     #[serde(rename = "c")]
     #[serde(default, skip_serializing_if = "is_empty")]
     children: HashMap<String, Stored>,
+    #[serde(rename = "h")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shards: Option<Shards>, // the entries of a large directory, see shards.rs
     #[serde(rename = "m")]
     pub metadata: Metadata,
+    #[serde(skip)]
+    replaced: Vec<Stored>, // chunks that changes replaced, see take_replaced
 }
 
 fn is_empty<T>(map: &HashMap<String, T>) -> bool {
@@ -23,32 +31,46 @@ fn is_empty<T>(map: &HashMap<String, T>) -> bool {
 
 #[async_trait]
 impl Inode for Directory {
-    async fn metadata(&self) -> &Metadata {
+    fn metadata(&self) -> &Metadata {
         &self.metadata
     }
 
-    async fn delete<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
-        &mut self,
-        global: Arc<U>,
-    ) -> Result<(), String> {
+    fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    async fn delete<U: GlobalTrait + Send + Sync>(&mut self, global: Arc<U>) -> Result<(), Error> {
+        let (entries, nodes) = self.walk(global.clone()).await?;
         let mut errors = Vec::new();
-        for (_, stored) in self.children.drain() {
-            match &mut stored.get::<InodeType, U>(global.clone()).await {
-                Ok(inode) => match inode.delete(global.clone()).await {
-                    Ok(_) => (),
-                    Err(e) => errors.push(e),
-                },
-                Err(e) => errors.push(e.clone()),
-            };
-            match stored.delete(global.clone()).await {
-                Ok(_) => (),
-                Err(e) => errors.push(e),
+        for (_, stored) in entries {
+            if let Err(e) = drop_link(global.clone(), &stored).await {
+                errors.push(e);
             }
         }
+        for node in nodes {
+            if let Err(e) = node.delete(global.clone()).await {
+                errors.push(e);
+            }
+        }
+        self.children.clear();
+        self.shards = None;
         match errors.len() {
             0 => Ok(()),
-            _ => Err(errors.join(", ")),
+            _ => Err(Error::many(errors)),
+        }
+    }
+
+    async fn chunks<U: GlobalTrait + Send + Sync>(
+        &self,
+        global: Arc<U>,
+    ) -> Result<Vec<Stored>, Error> {
+        let (entries, mut chunks) = self.walk(global.clone()).await?;
+        for (_, stored) in entries {
+            let inode = stored.get::<InodeType, U>(global.clone()).await?;
+            chunks.push(stored);
+            chunks.extend(inode.chunks(global.clone()).await?);
         }
+        Ok(chunks)
     }
 }
 
@@ -56,86 +78,430 @@ impl Directory {
     pub fn new() -> Self {
         Self {
             children: HashMap::new(),
+            shards: None,
             metadata: Metadata::new(),
+            replaced: Vec::new(),
         }
     }
 
+    // A directory that is only walked, never stored, so its entries need no checks
+    pub fn with_children(children: HashMap<String, Stored>) -> Self {
+        let mut directory = Self::new();
+        directory.children = children;
+        directory
+    }
+
+    #[allow(clippy::wrong_self_convention)] // mirrors Block::to_enum
     pub fn to_enum(self) -> InodeType {
         InodeType::Directory(self)
     }
 
-    pub async fn add<U: GlobalTrait>(
+    pub async fn add<U: GlobalTrait + Send + Sync>(
         &mut self,
         global: Arc<U>,
         name: &String,
         inode: InodeType,
-    ) -> Result<&Stored, String> {
-        if self.children.contains_key(name) {
-            return Err(format!("File {} already exists", name));
+    ) -> Result<Stored, Error> {
+        if self.lookup(global.clone(), name).await?.is_some() {
+            return Err(Error::AlreadyExists(format!(
+                "File {} already exists",
+                name
+            )));
         }
 
-        let stored = Stored::create(global, inode).await?;
+        // what a new directory's shards replaced may still be used where it came from, so it is leaked
+        let inode = match inode {
+            InodeType::Directory(mut directory) => {
+                directory.flush(global.clone()).await?;
+                directory.to_enum()
+            }
+            inode => inode,
+        };
+        let stored = Stored::create(global.clone(), inode).await?;
 
-        self.children.insert(name.clone(), stored);
-        self.metadata.modified(Size::Entries(self.children.len()));
+        self.insert(global, name, stored.clone()).await?;
+        Ok(stored)
+    }
 
-        self.children
-            .get(name)
-            .ok_or("Directory.add: failed to get just inserted child".to_string())
+    #[allow(dead_code)] // deletes go through the trash, only the tests destroy entries directly
+    pub async fn remove<U: GlobalTrait + Send + Sync>(
+        &mut self,
+        global: Arc<U>,
+        name: &String,
+    ) -> Result<(), Error> {
+        let stored = self.unlink(global.clone(), name).await?;
+        drop_link(global, &stored).await
     }
 
-    pub async fn remove<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+    // Adds another name for the file or symlink at `stored`, every name sees changes to it
+    pub async fn link<U: GlobalTrait + Send + Sync>(
         &mut self,
         global: Arc<U>,
         name: &String,
-    ) -> Result<(), String> {
-        if !self.children.contains_key(name) {
-            return Err(format!("File {} does not exist", name));
+        stored: Stored,
+    ) -> Result<(), Error> {
+        if self.lookup(global.clone(), name).await?.is_some() {
+            return Err(Error::AlreadyExists(format!(
+                "File {} already exists",
+                name
+            )));
         }
+        let mut inode = stored.get::<InodeType, U>(global.clone()).await?;
+        if let InodeType::Directory(_) = inode {
+            return Err(Error::Invalid(
+                "Directories can't be hard linked".to_string(),
+            ));
+        }
+        // the count is written in place, which would change the snapshots using it
+        if global.is_pinned(&stored) {
+            return Err(Error::Conflict(format!(
+                "{} is part of a snapshot, it can't be hard linked",
+                name
+            )));
+        }
+        // counted before it is linked, a failure in between only leaks the inode
+        inode.metadata_mut().links += 1;
+        stored.put(global.clone(), inode).await?;
+        self.insert(global, name, stored).await
+    }
 
-        let stored = self.children.remove(name).unwrap();
-        let mut inode: Result<InodeType, String> = stored.get(global.clone()).await;
+    // Adds the file, or makes it the current version of the file already called `name`
+    pub async fn overwrite<U: GlobalTrait + Send + Sync>(
+        &mut self,
+        global: Arc<U>,
+        name: &String,
+        mut file: File,
+    ) -> Result<Stored, Error> {
+        let previous = match self.lookup(global.clone(), name).await? {
+            Some(stored) => stored,
+            None => return self.add(global, name, file.to_enum()).await,
+        };
+        let pruned = match previous.get::<InodeType, U>(global.clone()).await? {
+            InodeType::File(old) => file.supersede(old, global.get_version_retention()),
+            _ => return Err(Error::Invalid(format!("{} is not a file", name))),
+        };
+        self.store_file(global, name, previous, file, pruned).await
+    }
 
-        let res = match inode {
-            Ok(ref mut inode) => inode.delete(global.clone()).await,
-            Err(e) => Err(e),
+    // Makes version `number` of the file `name` current again, see File::restore
+    pub async fn restore_version<U: GlobalTrait + Send + Sync>(
+        &mut self,
+        global: Arc<U>,
+        name: &String,
+        number: usize,
+    ) -> Result<Stored, Error> {
+        let previous = self.get(global.clone(), name).await?;
+        let mut file = match previous.get::<InodeType, U>(global.clone()).await? {
+            InodeType::File(file) => file,
+            _ => return Err(Error::Invalid(format!("{} is not a file", name))),
         };
+        let pruned = file.restore(number, global.get_version_retention())?;
+        self.store_file(global, name, previous, file, pruned).await
+    }
 
-        stored.delete(global).await?;
-        res
+    // Stores `file` after a write_at, append or truncate. The chunks the change replaced are deleted
+    // once the directory is committed, see take_replaced.
+    pub async fn update_file<U: GlobalTrait + Send + Sync>(
+        &mut self,
+        global: Arc<U>,
+        name: &String,
+        file: File,
+        replaced: Vec<Stored>,
+    ) -> Result<Stored, Error> {
+        let previous = self.get(global.clone(), name).await?;
+        let stored = self
+            .store_file(global, name, previous, file, Vec::new())
+            .await?;
+        self.replaced.extend(replaced);
+        Ok(stored)
     }
 
-    pub fn unlink(&mut self, name: &String) -> Result<Stored, String> {
-        self.children
-            .remove(name)
-            .ok_or(format!("File {} does not exist", name))
+    // Files are copy-on-write like directories, snapshots may still use the old inode.
+    // A hard linked file is written in place instead, so every name sees the new contents.
+    // The old inode and the pruned versions are deleted along with the replaced shard nodes.
+    async fn store_file<U: GlobalTrait + Send + Sync>(
+        &mut self,
+        global: Arc<U>,
+        name: &String,
+        previous: Stored,
+        file: File,
+        pruned: Vec<Version>,
+    ) -> Result<Stored, Error> {
+        if file.metadata.links > 1 {
+            if global.is_pinned(&previous) {
+                return Err(Error::Conflict(format!(
+                    "{} is hard linked and part of a snapshot, it can't be changed",
+                    name
+                )));
+            }
+            previous.put(global.clone(), file.to_enum()).await?;
+            self.metadata.touch();
+        } else {
+            let stored = Stored::create(global.clone(), file.to_enum()).await?;
+            self.replace(global.clone(), name, stored).await?;
+            self.replaced.push(previous);
+        }
+
+        // the saved tree still uses them until the directory is committed, see take_replaced
+        for version in pruned {
+            match version.data.chunks(global.clone()).await {
+                Ok(chunks) => self.replaced.extend(chunks),
+                Err(e) => println!("failed to list the chunks of an old version: {}", e),
+            }
+        }
+        self.get(global, name).await
     }
 
-    pub fn list(&self) -> Vec<String> {
-        self.children.keys().cloned().collect()
+    pub async fn unlink<U: GlobalTrait>(
+        &mut self,
+        global: Arc<U>,
+        name: &String,
+    ) -> Result<Stored, Error> {
+        let removed = match self.children.remove(name) {
+            Some(stored) => Some(stored),
+            None => match &mut self.shards {
+                Some(shards) => shards.remove(global, name, &mut self.replaced).await?,
+                None => None,
+            },
+        };
+        let stored =
+            removed.ok_or_else(|| Error::NotFound(format!("File {} does not exist", name)))?;
+        self.metadata.modified(Size::Entries(self.count() as u64));
+        Ok(stored)
     }
 
-    pub fn list_tuples(&self) -> Vec<(String, Stored)> {
-        self.children
+    // Points an existing entry somewhere else, returns where it pointed before
+    pub async fn replace<U: GlobalTrait>(
+        &mut self,
+        global: Arc<U>,
+        name: &String,
+        stored: Stored,
+    ) -> Result<Stored, Error> {
+        let previous = match self.children.get_mut(name) {
+            Some(entry) => std::mem::replace(entry, stored),
+            None => {
+                self.get(global.clone(), name).await?;
+                let shards = self
+                    .shards
+                    .as_mut()
+                    .ok_or(Error::Internal("Directory has no shards".to_string()))?;
+                shards
+                    .insert(global, name.clone(), stored, &mut self.replaced)
+                    .await?
+                    .ok_or_else(|| Error::NotFound(format!("File {} does not exist", name)))?
+            }
+        };
+        self.metadata.touch();
+        Ok(previous)
+    }
+
+    // Searches every entry, so it loads every shard node. Look entries up by name where it is known.
+    pub async fn name_of<U: GlobalTrait>(
+        &self,
+        global: Arc<U>,
+        stored: &Stored,
+    ) -> Result<Option<String>, Error> {
+        Ok(self
+            .list_tuples(global)
+            .await?
+            .into_iter()
+            .find(|(_, child)| child == stored)
+            .map(|(name, _)| name))
+    }
+
+    // Opens the directory at `names` below this one for changes, see DirectoryPath
+    pub async fn open_path<U: GlobalTrait>(
+        self,
+        global: Arc<U>,
+        names: &[String],
+    ) -> Result<DirectoryPath, Error> {
+        let mut path = DirectoryPath::new(self);
+        for name in names {
+            path.enter(global.clone(), name).await?;
+        }
+        Ok(path)
+    }
+
+    // How many entries it has, without loading any shards
+    pub fn count(&self) -> usize {
+        self.children.len() + self.shards.as_ref().map_or(0, |shards| shards.count)
+    }
+
+    pub async fn list<U: GlobalTrait>(&self, global: Arc<U>) -> Result<Vec<String>, Error> {
+        Ok(self
+            .list_tuples(global)
+            .await?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    pub async fn list_tuples<U: GlobalTrait>(
+        &self,
+        global: Arc<U>,
+    ) -> Result<Vec<(String, Stored)>, Error> {
+        Ok(self.walk(global).await?.0)
+    }
+
+    // Every entry and every stored shard node
+    async fn walk<U: GlobalTrait>(
+        &self,
+        global: Arc<U>,
+    ) -> Result<(Vec<(String, Stored)>, Vec<Stored>), Error> {
+        let mut entries = self
+            .children
             .iter()
             .map(|(k, v)| (k.clone(), v.clone()))
-            .collect()
+            .collect::<Vec<_>>();
+        let mut nodes = Vec::new();
+        if let Some(shards) = &self.shards {
+            let (sharded, stored) = shards.walk(global).await?;
+            entries.extend(sharded);
+            nodes = stored;
+        }
+        Ok((entries, nodes))
+    }
+
+    // Up to `limit` entries after the one called `after`, ordered by the hash of their names. The order doesn't
+    // change as entries come and go, so paging through sees every entry that stays in the directory meanwhile.
+    pub async fn page<U: GlobalTrait + Send + Sync>(
+        &self,
+        global: Arc<U>,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Stored)>, Error> {
+        let after = after.map(|name| (hash(name), name.to_string()));
+        let mut page = self
+            .children
+            .iter()
+            .map(|(name, stored)| (hash(name), name.clone(), stored.clone()))
+            .filter(|(hash, name, _)| {
+                after
+                    .as_ref()
+                    .is_none_or(|after| (*hash, name) > (after.0, &after.1))
+            })
+            .collect::<Vec<_>>();
+        if let Some(shards) = &self.shards {
+            page.extend(shards.page(global, after.as_ref(), limit).await?);
+        }
+        page.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+        Ok(page
+            .into_iter()
+            .take(limit)
+            .map(|(_, name, stored)| (name, stored))
+            .collect())
+    }
+
+    async fn lookup<U: GlobalTrait>(
+        &self,
+        global: Arc<U>,
+        name: &String,
+    ) -> Result<Option<Stored>, Error> {
+        match (self.children.get(name), &self.shards) {
+            (Some(stored), _) => Ok(Some(stored.clone())),
+            (None, Some(shards)) => shards.get(global, name).await,
+            (None, None) => Ok(None),
+        }
+    }
+
+    pub async fn get<U: GlobalTrait>(
+        &self,
+        global: Arc<U>,
+        name: &String,
+    ) -> Result<Stored, Error> {
+        self.lookup(global, name)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("File {} does not exist", name)))
     }
 
-    pub fn get(&self, name: &String) -> Result<&Stored, String> {
-        self.children
-            .get(name)
-            .ok_or(format!("File {} does not exist", name))
+    pub async fn put<U: GlobalTrait>(
+        &mut self,
+        global: Arc<U>,
+        name: &String,
+        stored: Stored,
+    ) -> Result<(), Error> {
+        if self.lookup(global.clone(), name).await?.is_some() {
+            return Err(Error::AlreadyExists(format!(
+                "File {} already exists",
+                name
+            )));
+        }
+        self.insert(global, name, stored).await
     }
 
-    pub fn put(&mut self, name: &String, stored: Stored) -> Result<(), String> {
-        if self.children.contains_key(name) {
-            return Err(format!("File {} already exists", name));
+    // Adds an entry that was checked not to exist
+    async fn insert<U: GlobalTrait>(
+        &mut self,
+        global: Arc<U>,
+        name: &str,
+        stored: Stored,
+    ) -> Result<(), Error> {
+        match &mut self.shards {
+            Some(shards) => {
+                shards
+                    .insert(global, name.to_string(), stored, &mut self.replaced)
+                    .await?;
+            }
+            None => {
+                self.children.insert(name.to_string(), stored);
+            }
         }
+        self.metadata.modified(Size::Entries(self.count() as u64));
 
-        self.children.insert(name.clone(), stored);
-        self.metadata.modified(Size::Entries(self.children.len()));
+        Ok(())
+    }
 
+    // Writes the changed shard nodes, call it before storing the directory. Entries spill into shards past
+    // `directories.inline` and come back once they are down to half of it.
+    // The nodes it replaced are deleted by whoever saves the directory, see take_replaced.
+    pub async fn flush<U: GlobalTrait + Send + Sync>(
+        &mut self,
+        global: Arc<U>,
+    ) -> Result<(), Error> {
+        let config = global.get_shard_config();
+        if self.shards.is_none() && self.children.len() > config.inline {
+            self.shards = Some(Shards::new());
+        }
+        let shards = match &mut self.shards {
+            Some(shards) => shards,
+            None => return Ok(()),
+        };
+        // a merge can leave entries inline next to the shards
+        for (name, stored) in self.children.drain() {
+            shards
+                .insert(global.clone(), name, stored, &mut self.replaced)
+                .await?;
+        }
+        if shards.count * 2 > config.inline {
+            return shards.flush(global.clone(), config.shard).await;
+        }
+        let (entries, nodes) = shards.walk(global.clone()).await?;
+        self.children.extend(entries);
+        self.replaced.extend(nodes);
+        self.shards = None;
         Ok(())
     }
+
+    // The shard nodes flushed changes replaced and the file inodes, versions and chunks files dropped,
+    // to delete once the directory is saved. Until then the saved tree may still point at them.
+    pub fn take_replaced(&mut self) -> Vec<Stored> {
+        std::mem::take(&mut self.replaced)
+    }
+
+    // For replaying and merging saved roots, which never have unflushed changes
+    pub fn children(&self) -> &HashMap<String, Stored> {
+        &self.children
+    }
+
+    pub fn children_mut(&mut self) -> &mut HashMap<String, Stored> {
+        &mut self.children
+    }
+
+    pub fn shards(&self) -> Option<&Shards> {
+        self.shards.as_ref()
+    }
+
+    pub fn set_shards(&mut self, shards: Option<Shards>) {
+        self.shards = shards;
+    }
 }
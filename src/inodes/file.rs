@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use async_trait::async_trait;
 use futures::{stream::BoxStream, StreamExt};
@@ -6,37 +9,123 @@ use serde::{Deserialize, Serialize};
 
 use super::{
     inode::{Inode, InodeType},
-    metadata::{Metadata, Size},
+    metadata::{Metadata, Size, Timestamp},
 };
 use crate::{
     blocks::{
         block::{Block, BlockType},
         indirect_block::IndirectBlock,
     },
+    error::Error,
     global::GlobalTrait,
+    stored::Stored,
 };
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct File {
     pub data: IndirectBlock,
     pub metadata: Metadata,
+    #[serde(rename = "v")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub versions: Vec<Version>, // newest first
+}
+
+// The contents a file had before it was overwritten
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Version {
+    #[serde(rename = "d")]
+    pub data: IndirectBlock,
+    #[serde(rename = "m")]
+    pub metadata: Metadata,
+    #[serde(rename = "r")]
+    pub replaced: u64, // when it stopped being the current version
+}
+
+// How many old versions overwriting a file keeps, and for how long
+#[derive(Deserialize, Debug)]
+pub struct VersionRetention {
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+    #[serde(default)]
+    pub max_age: Option<u64>, // in seconds since the version was replaced
+}
+
+const fn default_keep() -> usize {
+    10
+}
+
+impl Default for VersionRetention {
+    fn default() -> Self {
+        Self {
+            keep: default_keep(),
+            max_age: None,
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn read<'a, U: GlobalTrait + std::marker::Send + std::marker::Sync + 'a>(
+    data: &'a IndirectBlock,
+    global: Arc<U>,
+) -> BoxStream<'a, Result<Vec<u8>, Error>> {
+    Box::pin(async_stream::stream! {
+        let range = data.range(global.clone()).await?;
+        let mut stream = data.get(global.clone(), range.clone());
+        while let Some(result) = stream.next().await {
+            yield result;
+        }
+    })
 }
 
 #[async_trait]
 impl Inode for File {
-    async fn metadata(&self) -> &Metadata {
+    fn metadata(&self) -> &Metadata {
         &self.metadata
     }
 
+    fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
     async fn delete<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &mut self,
         global: Arc<U>,
-    ) -> Result<(), String> {
-        self.data.delete(global).await
+    ) -> Result<(), Error> {
+        let mut errors = Vec::new();
+        for version in self.versions.drain(..) {
+            if let Err(e) = version.data.delete(global.clone()).await {
+                errors.push(e);
+            }
+        }
+        if let Err(e) = self.data.delete(global).await {
+            errors.push(e);
+        }
+        match errors.len() {
+            0 => Ok(()),
+            _ => Err(Error::many(errors)),
+        }
+    }
+
+    async fn chunks<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &self,
+        global: Arc<U>,
+    ) -> Result<Vec<Stored>, Error> {
+        let mut chunks = self.data.chunks(global.clone()).await?;
+        for version in &self.versions {
+            chunks.extend(version.data.chunks(global.clone()).await?);
+        }
+        Ok(chunks)
     }
 }
 
 impl File {
+    #[allow(clippy::wrong_self_convention)] // mirrors Block::to_enum
     pub fn to_enum(self) -> InodeType {
         InodeType::File(self)
     }
@@ -44,8 +133,8 @@ impl File {
     pub async fn create<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         global: Arc<U>,
         data: Vec<u8>,
-    ) -> Result<Self, String> {
-        let size = data.len();
+    ) -> Result<Self, Error> {
+        let size = data.len() as u64;
         let block = match IndirectBlock::create(global, data, 0).await? {
             BlockType::Indirect(block) => block,
             _ => panic!("This should never happen"),
@@ -55,19 +144,150 @@ impl File {
         Ok(Self {
             data: block,
             metadata,
+            versions: Vec::new(),
         })
     }
 
     pub fn get<'a, U: GlobalTrait + std::marker::Send + std::marker::Sync + 'a>(
         &'a self,
         global: Arc<U>,
-    ) -> BoxStream<'a, Result<Vec<u8>, String>> {
-        Box::pin(async_stream::stream! {
-            let range = self.data.range(global.clone()).await?;
-            let mut stream = self.data.get(global.clone(), range.clone());
-            while let Some(result) = stream.next().await {
-                yield result;
+    ) -> BoxStream<'a, Result<Vec<u8>, Error>> {
+        read(&self.data, global)
+    }
+
+    // Writes `data` at `offset`, a gap after the end is filled with zeros. Only the chunks it touches are rewritten.
+    // Returns the chunks it replaced, see Directory::update_file.
+    pub async fn write_at<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &mut self,
+        global: Arc<U>,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> Result<Vec<Stored>, Error> {
+        let size = self.data.range(global.clone()).await?.end;
+        let (offset, data) = match offset > size {
+            true => {
+                let gap = usize::try_from(offset - size)
+                    .map_err(|_| Error::Invalid("The gap is too large".to_string()))?;
+                let mut padded = vec![0; gap];
+                padded.extend(data);
+                (size, padded)
             }
-        })
+            false => (offset, data),
+        };
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+        let end = offset + data.len() as u64;
+        let replaced = self.data.put(global, data, offset..end).await?;
+        self.changed(std::cmp::max(size, end));
+        Ok(replaced)
+    }
+
+    pub async fn append<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &mut self,
+        global: Arc<U>,
+        data: Vec<u8>,
+    ) -> Result<Vec<Stored>, Error> {
+        let size = self.data.range(global.clone()).await?.end;
+        self.write_at(global, size, data).await
+    }
+
+    // Cuts the file off at `size`, or grows it with zeros
+    pub async fn truncate<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &mut self,
+        global: Arc<U>,
+        size: u64,
+    ) -> Result<Vec<Stored>, Error> {
+        let current = self.data.range(global.clone()).await?.end;
+        if size >= current {
+            return self.write_at(global, size, Vec::new()).await;
+        }
+        let replaced = self.data.truncate(global, size).await?;
+        self.changed(size);
+        Ok(replaced)
+    }
+
+    fn changed(&mut self, size: u64) {
+        self.metadata.modified(Size::Bytes(size));
+        // the uploaded file's time would be restored on download otherwise
+        if let Some(posix) = &mut self.metadata.posix {
+            posix.mtime = Some(Timestamp::from_system_time(SystemTime::now()));
+        }
+    }
+
+    // Versions are numbered from 1, the version replaced last
+    pub fn version(&self, number: usize) -> Result<&Version, Error> {
+        number
+            .checked_sub(1)
+            .and_then(|index| self.versions.get(index))
+            .ok_or_else(|| Error::NotFound(format!("Version {} does not exist", number)))
+    }
+
+    // Makes this file the current version of `previous`, which keeps its history.
+    // Returns the versions the retention drops, their data is still to be deleted.
+    pub fn supersede(&mut self, previous: File, retention: &VersionRetention) -> Vec<Version> {
+        self.metadata.created = previous.metadata.created;
+        self.metadata.links = previous.metadata.links;
+        self.versions = previous.versions;
+        self.versions.insert(
+            0,
+            Version {
+                data: previous.data,
+                metadata: previous.metadata,
+                replaced: now(),
+            },
+        );
+        self.prune(retention)
+    }
+
+    // Makes an old version current again, the current one becomes the newest old version
+    pub fn restore(
+        &mut self,
+        number: usize,
+        retention: &VersionRetention,
+    ) -> Result<Vec<Version>, Error> {
+        self.version(number)?;
+        let version = self.versions.remove(number - 1);
+        let current = Version {
+            data: std::mem::replace(&mut self.data, version.data),
+            metadata: std::mem::replace(&mut self.metadata, version.metadata),
+            replaced: now(),
+        };
+        self.metadata.created = current.metadata.created;
+        self.metadata.links = current.metadata.links;
+        self.metadata.touch();
+        self.versions.insert(0, current);
+        Ok(self.prune(retention))
+    }
+
+    fn prune(&mut self, retention: &VersionRetention) -> Vec<Version> {
+        let now = now();
+        let (kept, dropped) = std::mem::take(&mut self.versions)
+            .into_iter()
+            .enumerate()
+            .partition::<Vec<_>, _>(|(index, version)| {
+                *index < retention.keep
+                    && retention
+                        .max_age
+                        .map(|age| now.saturating_sub(version.replaced) <= age)
+                        .unwrap_or(true)
+            });
+        self.versions = kept.into_iter().map(|(_, version)| version).collect();
+        dropped.into_iter().map(|(_, version)| version).collect()
+    }
+}
+
+impl Version {
+    pub fn get<'a, U: GlobalTrait + std::marker::Send + std::marker::Sync + 'a>(
+        &'a self,
+        global: Arc<U>,
+    ) -> BoxStream<'a, Result<Vec<u8>, Error>> {
+        read(&self.data, global)
+    }
+
+    pub fn human_replaced(&self) -> String {
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(self.replaced);
+        let datetime: chrono::DateTime<chrono::Utc> = time.into();
+        datetime.format("%Y-%m-%d %H:%M:%S").to_string()
     }
 }
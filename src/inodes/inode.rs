@@ -7,17 +7,23 @@ use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::global::GlobalTrait;
+use crate::{error::Error, global::GlobalTrait, stored::Stored};
 
-use super::{directory::Directory, file::File, metadata::Metadata};
+use super::{directory::Directory, file::File, metadata::Metadata, symlink::Symlink};
 
 #[async_trait]
 pub trait Inode {
-    async fn metadata(&self) -> &Metadata;
+    fn metadata(&self) -> &Metadata;
+    fn metadata_mut(&mut self) -> &mut Metadata;
     async fn delete<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &mut self,
         global: Arc<U>,
-    ) -> Result<(), String>;
+    ) -> Result<(), Error>;
+    // every chunk below the inode, not counting the one it is stored in
+    async fn chunks<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &self,
+        global: Arc<U>,
+    ) -> Result<Vec<Stored>, Error>;
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,6 +32,8 @@ pub enum InodeType {
     File(File),
     #[serde(rename = "d")]
     Directory(Directory),
+    #[serde(rename = "s")]
+    Symlink(Symlink),
 }
 
 macro_rules! match_method {
@@ -33,20 +41,53 @@ macro_rules! match_method {
         match $self {
             InodeType::File(inode) => inode.$method($($arg),*),
             InodeType::Directory(inode) => inode.$method($($arg),*),
+            InodeType::Symlink(inode) => inode.$method($($arg),*),
         }
     };
 }
 
 #[async_trait]
 impl Inode for InodeType {
-    async fn metadata(&self) -> &Metadata {
-        match_method!(self, metadata,).await
+    fn metadata(&self) -> &Metadata {
+        match_method!(self, metadata,)
+    }
+
+    fn metadata_mut(&mut self) -> &mut Metadata {
+        match_method!(self, metadata_mut,)
     }
 
     async fn delete<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
         &mut self,
         global: Arc<U>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         match_method!(self, delete, global).await
     }
+
+    async fn chunks<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &self,
+        global: Arc<U>,
+    ) -> Result<Vec<Stored>, Error> {
+        match_method!(self, chunks, global).await
+    }
+}
+
+// Removes one link to the inode at `stored`, deleting it and its chunks with the last one.
+// A snapshot keeps the count it was taken with, so a pinned count is never lowered: leaking beats deleting
+// data that a restored snapshot links to more than once.
+pub async fn drop_link<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+    global: Arc<U>,
+    stored: &Stored,
+) -> Result<(), Error> {
+    let mut inode = stored.get::<InodeType, U>(global.clone()).await?;
+    let links = inode.metadata().links;
+    if links > 1 {
+        if global.is_pinned(stored) {
+            return Ok(());
+        }
+        inode.metadata_mut().links = links - 1;
+        return stored.put(global, inode).await;
+    }
+    let res = inode.delete(global.clone()).await;
+    stored.delete(global).await?;
+    res
 }
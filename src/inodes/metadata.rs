@@ -1,24 +1,20 @@
 use serde::{Deserialize, Serialize};
 use std::{
     cmp::Ordering,
-    time::{SystemTime, UNIX_EPOCH},
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
 pub enum Size {
     #[serde(rename = "e")]
-    Entries(usize),
+    Entries(u64),
     #[serde(rename = "b")]
-    Bytes(usize),
+    Bytes(u64),
+    #[default]
     Empty,
 }
 
-impl Default for Size {
-    fn default() -> Self {
-        Self::Empty
-    }
-}
-
 impl PartialOrd for Size {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         // entries are always less than bytes, empty is always less than anything
@@ -62,12 +58,59 @@ pub struct Metadata {
     #[serde(rename = "s")]
     #[serde(default, skip_serializing_if = "is_default")]
     pub size: Size,
+
+    #[serde(rename = "l")]
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    pub links: u32, // how many directory entries point at the inode, see Directory::link
+
+    #[serde(rename = "p")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub posix: Option<Posix>, // only for entries uploaded from a filesystem, see posix.rs
+
+    #[serde(rename = "t")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>, // files only, detected when they are uploaded, see mime.rs
+}
+
+// Seconds and nanoseconds since the epoch
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub struct Timestamp(pub i64, pub u32);
+
+// What a POSIX filesystem keeps about an entry besides its contents, every part is optional
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct Posix {
+    #[serde(rename = "o")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>, // permission bits only, the type is the inode's
+    #[serde(rename = "u")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(rename = "g")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+    #[serde(rename = "m")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<Timestamp>,
+    #[serde(rename = "a")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub atime: Option<Timestamp>,
+    #[serde(rename = "x")]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub xattrs: BTreeMap<String, Vec<u8>>,
 }
 
 const fn is_default(size: &Size) -> bool {
     matches!(size, Size::Empty)
 }
 
+const fn one() -> u32 {
+    1
+}
+
+const fn is_one(links: &u32) -> bool {
+    *links == 1
+}
+
 impl Metadata {
     pub fn new() -> Self {
         Self {
@@ -80,6 +123,9 @@ impl Metadata {
                 .unwrap_or_default()
                 .as_secs(),
             size: Size::Empty,
+            links: 1,
+            posix: None,
+            mime: None,
         }
     }
 
@@ -107,3 +153,32 @@ impl Metadata {
         datetime.format("%Y-%m-%d %H:%M:%S").to_string()
     }
 }
+
+impl Timestamp {
+    pub fn from_system_time(time: SystemTime) -> Self {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => Timestamp(after.as_secs() as i64, after.subsec_nanos()),
+            Err(e) => {
+                // before the epoch, the nanoseconds still count forwards
+                let before = e.duration();
+                match before.subsec_nanos() {
+                    0 => Timestamp(-(before.as_secs() as i64), 0),
+                    nanos => Timestamp(-(before.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+                }
+            }
+        }
+    }
+
+    pub fn to_system_time(self) -> SystemTime {
+        let nanos = Duration::from_nanos(self.1 as u64);
+        match self.0 >= 0 {
+            true => UNIX_EPOCH + Duration::from_secs(self.0 as u64) + nanos,
+            false => UNIX_EPOCH - Duration::from_secs(self.0.unsigned_abs()) + nanos,
+        }
+    }
+
+    pub fn human(&self) -> String {
+        let datetime: chrono::DateTime<chrono::Utc> = self.to_system_time().into();
+        datetime.format("%Y-%m-%d %H:%M:%S%.f").to_string()
+    }
+}
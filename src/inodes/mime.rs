@@ -0,0 +1,27 @@
+/*
+   Files get a MIME type when they are uploaded, so the HTTP service can let browsers preview them.
+   The contents win over the name when they have a known signature, text formats have none and go by the extension.
+   Files uploaded before this, or whose type can't be told, fall back to the name when they are served.
+*/
+
+// The type of a file called `name` that starts with `data`
+pub fn detect(name: &str, data: &[u8]) -> Option<String> {
+    match infer::get(data) {
+        Some(kind) => Some(kind.mime_type().to_string()),
+        None => guess(name),
+    }
+}
+
+// The type by extension only
+pub fn guess(name: &str) -> Option<String> {
+    mime_guess::from_path(name)
+        .first()
+        .map(|mime| mime.essence_str().to_string())
+}
+
+// What to send as Content-Type for a file with metadata type `mime` called `name`
+pub fn content_type(mime: Option<&str>, name: &str) -> String {
+    mime.map(str::to_string)
+        .or_else(|| guess(name))
+        .unwrap_or("application/octet-stream".to_string())
+}
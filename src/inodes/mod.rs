@@ -2,3 +2,7 @@ pub mod directory;
 pub mod file;
 pub mod inode;
 pub mod metadata;
+pub mod mime;
+pub mod path;
+pub mod shards;
+pub mod symlink;
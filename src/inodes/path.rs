@@ -0,0 +1,144 @@
+/*
+   Changes below the root go through a DirectoryPath, which holds every directory from the top (usually the root)
+   down to the one being changed. Committing writes the changed directory and each of its ancestors to new chunks,
+   bottom-up, touching their metadata on the way. The tree only switches over when the caller saves the new top,
+   so readers never see half a change, and the replaced chunks are deleted after that.
+*/
+
+use std::sync::Arc;
+
+use super::{directory::Directory, inode::InodeType};
+use crate::{error::Error, global::GlobalTrait, stored::Stored};
+
+struct Level {
+    name: String,   // in the parent directory
+    stored: Stored, // where the directory is stored before the commit
+    directory: Directory,
+}
+
+pub struct DirectoryPath {
+    top: Directory,
+    levels: Vec<Level>,
+}
+
+// What DirectoryPath::commit wrote, the top still has to be saved before calling cleanup
+pub struct Commit {
+    pub top: Directory,
+    pub path: Vec<Stored>, // where each directory below the top is stored now
+    replaced: Vec<Stored>,
+}
+
+impl DirectoryPath {
+    pub fn new(top: Directory) -> Self {
+        DirectoryPath {
+            top,
+            levels: Vec::new(),
+        }
+    }
+
+    // Descends into the subdirectory `name` of the current directory
+    pub async fn enter<U: GlobalTrait>(
+        &mut self,
+        global: Arc<U>,
+        name: &String,
+    ) -> Result<(), Error> {
+        let stored = self.directory().get(global.clone(), name).await?;
+        let directory = match stored.get::<InodeType, U>(global).await? {
+            InodeType::Directory(directory) => directory,
+            _ => return Err(Error::Invalid(format!("{} is not a directory", name))),
+        };
+        self.levels.push(Level {
+            name: name.clone(),
+            stored,
+            directory,
+        });
+        Ok(())
+    }
+
+    // The directory the path leads to
+    pub fn directory(&mut self) -> &mut Directory {
+        match self.levels.last_mut() {
+            Some(level) => &mut level.directory,
+            None => &mut self.top,
+        }
+    }
+
+    // Where each directory below the top is stored, as of opening the path
+    pub fn stored(&self) -> Vec<Stored> {
+        self.levels
+            .iter()
+            .map(|level| level.stored.clone())
+            .collect()
+    }
+
+    // The names of the directories below the top
+    pub fn names(&self) -> Vec<String> {
+        self.levels.iter().map(|level| level.name.clone()).collect()
+    }
+
+    pub async fn commit<U: GlobalTrait + Send + Sync>(
+        mut self,
+        global: Arc<U>,
+    ) -> Result<Commit, Error> {
+        let mut path: Vec<Stored> = Vec::new();
+        let mut replaced = Vec::new();
+        let mut child: Option<String> = None; // name of the level written last, it is at path[0]
+        while let Some(level) = self.levels.pop() {
+            let written = child.as_ref().map(|name| (name, path[0].clone()));
+            match write_level(global.clone(), level.directory, written).await {
+                Ok((stored, nodes)) => {
+                    path.insert(0, stored);
+                    replaced.extend(nodes);
+                }
+                Err(e) => {
+                    // nothing points at the new chunks yet
+                    for stored in path {
+                        let _ = stored.delete(global.clone()).await;
+                    }
+                    return Err(e);
+                }
+            }
+            replaced.push(level.stored);
+            child = Some(level.name);
+        }
+        if let Some(name) = child {
+            self.top
+                .replace(global.clone(), &name, path[0].clone())
+                .await?;
+        }
+        // the caller saves the top, its shards have to be written first
+        self.top.flush(global).await?;
+        replaced.extend(self.top.take_replaced());
+        Ok(Commit {
+            top: self.top,
+            path,
+            replaced,
+        })
+    }
+}
+
+// Points the directory at its rewritten child and stores it, also returns the shard nodes that replaced
+async fn write_level<U: GlobalTrait + Send + Sync>(
+    global: Arc<U>,
+    mut directory: Directory,
+    child: Option<(&String, Stored)>,
+) -> Result<(Stored, Vec<Stored>), Error> {
+    if let Some((name, stored)) = child {
+        directory.replace(global.clone(), name, stored).await?;
+    }
+    directory.flush(global.clone()).await?;
+    let replaced = directory.take_replaced();
+    let stored = Stored::create(global, directory.to_enum()).await?;
+    Ok((stored, replaced))
+}
+
+impl Commit {
+    // Deletes the chunks the commit replaced, a failure only leaks them
+    pub async fn cleanup<U: GlobalTrait>(&self, global: Arc<U>) {
+        for stored in &self.replaced {
+            if let Err(e) = stored.delete(global.clone()).await {
+                println!("failed to delete replaced directory: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,427 @@
+/*
+   Directories with many entries spill them into a hash trie of separately stored nodes, so no chunk grows with
+   the directory and a change only rewrites the nodes on the way to one entry.
+   The directory keeps the top level of the trie: 64 slots, picked by the first 6 bits of an entry's name hash.
+   Every slot holds a leaf with entries or a branch with 64 more slots for the next 6 bits. Leaves with more than
+   `directories.shard` entries are split when the directory is flushed.
+   Nodes are copy-on-write like directories. A changed node stays in memory until the directory is flushed,
+   and the node it replaced is only deleted once the new tree is saved, see Directory::flush.
+*/
+
+use futures::future::BoxFuture;
+use serde::{ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{error::Error, global::GlobalTrait, stored::Stored};
+
+#[derive(Deserialize, Debug)]
+pub struct ShardConfig {
+    #[serde(default = "default_inline")]
+    pub inline: usize, // entries a directory holds itself before it spills them into shards
+    #[serde(default = "default_shard")]
+    pub shard: usize, // entries a shard holds before it is split
+}
+
+const fn default_inline() -> usize {
+    1000
+}
+const fn default_shard() -> usize {
+    1000
+}
+
+impl Default for ShardConfig {
+    fn default() -> Self {
+        Self {
+            inline: default_inline(),
+            shard: default_shard(),
+        }
+    }
+}
+
+const BITS: usize = 6;
+const FANOUT: usize = 1 << BITS;
+// the deepest slots that still get a full 6 bits of the hash, leaves there grow instead of splitting
+const MAX_DEPTH: usize = 64 / BITS - 1;
+
+// FNV-1a with a final mix, so the top bits depend on every byte. It must never change, nodes are placed by it.
+pub fn hash(name: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in name.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+// The slot an entry with `hash` is in at `depth`
+fn index(hash: u64, depth: usize) -> usize {
+    (hash >> (64 - BITS * (depth + 1))) as usize & (FANOUT - 1)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Node {
+    #[serde(rename = "l")]
+    Leaf(HashMap<String, Stored>),
+    #[serde(rename = "b")]
+    Branch(Vec<Slot>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Slot {
+    Empty,
+    Stored(Stored),
+    Changed(Box<Node>), // not written yet, see Shards::flush
+}
+
+// Slots are stored as the node's Stored, a changed node has to be flushed first
+impl Serialize for Slot {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Slot::Empty => serializer.serialize_none(),
+            Slot::Stored(stored) => serializer.serialize_some(stored),
+            Slot::Changed(_) => Err(S::Error::custom("directory has unwritten shards")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Slot {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match Option::<Stored>::deserialize(deserializer)? {
+            Some(stored) => Slot::Stored(stored),
+            None => Slot::Empty,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Shards {
+    #[serde(rename = "n")]
+    pub count: usize,
+    #[serde(rename = "s")]
+    slots: Vec<Slot>,
+}
+
+impl Shards {
+    pub fn new() -> Self {
+        Shards {
+            count: 0,
+            slots: vec![Slot::Empty; FANOUT],
+        }
+    }
+
+    // Our changes since `base` on top of theirs. A top slot only one side changed is taken from that side,
+    // the entries of a slot both sides changed are merged one by one, where both changed an entry ours wins.
+    // Merged slots are written right away. The nodes they replace are left behind, the other roots may use them.
+    pub async fn merge<U: GlobalTrait + Send + Sync>(
+        global: Arc<U>,
+        base: &Shards,
+        ours: &Shards,
+        theirs: &Shards,
+    ) -> Result<Shards, Error> {
+        let mut merged = theirs.clone();
+        // exact for the slots only one side changed, the others are corrected below
+        let mut count = theirs.count as i64 + ours.count as i64 - base.count as i64;
+        for (i, slot) in merged.slots.iter_mut().enumerate() {
+            if theirs.slots[i] == base.slots[i] {
+                *slot = ours.slots[i].clone();
+                continue;
+            }
+            if ours.slots[i] == base.slots[i] {
+                continue;
+            }
+            let base = slot_entries(global.clone(), &base.slots[i]).await?;
+            let ours = slot_entries(global.clone(), &ours.slots[i]).await?;
+            let mut entries = slot_entries(global.clone(), &theirs.slots[i]).await?;
+            count += base.len() as i64 - ours.len() as i64 - entries.len() as i64;
+            for name in base.keys() {
+                if !ours.contains_key(name) {
+                    entries.remove(name);
+                }
+            }
+            for (name, stored) in ours {
+                if base.get(&name) != Some(&stored) {
+                    entries.insert(name, stored);
+                }
+            }
+            count += entries.len() as i64;
+            *slot = match entries.is_empty() {
+                true => Slot::Empty,
+                false => Slot::Changed(Box::new(Node::Leaf(entries))),
+            };
+        }
+        merged.count = count.max(0) as usize;
+        let shard = global.get_shard_config().shard;
+        flush_slots(&mut merged.slots, 0, global, shard).await?;
+        Ok(merged)
+    }
+
+    pub async fn get<U: GlobalTrait>(
+        &self,
+        global: Arc<U>,
+        name: &str,
+    ) -> Result<Option<Stored>, Error> {
+        let hash = hash(name);
+        let mut depth = 0;
+        // changed nodes are only ever above stored ones
+        let mut slots = &self.slots;
+        let stored = loop {
+            match &slots[index(hash, depth)] {
+                Slot::Empty => return Ok(None),
+                Slot::Stored(stored) => break stored.clone(),
+                Slot::Changed(node) => match node.as_ref() {
+                    Node::Leaf(entries) => return Ok(entries.get(name).cloned()),
+                    Node::Branch(next) => slots = next,
+                },
+            }
+            depth += 1;
+        };
+        let mut node = stored.get::<Node, U>(global.clone()).await?;
+        loop {
+            depth += 1;
+            let stored = match node {
+                Node::Leaf(mut entries) => return Ok(entries.remove(name)),
+                Node::Branch(mut next) => {
+                    match std::mem::replace(&mut next[index(hash, depth)], Slot::Empty) {
+                        Slot::Stored(stored) => stored,
+                        _ => return Ok(None),
+                    }
+                }
+            };
+            node = stored.get::<Node, U>(global.clone()).await?;
+        }
+    }
+
+    // The leaf `name` belongs in, loaded for changes. The stored nodes it loads are added to `replaced`.
+    async fn leaf<U: GlobalTrait>(
+        &mut self,
+        global: Arc<U>,
+        name: &str,
+        replaced: &mut Vec<Stored>,
+    ) -> Result<&mut HashMap<String, Stored>, Error> {
+        let hash = hash(name);
+        let mut slots = &mut self.slots;
+        let mut depth = 0;
+        loop {
+            let slot = &mut slots[index(hash, depth)];
+            let loaded = match slot {
+                Slot::Empty => Some(Node::Leaf(HashMap::new())),
+                Slot::Stored(stored) => {
+                    let node = stored.get::<Node, U>(global.clone()).await?;
+                    replaced.push(stored.clone());
+                    Some(node)
+                }
+                Slot::Changed(_) => None,
+            };
+            if let Some(node) = loaded {
+                *slot = Slot::Changed(Box::new(node));
+            }
+            match slot {
+                Slot::Changed(node) => match node.as_mut() {
+                    Node::Leaf(entries) => return Ok(entries),
+                    Node::Branch(next) => slots = next,
+                },
+                _ => unreachable!("the slot was just loaded"),
+            }
+            depth += 1;
+        }
+    }
+
+    // Returns the entry it replaced
+    pub async fn insert<U: GlobalTrait>(
+        &mut self,
+        global: Arc<U>,
+        name: String,
+        stored: Stored,
+        replaced: &mut Vec<Stored>,
+    ) -> Result<Option<Stored>, Error> {
+        let previous = self
+            .leaf(global, &name, replaced)
+            .await?
+            .insert(name, stored);
+        if previous.is_none() {
+            self.count += 1;
+        }
+        Ok(previous)
+    }
+
+    pub async fn remove<U: GlobalTrait>(
+        &mut self,
+        global: Arc<U>,
+        name: &str,
+        replaced: &mut Vec<Stored>,
+    ) -> Result<Option<Stored>, Error> {
+        // checked first, so a missing entry doesn't rewrite its leaf
+        if self.get(global.clone(), name).await?.is_none() {
+            return Ok(None);
+        }
+        let removed = self.leaf(global, name, replaced).await?.remove(name);
+        if removed.is_some() {
+            self.count -= 1;
+        }
+        Ok(removed)
+    }
+
+    // Every entry and every stored node, in no particular order
+    pub async fn walk<U: GlobalTrait>(
+        &self,
+        global: Arc<U>,
+    ) -> Result<(Vec<(String, Stored)>, Vec<Stored>), Error> {
+        walk_slots(global, &self.slots).await
+    }
+
+    // Up to `limit` entries after `after` in (hash, name) order, with their hashes
+    pub async fn page<U: GlobalTrait + Send + Sync>(
+        &self,
+        global: Arc<U>,
+        after: Option<&(u64, String)>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String, Stored)>, Error> {
+        let mut page = Vec::new();
+        page_slots(&self.slots, 0, global, after, limit, &mut page).await?;
+        Ok(page)
+    }
+
+    // Writes every changed node, splitting leaves with more than `shard` entries
+    pub async fn flush<U: GlobalTrait + Send + Sync>(
+        &mut self,
+        global: Arc<U>,
+        shard: usize,
+    ) -> Result<(), Error> {
+        flush_slots(&mut self.slots, 0, global, shard).await
+    }
+}
+
+async fn walk_slots<U: GlobalTrait>(
+    global: Arc<U>,
+    slots: &[Slot],
+) -> Result<(Vec<(String, Stored)>, Vec<Stored>), Error> {
+    fn walk_changed(slots: &[Slot], entries: &mut Vec<(String, Stored)>, nodes: &mut Vec<Stored>) {
+        for slot in slots {
+            match slot {
+                Slot::Empty => (),
+                Slot::Stored(stored) => nodes.push(stored.clone()),
+                Slot::Changed(node) => match node.as_ref() {
+                    Node::Leaf(leaf) => entries.extend(leaf.clone()),
+                    Node::Branch(next) => walk_changed(next, entries, nodes),
+                },
+            }
+        }
+    }
+    let mut entries = Vec::new();
+    let mut nodes = Vec::new();
+    walk_changed(slots, &mut entries, &mut nodes);
+    // nodes grows while it is read, every stored branch adds its slots
+    let mut next = 0;
+    while let Some(stored) = nodes.get(next) {
+        match stored.get::<Node, U>(global.clone()).await? {
+            Node::Leaf(leaf) => entries.extend(leaf),
+            Node::Branch(slots) => walk_changed(&slots, &mut entries, &mut nodes),
+        }
+        next += 1;
+    }
+    Ok((entries, nodes))
+}
+
+// Every entry below one slot
+async fn slot_entries<U: GlobalTrait>(
+    global: Arc<U>,
+    slot: &Slot,
+) -> Result<HashMap<String, Stored>, Error> {
+    let (entries, _) = walk_slots(global, std::slice::from_ref(slot)).await?;
+    Ok(entries.into_iter().collect())
+}
+
+// `after` is only passed down the slots that lead to it, everything in later slots comes after it
+fn page_slots<'a, U: GlobalTrait + Send + Sync + 'a>(
+    slots: &'a [Slot],
+    depth: usize,
+    global: Arc<U>,
+    after: Option<&'a (u64, String)>,
+    limit: usize,
+    page: &'a mut Vec<(u64, String, Stored)>,
+) -> BoxFuture<'a, Result<(), Error>> {
+    Box::pin(async move {
+        let first = after.map_or(0, |(hash, _)| index(*hash, depth));
+        for (i, slot) in slots.iter().enumerate().skip(first) {
+            if page.len() >= limit {
+                break;
+            }
+            let after = after.filter(|_| i == first);
+            let loaded;
+            let node = match slot {
+                Slot::Empty => continue,
+                Slot::Changed(node) => node.as_ref(),
+                Slot::Stored(stored) => {
+                    loaded = stored.get::<Node, U>(global.clone()).await?;
+                    &loaded
+                }
+            };
+            match node {
+                Node::Leaf(entries) => {
+                    let mut entries = entries
+                        .iter()
+                        .map(|(name, stored)| (hash(name), name.clone(), stored.clone()))
+                        .filter(|(hash, name, _)| {
+                            after.is_none_or(|after| (*hash, name) > (after.0, &after.1))
+                        })
+                        .collect::<Vec<_>>();
+                    entries.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+                    entries.truncate(limit - page.len());
+                    page.extend(entries);
+                }
+                Node::Branch(next) => {
+                    page_slots(next, depth + 1, global.clone(), after, limit, page).await?
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+fn flush_slots<'a, U: GlobalTrait + Send + Sync + 'a>(
+    slots: &'a mut [Slot],
+    depth: usize,
+    global: Arc<U>,
+    shard: usize,
+) -> BoxFuture<'a, Result<(), Error>> {
+    Box::pin(async move {
+        for slot in slots.iter_mut() {
+            let node = match slot {
+                Slot::Changed(node) => node,
+                _ => continue,
+            };
+            if let Node::Leaf(entries) = node.as_mut() {
+                if entries.len() > shard && depth < MAX_DEPTH {
+                    let mut leaves = vec![HashMap::new(); FANOUT];
+                    for (name, stored) in entries.drain() {
+                        leaves[index(hash(&name), depth + 1)].insert(name, stored);
+                    }
+                    let next = leaves
+                        .into_iter()
+                        .map(|leaf| match leaf.is_empty() {
+                            true => Slot::Empty,
+                            false => Slot::Changed(Box::new(Node::Leaf(leaf))),
+                        })
+                        .collect();
+                    **node = Node::Branch(next);
+                }
+            }
+            if let Node::Branch(next) = node.as_mut() {
+                flush_slots(next, depth + 1, global.clone(), shard).await?;
+            }
+            let empty = match node.as_ref() {
+                Node::Leaf(entries) => entries.is_empty(),
+                Node::Branch(next) => next.iter().all(|slot| *slot == Slot::Empty),
+            };
+            *slot = match empty {
+                true => Slot::Empty,
+                false => Slot::Stored(Stored::create(global.clone(), node.as_ref()).await?),
+            };
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,89 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::{
+    inode::{Inode, InodeType},
+    metadata::Metadata,
+};
+use crate::{error::Error, global::GlobalTrait, stored::Stored};
+
+// A path to another entry, resolved by name when it is followed like on a regular filesystem
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Symlink {
+    #[serde(rename = "t")]
+    pub target: String,
+    #[serde(rename = "m")]
+    pub metadata: Metadata,
+}
+
+#[async_trait]
+impl Inode for Symlink {
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    async fn delete<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &mut self,
+        _global: Arc<U>,
+    ) -> Result<(), Error> {
+        Ok(()) // the target lives on its own
+    }
+
+    async fn chunks<U: GlobalTrait + std::marker::Send + std::marker::Sync>(
+        &self,
+        _global: Arc<U>,
+    ) -> Result<Vec<Stored>, Error> {
+        Ok(Vec::new())
+    }
+}
+
+impl Symlink {
+    pub fn new(target: String) -> Self {
+        Self {
+            target,
+            metadata: Metadata::new(),
+        }
+    }
+
+    #[allow(clippy::wrong_self_convention)] // mirrors Block::to_enum
+    pub fn to_enum(self) -> InodeType {
+        InodeType::Symlink(self)
+    }
+
+    // Whether the target starts at the root, and the names to follow with `..` for going up
+    pub fn components(&self) -> (bool, Vec<String>) {
+        components(&self.target)
+    }
+}
+
+fn components(target: &str) -> (bool, Vec<String>) {
+    let names = target
+        .split('/')
+        .filter(|name| !name.is_empty() && *name != ".")
+        .map(|name| name.to_string())
+        .collect();
+    (target.starts_with('/'), names)
+}
+
+// Where `target` leads from the directory at `from`, both as names from the root
+pub fn resolve_path(from: &[String], target: &str) -> Vec<String> {
+    let (absolute, names) = components(target);
+    let mut path = match absolute {
+        true => Vec::new(),
+        false => from.to_vec(),
+    };
+    for name in names {
+        match name.as_str() {
+            ".." => {
+                path.pop();
+            }
+            _ => path.push(name),
+        }
+    }
+    path
+}
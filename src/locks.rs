@@ -0,0 +1,82 @@
+/*
+   Serializes read-modify-write of directories, so two writers never lose each other's entries.
+   Within a process every directory has an async mutex. The shell and the services run as separate
+   processes, so a lock file per directory is locked as well (next to root_path, in `<root_path>.locks`).
+   Lock files are never deleted, removing one could hand the same lock to two processes.
+*/
+
+use crypto::{digest::Digest, sha2::Sha256};
+use std::{
+    collections::HashMap,
+    fs::{create_dir_all, File, OpenOptions},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::OwnedMutexGuard;
+
+use crate::{error::Error, stored::Stored};
+
+#[derive(Default)]
+pub struct DirectoryLocks(Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>);
+
+impl std::fmt::Debug for DirectoryLocks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "DirectoryLocks({} held)",
+            self.0.lock().map(|locks| locks.len()).unwrap_or(0)
+        )
+    }
+}
+
+// Dropping the guard releases both locks, the file lock goes with its file
+pub struct DirectoryGuard {
+    _file: File,
+    _local: OwnedMutexGuard<()>,
+}
+
+fn lock_path(folder: &Path, key: &str) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.input_str(key);
+    folder.join(hasher.result_str())
+}
+
+impl DirectoryLocks {
+    // Locks `directory`, or the root when it is None
+    pub async fn lock(
+        &self,
+        folder: PathBuf,
+        directory: Option<&Stored>,
+    ) -> Result<DirectoryGuard, Error> {
+        let key = directory
+            .map(|stored| stored.as_url())
+            .unwrap_or("root".to_string());
+        let local = {
+            let mut locks = self.0.lock().unwrap();
+            // mutexes nobody holds or waits for are dropped, so the map doesn't grow forever
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(key.clone()).or_default().clone()
+        };
+        let local = local.lock_owned().await;
+
+        let file = tokio::task::spawn_blocking(move || {
+            create_dir_all(&folder).map_err(|e| Error::io("Error creating lock folder", e))?;
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(lock_path(&folder, &key))
+                .map_err(|e| Error::io("Error opening lock file", e))?;
+            file.lock()
+                .map_err(|e| Error::io("Error locking directory", e))?;
+            Ok::<File, Error>(file)
+        })
+        .await
+        .map_err(|e| Error::Internal(format!("Error joining lock task: {}", e)))??;
+
+        Ok(DirectoryGuard {
+            _file: file,
+            _local: local,
+        })
+    }
+}
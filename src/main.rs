@@ -8,13 +8,20 @@ use std::sync::Arc;
 mod blocks;
 mod bucket;
 mod encryption;
+mod error;
 mod global;
 mod inodes;
+mod locks;
+mod posix;
+mod root_file;
 mod s3;
 mod services;
 mod shell;
+mod snapshots;
 mod sources;
 mod stored;
+mod superblock;
+mod trash;
 
 #[cfg(test)]
 mod tests; // this is only included when running tests
@@ -0,0 +1,236 @@
+/*
+   Carries what a POSIX filesystem keeps about an entry across an upload and a download.
+   `capture` reads the permission bits, owner, times and extended attributes of a local path without following
+   symlinks, and `apply` writes them back. Anything the platform or the user can't read or set is left out,
+   the metadata only ever holds what was actually there.
+*/
+
+use std::{collections::BTreeMap, path::Path};
+
+use crate::inodes::metadata::{Posix, Timestamp};
+
+pub fn capture(path: &Path) -> Option<Posix> {
+    let metadata = std::fs::symlink_metadata(path).ok()?;
+    let mut posix = Posix {
+        mtime: metadata.modified().ok().map(Timestamp::from_system_time),
+        atime: metadata.accessed().ok().map(Timestamp::from_system_time),
+        xattrs: list_xattrs(path),
+        ..Default::default()
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        posix.mode = Some(metadata.mode() & 0o7777);
+        posix.uid = Some(metadata.uid());
+        posix.gid = Some(metadata.gid());
+    }
+    Some(posix)
+}
+
+// Sets what `posix` holds on `path`, which is a symlink itself when `symlink` is set.
+// Returns what couldn't be set, the rest is still applied.
+pub fn apply(path: &Path, posix: &Posix, symlink: bool) -> Vec<String> {
+    let mut errors = Vec::new();
+    // attributes first, read-only permissions would refuse them
+    for (name, value) in &posix.xattrs {
+        if let Err(e) = set_xattr(path, name, value) {
+            errors.push(format!("xattr {}: {}", name, e));
+        }
+    }
+    // the owner before the mode, a chown clears the setuid and setgid bits
+    if let Err(e) = set_owner(path, posix.uid, posix.gid) {
+        errors.push(format!("owner: {}", e));
+    }
+    // symlinks have no permissions of their own
+    if let (Some(mode), false) = (posix.mode, symlink) {
+        if let Err(e) = set_mode(path, mode) {
+            errors.push(format!("mode: {}", e));
+        }
+    }
+    // the times last, everything else counts as a change
+    if posix.mtime.is_some() || posix.atime.is_some() {
+        if let Err(e) = set_times(path, posix.atime, posix.mtime, symlink) {
+            errors.push(format!("times: {}", e));
+        }
+    }
+    errors
+}
+
+#[cfg(unix)]
+fn set_owner(path: &Path, uid: Option<u32>, gid: Option<u32>) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    // unchanged owners are skipped, only root may give files away
+    let current = std::fs::symlink_metadata(path)?;
+    let uid = uid.filter(|uid| *uid != current.uid());
+    let gid = gid.filter(|gid| *gid != current.gid());
+    match uid.is_some() || gid.is_some() {
+        true => std::os::unix::fs::lchown(path, uid, gid),
+        false => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_owner(_path: &Path, _uid: Option<u32>, _gid: Option<u32>) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn c_path(path: &Path) -> std::io::Result<std::ffi::CString> {
+    use std::os::unix::ffi::OsStrExt;
+    std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+}
+
+#[cfg(unix)]
+fn set_times(
+    path: &Path,
+    atime: Option<Timestamp>,
+    mtime: Option<Timestamp>,
+    symlink: bool,
+) -> std::io::Result<()> {
+    let spec = |time: Option<Timestamp>| match time {
+        Some(Timestamp(secs, nanos)) => libc::timespec {
+            tv_sec: secs as libc::time_t,
+            tv_nsec: nanos as _,
+        },
+        None => libc::timespec {
+            tv_sec: 0,
+            tv_nsec: libc::UTIME_OMIT,
+        },
+    };
+    let times = [spec(atime), spec(mtime)];
+    let flags = match symlink {
+        true => libc::AT_SYMLINK_NOFOLLOW,
+        false => 0,
+    };
+    let path = c_path(path)?;
+    match unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), times.as_ptr(), flags) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_times(
+    path: &Path,
+    atime: Option<Timestamp>,
+    mtime: Option<Timestamp>,
+    symlink: bool,
+) -> std::io::Result<()> {
+    if symlink {
+        return Ok(());
+    }
+    let mut times = std::fs::FileTimes::new();
+    if let Some(atime) = atime {
+        times = times.set_accessed(atime.to_system_time());
+    }
+    if let Some(mtime) = mtime {
+        times = times.set_modified(mtime.to_system_time());
+    }
+    std::fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_times(times)
+}
+
+// Attributes that can't be read are left out, like on a filesystem without them
+#[cfg(target_os = "linux")]
+fn list_xattrs(path: &Path) -> BTreeMap<String, Vec<u8>> {
+    let mut xattrs = BTreeMap::new();
+    let path = match c_path(path) {
+        Ok(path) => path,
+        Err(_) => return xattrs,
+    };
+    let names = match read_sized(|buf, len| unsafe {
+        libc::llistxattr(path.as_ptr(), buf as *mut libc::c_char, len)
+    }) {
+        Some(names) => names,
+        None => return xattrs,
+    };
+    for name in names
+        .split(|byte| *byte == 0)
+        .filter(|name| !name.is_empty())
+    {
+        let c_name = match std::ffi::CString::new(name) {
+            Ok(c_name) => c_name,
+            Err(_) => continue,
+        };
+        let value = read_sized(|buf, len| unsafe {
+            libc::lgetxattr(
+                path.as_ptr(),
+                c_name.as_ptr(),
+                buf as *mut libc::c_void,
+                len,
+            )
+        });
+        if let Some(value) = value {
+            xattrs.insert(String::from_utf8_lossy(name).to_string(), value);
+        }
+    }
+    xattrs
+}
+
+#[cfg(not(target_os = "linux"))]
+fn list_xattrs(_path: &Path) -> BTreeMap<String, Vec<u8>> {
+    BTreeMap::new()
+}
+
+// Calls `read` once for the size and once for the data, retrying when it grew in between
+#[cfg(target_os = "linux")]
+fn read_sized(read: impl Fn(*mut u8, usize) -> libc::ssize_t) -> Option<Vec<u8>> {
+    for _ in 0..3 {
+        let len = read(std::ptr::null_mut(), 0);
+        if len < 0 {
+            return None;
+        }
+        let mut buf = vec![0u8; len as usize];
+        let read_len = read(buf.as_mut_ptr(), buf.len());
+        if read_len >= 0 {
+            buf.truncate(read_len as usize);
+            return Some(buf);
+        }
+        if std::io::Error::last_os_error().raw_os_error() != Some(libc::ERANGE) {
+            return None;
+        }
+    }
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn set_xattr(path: &Path, name: &str, value: &[u8]) -> std::io::Result<()> {
+    let path = c_path(path)?;
+    let name = std::ffi::CString::new(name)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let result = unsafe {
+        libc::lsetxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr() as *const libc::c_void,
+            value.len(),
+            0,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_xattr(_path: &Path, _name: &str, _value: &[u8]) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "extended attributes are not supported here",
+    ))
+}
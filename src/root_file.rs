@@ -0,0 +1,297 @@
+/*
+   Keeps the root directory in a local file without ever leaving a torn one behind.
+   A new root is written to a temporary file, synced and renamed into place, and the previous roots
+   are kept as `<root_path>.1`, `<root_path>.2`, ... up to the configured number of generations.
+   Before a root is written, its changes are appended to a journal (`<root_path>.journal`). When the newest
+   root is unreadable, the newest readable generation is loaded and the journal is replayed on top of it.
+*/
+
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use rmp_serde::Deserializer;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    error::Error,
+    global::GlobalTrait,
+    inodes::{directory::Directory, metadata::Metadata, shards::Shards},
+    stored::Stored,
+};
+
+#[derive(Serialize, Deserialize)]
+struct RootFile {
+    #[serde(rename = "s")]
+    sequence: u64,
+    #[serde(rename = "r")]
+    root: Directory,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum Change {
+    #[serde(rename = "p")]
+    Put(String, Stored),
+    #[serde(rename = "r")]
+    Remove(String),
+    #[serde(rename = "m")]
+    Metadata(Metadata),
+    #[serde(rename = "h")]
+    Shards(Option<Shards>), // entries in shards change with the nodes they are in, see shards.rs
+}
+
+// One entry per save, so a torn entry never leaves half of a save applied
+#[derive(Serialize, Deserialize, Debug)]
+struct JournalEntry {
+    #[serde(rename = "s")]
+    sequence: u64, // the root these changes lead to
+    #[serde(rename = "c")]
+    changes: Vec<Change>,
+}
+
+pub const fn default_generations() -> usize {
+    3
+}
+
+fn generation_path(root_path: &str, generation: usize) -> PathBuf {
+    match generation {
+        0 => PathBuf::from(root_path),
+        n => PathBuf::from(format!("{}.{}", root_path, n)),
+    }
+}
+
+fn journal_path(root_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.journal", root_path))
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let suffix = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(8)
+        .map(char::from)
+        .collect::<String>();
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", suffix));
+    path.with_file_name(name)
+}
+
+// Writes `data` next to `path`, syncs it and renames it over `path`
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<(), Error> {
+    let temp = temp_path(path);
+    let written = File::create(&temp).and_then(|mut file| {
+        file.write_all(data)?;
+        file.sync_all()
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(Error::io(format!("Error writing {}", temp.display()), e));
+    }
+    fs::rename(&temp, path)
+        .map_err(|e| Error::io(format!("Error replacing {}", path.display()), e))?;
+    sync_parent(path);
+    Ok(())
+}
+
+// Not every platform can open directories, so this is best effort
+fn sync_parent(path: &Path) {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(parent) {
+        let _ = dir.sync_all();
+    }
+}
+
+// Roots written before generations existed are a bare Directory, those count as sequence 0
+fn read_root(path: &Path) -> Result<Option<(u64, Directory)>, Error> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::io(format!("Error reading {}", path.display()), e)),
+    };
+    if let Ok(file) = RootFile::deserialize(&mut Deserializer::new(&data[..])) {
+        return Ok(Some((file.sequence, file.root)));
+    }
+    Directory::deserialize(&mut Deserializer::new(&data[..]))
+        .map(|root| Some((0, root)))
+        .map_err(|e| Error::deserialize(format!("Error parsing {}", path.display()), e))
+}
+
+// Reads entries until the end of the journal, a torn entry from a crash mid-append ends it early.
+// Also returns how many bytes the readable entries take up.
+fn read_journal(root_path: &str) -> (Vec<JournalEntry>, usize) {
+    let data = fs::read(journal_path(root_path)).unwrap_or_default();
+    let mut rest = &data[..];
+    let mut entries = Vec::new();
+    loop {
+        let mut deserializer = Deserializer::new(rest);
+        match JournalEntry::deserialize(&mut deserializer) {
+            Ok(entry) => {
+                rest = *deserializer.get_ref();
+                entries.push(entry);
+            }
+            Err(_) => break,
+        }
+    }
+    (entries, data.len() - rest.len())
+}
+
+fn apply(root: &mut Directory, change: Change) {
+    match change {
+        Change::Put(name, stored) => {
+            root.children_mut().insert(name, stored);
+        }
+        Change::Remove(name) => {
+            root.children_mut().remove(&name);
+        }
+        Change::Metadata(metadata) => root.metadata = metadata,
+        Change::Shards(shards) => root.set_shards(shards),
+    }
+}
+
+// The changes that turn `old` into `new`, metadata last so it isn't touched up by the puts
+pub fn diff(old: &Directory, new: &Directory) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for name in old.children().keys() {
+        if !new.children().contains_key(name) {
+            changes.push(Change::Remove(name.clone()));
+        }
+    }
+    for (name, stored) in new.children() {
+        if old.children().get(name) != Some(stored) {
+            changes.push(Change::Put(name.clone(), stored.clone()));
+        }
+    }
+    if old.shards() != new.shards() {
+        changes.push(Change::Shards(new.shards().cloned()));
+    }
+    if old.metadata != new.metadata {
+        changes.push(Change::Metadata(new.metadata.clone()));
+    }
+    changes
+}
+
+// Replays our changes since `base` on top of `theirs`, where both sides changed an entry ours wins.
+// Sharded entries are merged the same way, see Shards::merge.
+pub async fn merge<U: GlobalTrait + Send + Sync>(
+    global: Arc<U>,
+    base: &Directory,
+    ours: &Directory,
+    theirs: &Directory,
+) -> Result<Directory, Error> {
+    let mut merged = theirs.clone();
+    for change in diff(base, ours) {
+        let change = match (change, base.shards(), theirs.shards()) {
+            (Change::Shards(Some(ours)), Some(base), Some(theirs)) => Change::Shards(Some(
+                Shards::merge(global.clone(), base, &ours, theirs).await?,
+            )),
+            (change, _, _) => change,
+        };
+        apply(&mut merged, change);
+    }
+    Ok(merged)
+}
+
+// Loads the newest readable root with the journal replayed on top, and the sequence it is at
+pub fn load(root_path: &str, generations: usize) -> Option<(u64, Directory)> {
+    for generation in 0..=generations {
+        let path = generation_path(root_path, generation);
+        let (mut sequence, mut root) = match read_root(&path) {
+            Ok(Some(root)) => root,
+            Ok(None) => continue,
+            Err(e) => {
+                println!("failed to read root {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let mut replayed = 0;
+        for entry in read_journal(root_path).0 {
+            if entry.sequence <= sequence {
+                continue; // already part of this root
+            }
+            if entry.sequence > sequence + 1 {
+                break; // the entries in between were compacted away
+            }
+            sequence = entry.sequence;
+            for change in entry.changes {
+                apply(&mut root, change);
+            }
+            replayed += 1;
+        }
+        if generation > 0 || replayed > 0 {
+            println!(
+                "recovered root from {} with {} journal entries",
+                path.display(),
+                replayed
+            );
+        }
+        return Some((sequence, root));
+    }
+    None
+}
+
+pub fn save(root_path: &str, generations: usize, root: &Directory) -> Result<(), Error> {
+    let (previous_sequence, previous) =
+        load(root_path, generations).unwrap_or((0, Directory::new()));
+    let sequence = previous_sequence + 1;
+
+    // 1. journal the changes, so they survive even if writing the root doesn't.
+    // Saves without changes are journaled as well, replaying stops at a missing sequence.
+    let changes = diff(&previous, root);
+    let data = Stored::serialize(&JournalEntry { sequence, changes })?;
+    let mut journal = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal_path(root_path))
+        .map_err(|e| Error::io("Error opening journal", e))?;
+    // a torn entry would hide everything appended after it
+    let (_, readable) = read_journal(root_path);
+    journal
+        .set_len(readable as u64)
+        .and_then(|_| journal.write_all(&data))
+        .and_then(|_| journal.sync_all())
+        .map_err(|e| Error::io("Error writing journal", e))?;
+
+    // 2. shift the generations, the current root is linked so root_path never goes missing
+    for generation in (1..generations).rev() {
+        match fs::rename(
+            generation_path(root_path, generation),
+            generation_path(root_path, generation + 1),
+        ) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                return Err(Error::io("Error rotating roots", e))
+            }
+            _ => (),
+        }
+    }
+    if generations > 0 && Path::new(root_path).exists() {
+        let first = generation_path(root_path, 1);
+        let _ = fs::remove_file(&first);
+        if fs::hard_link(root_path, &first).is_err() {
+            fs::copy(root_path, &first).map_err(|e| Error::io("Error keeping old root", e))?;
+        }
+    }
+
+    // 3. replace the root
+    let file = RootFile {
+        sequence,
+        root: root.clone(),
+    };
+    write_atomic(Path::new(root_path), &Stored::serialize(&file)?)?;
+
+    // 4. forget journal entries that even the oldest generation already contains
+    let oldest = sequence.saturating_sub(generations as u64);
+    let (entries, _) = read_journal(root_path);
+    if entries.iter().any(|entry| entry.sequence <= oldest) {
+        let mut data = Vec::new();
+        for entry in entries.into_iter().filter(|entry| entry.sequence > oldest) {
+            data.extend(Stored::serialize(&entry)?);
+        }
+        write_atomic(&journal_path(root_path), &data)?;
+    }
+    Ok(())
+}
@@ -1,10 +1,16 @@
 use serde::Deserialize;
+use std::time::Duration;
 
-use rusoto_core::{ByteStream, HttpClient, Region, RusotoError};
-use rusoto_credential::StaticProvider;
+use rusoto_core::{HttpClient, Region, RusotoError};
+use rusoto_credential::{AwsCredentials, StaticProvider};
 use rusoto_s3::{
-    GetObjectRequest, ListObjectsV2Request, PutObjectOutput, PutObjectRequest, S3Client, S3,
+    util::{PreSignedRequest, PreSignedRequestOption},
+    GetObjectError, GetObjectRequest, HeadObjectError, HeadObjectRequest, PutObjectRequest,
+    S3Client, S3,
 };
+use tokio::io::AsyncReadExt;
+
+use crate::error::Error;
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "type")]
@@ -16,100 +22,138 @@ pub struct S3Type {
     region: String,
 }
 
-pub async fn list_files_in_bucket(
-    s3: &S3Type,
-) -> Result<Vec<String>, rusoto_core::RusotoError<rusoto_s3::ListObjectsV2Error>> {
-    let access_key_id = &s3.access_key_id;
-    let secret_access_key = &s3.secret_access_key;
-    let endpoint = &s3.endpoint;
-    let bucket_name = &s3.bucket_name;
-    let provider = StaticProvider::new_minimal(access_key_id.into(), secret_access_key.into());
-    let region = Region::Custom {
+pub enum PutOutcome {
+    Written(String), // the new etag
+    Conflict,        // the object isn't the one we expected anymore
+}
+
+fn region(s3: &S3Type) -> Region {
+    Region::Custom {
         name: s3.region.to_owned(),
-        endpoint: endpoint.to_owned(),
-    };
+        endpoint: s3.endpoint.to_owned(),
+    }
+}
 
-    let client = S3Client::new_with(
+fn client(s3: &S3Type) -> S3Client {
+    let provider =
+        StaticProvider::new_minimal(s3.access_key_id.clone(), s3.secret_access_key.clone());
+    S3Client::new_with(
         HttpClient::new().expect("Failed to create HTTP client"),
         provider,
-        region,
-    );
-
-    let request = ListObjectsV2Request {
-        bucket: bucket_name.to_string(),
-        ..Default::default()
-    };
+        region(s3),
+    )
+}
 
-    Ok(client
-        .list_objects_v2(request)
-        .await?
-        .contents
-        .unwrap_or_default()
-        .into_iter()
-        .filter_map(|object| object.key)
-        .collect())
+// Some providers answer a missing key with a bare 404 instead of NoSuchKey
+fn is_not_found<E>(err: &RusotoError<E>) -> bool {
+    matches!(err, RusotoError::Unknown(response) if response.status.as_u16() == 404)
 }
 
+// The object and its etag, None if the key doesn't exist
 pub async fn download_file(
     s3: &S3Type,
     object_key: &str,
-) -> Result<ByteStream, rusoto_core::RusotoError<rusoto_s3::GetObjectError>> {
-    let access_key_id = &s3.access_key_id;
-    let secret_access_key = &s3.secret_access_key;
-    let endpoint = &s3.endpoint;
-    let bucket_name = &s3.bucket_name;
-    let provider = StaticProvider::new_minimal(access_key_id.into(), secret_access_key.into());
-    let region = Region::Custom {
-        name: s3.region.to_owned(),
-        endpoint: endpoint.to_owned(),
-    };
-
-    let client = S3Client::new_with(
-        HttpClient::new().expect("Failed to create HTTP client"),
-        provider,
-        region,
-    );
-
+) -> Result<Option<(Vec<u8>, String)>, Error> {
     let request = GetObjectRequest {
-        bucket: bucket_name.to_string(),
+        bucket: s3.bucket_name.to_string(),
         key: object_key.to_string(),
         ..Default::default()
     };
+    let output = match client(s3).get_object(request).await {
+        Ok(output) => output,
+        Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => return Ok(None),
+        Err(err) if is_not_found(&err) => return Ok(None),
+        Err(err) => return Err(Error::Unavailable(err.to_string())),
+    };
 
-    let output = client.get_object(request).await?;
+    let mut data = Vec::new();
+    output
+        .body
+        .ok_or(Error::Unavailable(
+            "can't download file, GetObjectRequest body missing".to_string(),
+        ))?
+        .into_async_read()
+        .read_to_end(&mut data)
+        .await
+        .map_err(|e| Error::io("Error reading object", e))?;
+    Ok(Some((data, output.e_tag.unwrap_or_default())))
+}
 
-    output.body.ok_or(RusotoError::Validation(
-        "can't download file, GetObjectRequest body missing".to_string(),
-    ))
+pub async fn head_etag(s3: &S3Type, object_key: &str) -> Result<Option<String>, Error> {
+    let request = HeadObjectRequest {
+        bucket: s3.bucket_name.to_string(),
+        key: object_key.to_string(),
+        ..Default::default()
+    };
+    match client(s3).head_object(request).await {
+        Ok(output) => Ok(Some(output.e_tag.unwrap_or_default())),
+        Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => Ok(None),
+        Err(err) if is_not_found(&err) => Ok(None),
+        Err(err) => Err(Error::Unavailable(err.to_string())),
+    }
 }
 
-// Fonction pour uploader un fichier vers le bucket
-pub async fn upload_file(
+// Uploads the object only if its etag is still `expected`, or if it doesn't exist when `expected` is None.
+// rusoto can't send If-Match, so the put goes through a presigned url. Providers that ignore the
+// condition are still covered by the etag check before it, only a write in between slips through.
+pub async fn upload_file_if(
     s3: &S3Type,
     object_key: &str,
-    file: ByteStream,
-) -> Result<PutObjectOutput, rusoto_core::RusotoError<rusoto_s3::PutObjectError>> {
-    let access_key_id = &s3.access_key_id;
-    let secret_access_key = &s3.secret_access_key;
-    let endpoint = &s3.endpoint;
-    let bucket_name = &s3.bucket_name;
-    let provider = StaticProvider::new_minimal(access_key_id.into(), secret_access_key.into());
-    let region = Region::Custom {
-        name: s3.region.to_owned(),
-        endpoint: endpoint.to_owned(),
-    };
-    let client = S3Client::new_with(
-        HttpClient::new().expect("Failed to create HTTP client"),
-        provider,
-        region,
-    );
+    data: Vec<u8>,
+    expected: Option<&str>,
+) -> Result<PutOutcome, Error> {
+    if head_etag(s3, object_key).await?.as_deref() != expected {
+        return Ok(PutOutcome::Conflict);
+    }
 
     let request = PutObjectRequest {
-        bucket: bucket_name.to_string(),
+        bucket: s3.bucket_name.to_string(),
         key: object_key.to_string(),
-        body: Some(file), // Replace this with your file content
         ..Default::default()
     };
+    let credentials = AwsCredentials::new(
+        s3.access_key_id.clone(),
+        s3.secret_access_key.clone(),
+        None,
+        None,
+    );
+    let url = request.get_presigned_url(
+        &region(s3),
+        &credentials,
+        &PreSignedRequestOption {
+            expires_in: Duration::from_secs(300),
+        },
+    );
+    let request = reqwest::Client::new().put(url).body(data);
+    let request = match expected {
+        Some(etag) => request.header("If-Match", etag),
+        None => request.header("If-None-Match", "*"),
+    };
+    let response = request
+        .send()
+        .await
+        .map_err(|e| Error::http("Error sending request", e))?;
 
-    client.put_object(request).await
+    match response.status().as_u16() {
+        // 409 is what S3 answers when another conditional write to the key is in flight
+        412 | 409 => Ok(PutOutcome::Conflict),
+        status if !(200..300).contains(&status) => Err(Error::status(
+            response.status(),
+            format!(
+                "Error uploading object: {} {}",
+                status,
+                response.text().await.unwrap_or_default()
+            ),
+        )),
+        _ => match response
+            .headers()
+            .get("ETag")
+            .and_then(|etag| etag.to_str().ok())
+        {
+            Some(etag) => Ok(PutOutcome::Written(etag.to_string())),
+            None => Ok(PutOutcome::Written(
+                head_etag(s3, object_key).await?.unwrap_or_default(),
+            )),
+        },
+    }
 }
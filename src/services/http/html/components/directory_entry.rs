@@ -7,6 +7,8 @@ use crate::stored::Stored;
 
 #[derive(Properties)]
 pub struct DirectoryEntryProps {
+    pub base: String,
+    pub readonly: bool,
     pub path: Vec<String>,
     pub data: Arc<ServerData>,
     pub name: String,
@@ -15,14 +17,18 @@ pub struct DirectoryEntryProps {
 
 impl PartialEq for DirectoryEntryProps {
     fn eq(&self, other: &Self) -> bool {
-        self.path == other.path && self.name == other.name && self.inode == other.inode
+        self.base == other.base
+            && self.path == other.path
+            && self.name == other.name
+            && self.inode == other.inode
     }
 }
 
 #[function_component]
 pub fn DirectoryEntry(props: &DirectoryEntryProps) -> Html {
     let url = format!(
-        "/files/{}/{}${}",
+        "{}/{}/{}${}",
+        props.base,
         props.path.join("/"),
         props.inode.as_url(),
         props.name.replace('$', "%24")
@@ -31,7 +37,7 @@ pub fn DirectoryEntry(props: &DirectoryEntryProps) -> Html {
     html! {
         <li class="entry inode">
             <a href={ url.clone() }>{ &props.name }</a>
-            if !props.data.config.readonly {
+            if !props.readonly {
                 <div class="edit">
                     <button class="hamburger">{"☰"}</button>
                     <nav class="menu">
@@ -43,11 +49,17 @@ pub fn DirectoryEntry(props: &DirectoryEntryProps) -> Html {
                                 </form>
                             </li>
                             <li class="cut-option">
-                                <form action={ url } method="POST" class="cut" enctype="multipart/form-data">
+                                <form action={ url.clone() } method="POST" class="cut" enctype="multipart/form-data">
                                     <input type="hidden" name="request" value="cut" />
                                     <input type="submit" value="Cut" />
                                 </form>
                             </li>
+                            <li class="download-option">
+                                <a href={ format!("{}?download", url) }>{ "Download" }</a>
+                            </li>
+                            <li class="versions-option">
+                                <a href={ format!("{}?versions", url) }>{ "Versions" }</a>
+                            </li>
                         </ul>
                     </nav>
                 </div>
@@ -40,6 +40,10 @@ pub fn Layout(props: &LayoutProps) -> Html {
             <body>
                 <header>
                     <span>{ "chunkdrive" }</span>
+                    if props.data.config.see_root {
+                        <a href="/snapshots/">{ "snapshots" }</a>
+                        <a href="/trash/">{ "trash" }</a>
+                    }
                     <input type="checkbox" id="theme-switcher" />
                 </header>
                 <section class="content">
@@ -2,71 +2,84 @@ use std::sync::Arc;
 use yew::function_component;
 use yew::prelude::*;
 
-use crate::inodes::directory::Directory;
 use crate::services::http::html::components::directory_entry::DirectoryEntry;
 use crate::services::http::html::components::layout::Layout;
-use crate::services::http::service::ServerData;
+use crate::services::http::service::{entry_segment, split_segment, ServerData};
+use crate::stored::Stored;
 
 #[derive(Properties)]
 pub struct DirectoryIndexProps {
+    pub base: String, // "/files", or "/snapshots/<name>" when browsing a snapshot
+    pub readonly: bool,
     pub path: Vec<String>,
     pub data: Arc<ServerData>,
-    pub dir: Directory,
+    pub entries: Vec<(String, Stored)>, // one page of the directory
+    pub after: Option<String>,          // the entry this page starts after
+    pub next: Option<String>,           // the entry the next page starts after
     pub cut_inode: Option<String>,
 }
 
 impl PartialEq for DirectoryIndexProps {
     fn eq(&self, other: &Self) -> bool {
-        self.path == other.path
+        self.base == other.base && self.path == other.path && self.after == other.after
     }
 }
 
 #[function_component]
 pub fn DirectoryIndex(props: &DirectoryIndexProps) -> Html {
-    // for each part of the path where <a>$<b>$<c> strip $<c> if it exists
+    // the names stay in the path, changes look each directory up by them, see service.rs open_path
     let path = props
         .path
         .iter()
-        .map(|part| {
-            let parts = part.split('$').collect::<Vec<&str>>();
-            if parts.len() <= 2 {
-                return part.clone();
-            }
-            format!("{}${}", parts[0], parts[1])
+        .map(|part| match split_segment(part) {
+            Ok((stored, Some(name))) => entry_segment(&stored, &name),
+            _ => part.clone(),
         })
         .collect::<Vec<String>>();
+    let here = format!("{}/{}", props.base, path.join("/"));
 
     html! {
         <Layout data={props.data.clone()}>
             <ul class="index">
                 if path.len() > 1 {
                     <li class="entry back">
-                        <a href={ format!("/files/{}", path[..path.len()-1].join("/")) }>{ ".." }</a>
+                        <a href={ format!("{}/{}", props.base, path[..path.len()-1].join("/")) }>{ ".." }</a>
                     </li>
                 } else if path.len() == 1 && props.data.config.see_root {
                     <li class="entry back">
-                        <a href={"/files/"} >{ ".." }</a>
+                        <a href={ format!("{}/", props.base) } >{ ".." }</a>
                     </li>
                 }
-                { props.dir.list_tuples().iter().map(|(name, inode)| {
+                if props.after.is_some() {
+                    <li class="entry page">
+                        <a href={ here.clone() }>{ "First page" }</a>
+                    </li>
+                }
+                { props.entries.iter().map(|(name, inode)| {
                     html! {
-                        <DirectoryEntry name={name.clone()} inode={inode.clone()} data={props.data.clone()} path={path.clone()} />
+                        <DirectoryEntry name={name.clone()} inode={inode.clone()} data={props.data.clone()} base={props.base.clone()} readonly={props.readonly} path={path.clone()} />
                     }
                 }).collect::<Html>()}
-                if !props.data.config.readonly {
+                if let Some(next) = &props.next {
+                    <li class="entry page">
+                        <a href={ format!("{}?after={}", here, urlencoding::encode(next)) }>{ "Next page" }</a>
+                    </li>
+                }
+                if !props.readonly {
                     <div class="create-entries">
                         <li class="entry create create-file">
                             <span>{"Upload file"}</span>
                             <button class="create-btn">{"↑"}</button>
-                            <form action={ format!("/files/{}/", path.join("/")) } method="POST" enctype="multipart/form-data" class="create-form file-upload">
+                            <form action={ format!("{}/{}/", props.base, path.join("/")) } method="POST" enctype="multipart/form-data" class="create-form file-upload">
                                 <input type="file" name="file" />
+                                <label><input type="checkbox" name="overwrite" value="on" />{"Overwrite"}</label>
                                 <input type="submit" value="Upload file" />
                             </form>
                         </li>
                         <li class="entry create create-directory">
                             <span>{"Create directory"}</span>
                             <button class="create-btn">{"+"}</button>
-                            <form action={ format!("/files/{}/", path.join("/")) } method="POST" enctype="multipart/form-data" class="create-form directory-create">
+                            <form action={ format!("{}/{}/", props.base, path.join("/")) } method="POST" enctype="multipart/form-data" class="create-form directory-create">
                                 <input type="text" name="directory_name" placeholder="Directory name" />
                                 <input type="submit" value="Create directory" />
                             </form>
@@ -75,7 +88,7 @@ pub fn DirectoryIndex(props: &DirectoryIndexProps) -> Html {
                             <li class="entry create paste">
                                 <span>{"Paste"}</span>
                                 <button class="create-btn">{"V"}</button>
-                                <form action={ format!("/files/{}/", path.join("/")) } method="POST" enctype="multipart/form-data" class="create-form paste-inode">
+                                <form action={ format!("{}/{}/", props.base, path.join("/")) } method="POST" enctype="multipart/form-data" class="create-form paste-inode">
                                     <input type="text" name="paste_name" placeholder="Paste as" />
                                     <input type="submit" value="Paste" />
                                 </form>
@@ -1,2 +1,5 @@
 pub mod directory_index;
 pub mod error_page;
+pub mod snapshot_index;
+pub mod trash_index;
+pub mod version_index;
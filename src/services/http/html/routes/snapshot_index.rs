@@ -0,0 +1,46 @@
+use std::sync::Arc;
+use yew::function_component;
+use yew::prelude::*;
+
+use crate::services::http::html::components::layout::Layout;
+use crate::services::http::service::ServerData;
+use crate::snapshots::Snapshot;
+
+#[derive(Properties)]
+pub struct SnapshotIndexProps {
+    pub data: Arc<ServerData>,
+    pub snapshots: Vec<Snapshot>,
+}
+
+impl PartialEq for SnapshotIndexProps {
+    fn eq(&self, other: &Self) -> bool {
+        self.snapshots.len() == other.snapshots.len()
+            && self
+                .snapshots
+                .iter()
+                .zip(other.snapshots.iter())
+                .all(|(a, b)| a.name == b.name)
+    }
+}
+
+#[function_component]
+pub fn SnapshotIndex(props: &SnapshotIndexProps) -> Html {
+    html! {
+        <Layout data={props.data.clone()}>
+            <ul class="index">
+                <li class="entry back">
+                    <a href={"/files/"}>{ ".." }</a>
+                </li>
+                { props.snapshots.iter().map(|snapshot| {
+                    html! {
+                        <li class="entry inode">
+                            <a href={ format!("/snapshots/{}/", urlencoding::encode(&snapshot.name)) }>
+                                { format!("{} ({})", snapshot.name, snapshot.human_created()) }
+                            </a>
+                        </li>
+                    }
+                }).collect::<Html>()}
+            </ul>
+        </Layout>
+    }
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+use yew::function_component;
+use yew::prelude::*;
+
+use crate::services::http::html::components::layout::Layout;
+use crate::services::http::service::ServerData;
+use crate::trash::TrashEntry;
+
+#[derive(Properties)]
+pub struct TrashIndexProps {
+    pub data: Arc<ServerData>,
+    pub entries: Vec<TrashEntry>,
+}
+
+impl PartialEq for TrashIndexProps {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries.len() == other.entries.len()
+            && self
+                .entries
+                .iter()
+                .zip(other.entries.iter())
+                .all(|(a, b)| a.id == b.id)
+    }
+}
+
+#[function_component]
+pub fn TrashIndex(props: &TrashIndexProps) -> Html {
+    html! {
+        <Layout data={props.data.clone()}>
+            <ul class="index">
+                <li class="entry back">
+                    <a href={"/files/"}>{ ".." }</a>
+                </li>
+                { props.entries.iter().map(|entry| {
+                    html! {
+                        <li class="entry inode">
+                            <span>{ format!("{} (deleted {})", entry.original_path(), entry.human_deleted()) }</span>
+                            if !props.data.config.readonly {
+                                <div class="edit">
+                                    <button class="hamburger">{"☰"}</button>
+                                    <nav class="menu">
+                                        <ul>
+                                            <li class="versions-option">
+                                                <form action="/trash/" method="POST" class="restore" enctype="multipart/form-data">
+                                                    <input type="hidden" name="id" value={ entry.id.to_string() } />
+                                                    <input type="submit" value="Restore" />
+                                                </form>
+                                            </li>
+                                        </ul>
+                                    </nav>
+                                </div>
+                            }
+                        </li>
+                    }
+                }).collect::<Html>()}
+            </ul>
+        </Layout>
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+use yew::function_component;
+use yew::prelude::*;
+
+use crate::inodes::metadata::Metadata;
+use crate::services::http::html::components::layout::Layout;
+use crate::services::http::service::ServerData;
+
+#[derive(Properties)]
+pub struct VersionIndexProps {
+    pub data: Arc<ServerData>,
+    pub readonly: bool,
+    pub back: String, // the directory the file is in
+    pub url: String,  // the file itself
+    pub name: String,
+    pub current: Metadata,
+    pub versions: Vec<(Metadata, String)>, // newest first, with when they were replaced
+}
+
+impl PartialEq for VersionIndexProps {
+    fn eq(&self, other: &Self) -> bool {
+        self.url == other.url && self.versions.len() == other.versions.len()
+    }
+}
+
+#[function_component]
+pub fn VersionIndex(props: &VersionIndexProps) -> Html {
+    html! {
+        <Layout data={props.data.clone()}>
+            <ul class="index">
+                <li class="entry back">
+                    <a href={ props.back.clone() }>{ ".." }</a>
+                </li>
+                <li class="entry inode">
+                    <a href={ props.url.clone() }>
+                        { format!("{} (current, {}, {})", props.name, props.current.human_modified(), props.current.size.human()) }
+                    </a>
+                </li>
+                { props.versions.iter().enumerate().map(|(index, (metadata, replaced))| {
+                    let number = index + 1;
+                    html! {
+                        <li class="entry inode">
+                            <a href={ format!("{}?version={}", props.url, number) }>
+                                { format!("version {} ({}, {}, replaced {})", number, metadata.human_modified(), metadata.size.human(), replaced) }
+                            </a>
+                            if !props.readonly {
+                                <div class="edit">
+                                    <button class="hamburger">{"☰"}</button>
+                                    <nav class="menu">
+                                        <ul>
+                                            <li class="versions-option">
+                                                <form action={ props.url.clone() } method="POST" class="restore" enctype="multipart/form-data">
+                                                    <input type="hidden" name="request" value="restore" />
+                                                    <input type="hidden" name="version" value={ number.to_string() } />
+                                                    <input type="submit" value="Restore" />
+                                                </form>
+                                            </li>
+                                        </ul>
+                                    </nav>
+                                </div>
+                            }
+                        </li>
+                    }
+                }).collect::<Html>()}
+            </ul>
+        </Layout>
+    }
+}
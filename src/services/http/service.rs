@@ -1,5 +1,12 @@
 use actix_multipart::form::{bytes::Bytes, text::Text, MultipartForm};
-use actix_web::{cookie, route, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    cookie,
+    http::{
+        header::{Charset, ContentDisposition, DispositionParam, DispositionType, ExtendedValue},
+        StatusCode,
+    },
+    route, web, App, HttpRequest, HttpResponse, HttpServer, Responder,
+};
 use futures::StreamExt;
 use serde::Deserialize;
 use std::sync::Arc;
@@ -7,11 +14,11 @@ use tokio::io::AsyncReadExt;
 use yew::ServerRenderer;
 
 use crate::{
-    global::AsyncGlobal,
+    error::{Context, Error},
+    global::{AsyncGlobal, GlobalTrait},
     inodes::{
-        directory::Directory,
-        file::File,
-        inode::{Inode, InodeType},
+        directory::Directory, file::File, inode::InodeType, metadata::Size, mime,
+        path::DirectoryPath, symlink::Symlink,
     },
     services::service::Service,
     stored::Stored,
@@ -20,6 +27,9 @@ use crate::{
 use super::html::routes::{
     directory_index::{DirectoryIndex, DirectoryIndexProps},
     error_page::{ErrorPage, ErrorPageProps},
+    snapshot_index::{SnapshotIndex, SnapshotIndexProps},
+    trash_index::{TrashIndex, TrashIndexProps},
+    version_index::{VersionIndex, VersionIndexProps},
 };
 
 #[derive(Debug, Deserialize, Clone)]
@@ -45,6 +55,20 @@ pub struct HttpService {
 
     #[serde(default = "fn_script")]
     pub(crate) script_path: String,
+
+    #[serde(default = "fn_page_size")]
+    pub(crate) page_size: usize, // entries per directory page
+}
+
+// `?versions` lists the old versions of a file, `?version=<n>` downloads one.
+// Files open in the browser when it can show them, `?download` saves them instead.
+// Directories list a page at a time, `?after=<name>` continues after that entry.
+#[derive(Debug, Deserialize, Default)]
+pub struct FileQuery {
+    versions: Option<String>,
+    version: Option<usize>,
+    download: Option<String>,
+    after: Option<String>,
 }
 
 #[derive(Debug)]
@@ -68,6 +92,9 @@ const fn fn_true() -> bool {
 fn fn_style() -> String {
     "./style.css".to_string()
 }
+const fn fn_page_size() -> usize {
+    500
+}
 fn fn_script() -> String {
     "./script.js".to_string()
 }
@@ -85,7 +112,7 @@ impl Service for HttpService {
     }
 }
 
-fn run_blocking(data: Arc<ServerData>) -> Result<(), String> {
+fn run_blocking(data: Arc<ServerData>) -> Result<(), Error> {
     println!(
         "Starting HTTP service on http://{}:{}",
         data.config.address, data.config.port
@@ -101,29 +128,56 @@ fn run_blocking(data: Arc<ServerData>) -> Result<(), String> {
                 .service(redirect)
                 .service(get)
                 .service(post)
+                .service(snapshots)
+                .service(snapshot)
+                .service(trash)
+                .service(untrash)
         })
         .bind(format!("{}:{}", data.config.address, data.config.port))
-        .map_err(|e| format!("Failed to bind to port: {}", e))?
+        .map_err(|e| Error::io("Failed to bind to port", e))?
         .run()
         .await
-        .map_err(|e| format!("Failed to run server: {}", e))
+        .map_err(|e| Error::io("Failed to run server", e))
     })?;
 
     Ok(())
 }
 
-fn get_stored(path: &Vec<String>) -> Result<Stored, String> {
-    let entry = path.last().ok_or("Invalid path")?;
-    let parts = entry.split('$').collect::<Vec<&str>>();
-    let (bucket, descriptor) = match parts.len() {
-        2 => (parts[0].to_string(), parts[1].to_string()),
-        3 => (parts[0].to_string(), parts[1].to_string()),
-        _ => return Err("Invalid path".to_string()),
-    };
-    Stored::from_url(&bucket, &descriptor)
+// Entries are linked as <bucket>$<descriptor>$<name>, with the name they have in their directory.
+// Links from before every segment had a name are only <bucket>$<descriptor>.
+pub fn split_segment(segment: &str) -> Result<(Stored, Option<String>), Error> {
+    let parts = segment.splitn(3, '$').collect::<Vec<&str>>();
+    if parts.len() < 2 {
+        return Err(Error::Invalid("Invalid path".to_string()));
+    }
+    // actix already decoded the path, so the name is as it is
+    let name = parts.get(2).map(|name| name.to_string());
+    Ok((Stored::from_url(parts[0], parts[1])?, name))
+}
+
+// The entry the last segment of `path` links to, and its name
+fn split_entry(path: &[String]) -> Result<(Stored, String), Error> {
+    let segment = path
+        .last()
+        .ok_or(Error::Invalid("Invalid path".to_string()))?;
+    match split_segment(segment)? {
+        (stored, Some(name)) => Ok((stored, name)),
+        (_, None) => Err(Error::Invalid("Invalid path".to_string())),
+    }
 }
 
-async fn get_inode(data: Arc<ServerData>, path: &Vec<String>) -> Result<InodeType, String> {
+pub fn entry_segment(stored: &Stored, name: &str) -> String {
+    format!("{}${}", stored.as_url(), urlencoding::encode(name))
+}
+
+fn get_stored(path: &[String]) -> Result<Stored, Error> {
+    let entry = path
+        .last()
+        .ok_or(Error::Invalid("Invalid path".to_string()))?;
+    Ok(split_segment(entry)?.0)
+}
+
+async fn get_inode(data: Arc<ServerData>, path: &[String]) -> Result<InodeType, Error> {
     let stored = get_stored(path)?;
 
     let inode = stored
@@ -133,17 +187,146 @@ async fn get_inode(data: Arc<ServerData>, path: &Vec<String>) -> Result<InodeTyp
     Ok(inode)
 }
 
+// Mutations start at the root, or at the first directory of the path when the root isn't visible.
+// That directory is what shared links point to, so it is written in place to keep them working.
+fn top_stored(arc: &Arc<ServerData>, path: &[String]) -> Result<Option<Stored>, Error> {
+    match arc.config.see_root || path.is_empty() {
+        true => Ok(None),
+        false => get_stored(&path[..1]).map(Some),
+    }
+}
+
+async fn read_directory(
+    arc: &Arc<ServerData>,
+    stored: &Option<Stored>,
+) -> Result<Directory, Error> {
+    match stored {
+        Some(stored) => match stored
+            .get::<InodeType, AsyncGlobal>(arc.global.clone())
+            .await?
+        {
+            InodeType::Directory(dir) => Ok(dir),
+            _ => Err(Error::Invalid("Path is not a directory".to_string())),
+        },
+        None => arc.global.get_root().await,
+    }
+}
+
+// Every mutation rewrites its path up to the top, so they all hold the root's lock
+// (AsyncGlobal::lock_directory) from opening the path until commit_path
+async fn open_path(
+    arc: &Arc<ServerData>,
+    path: &[String],
+) -> Result<(Option<Stored>, DirectoryPath), Error> {
+    let top = top_stored(arc, path)?;
+    if let Some(stored) = &top {
+        // it is written in place, which would change the snapshots using it
+        if arc.global.is_pinned(stored) {
+            return Err(Error::Conflict(
+                "This directory is part of a snapshot, change it through the root instead"
+                    .to_string(),
+            ));
+        }
+    }
+    let below = match top {
+        Some(_) => &path[1..],
+        None => path,
+    };
+    let mut directory_path = DirectoryPath::new(read_directory(arc, &top).await?);
+    let moved = || {
+        Error::Conflict(
+            "Directory not found, it may have changed since the page was loaded".to_string(),
+        )
+    };
+    for part in below {
+        let (stored, name) = split_segment(part)?;
+        let name = match name {
+            Some(name) => name,
+            // an old link, the directory has to be searched for it
+            None => directory_path
+                .directory()
+                .name_of(arc.global.clone(), &stored)
+                .await?
+                .ok_or_else(moved)?,
+        };
+        directory_path
+            .enter(arc.global.clone(), &name)
+            .await
+            .map_err(|e| match e.is_not_found() {
+                true => moved(),
+                false => e,
+            })?;
+        if directory_path.stored().last() != Some(&stored) {
+            return Err(moved());
+        }
+    }
+    Ok((top, directory_path))
+}
+
+// Returns the new path of the directory, its url changes with every commit
+async fn commit_path(
+    arc: &Arc<ServerData>,
+    top: &Option<Stored>,
+    directory_path: DirectoryPath,
+) -> Result<Vec<String>, Error> {
+    let names = directory_path.names();
+    let commit = directory_path.commit(arc.global.clone()).await?;
+    match top {
+        Some(stored) => {
+            stored
+                .put(arc.global.clone(), commit.top.clone().to_enum())
+                .await?
+        }
+        None => arc.global.save_root(&commit.top).await?,
+    }
+    commit.cleanup(arc.global.clone()).await;
+    Ok(top
+        .iter()
+        .map(|stored| stored.as_url())
+        .chain(
+            commit
+                .path
+                .iter()
+                .zip(&names)
+                .map(|(stored, name)| entry_segment(stored, name)),
+        )
+        .collect())
+}
+
 async fn render_directory(
     data: Arc<ServerData>,
+    base: String,
     path: Vec<String>,
     directory: Directory,
+    after: Option<&str>,
     cookie: Option<cookie::Cookie<'static>>,
 ) -> HttpResponse {
+    // one more entry than shown tells if there is a next page
+    let page_size = data.config.page_size.max(1);
+    let mut entries = match directory
+        .page(data.global.clone(), after, page_size + 1)
+        .await
+    {
+        Ok(entries) => entries,
+        Err(err) => return render_error(data, err).await,
+    };
+    let next = match entries.len() > page_size {
+        true => {
+            entries.truncate(page_size);
+            entries.last().map(|(name, _)| name.clone())
+        }
+        false => None,
+    };
+    let after = after.map(str::to_string);
     let renderer: ServerRenderer<_> =
         ServerRenderer::<DirectoryIndex>::with_props(|| DirectoryIndexProps {
+            readonly: data.config.readonly || base != "/files",
             data,
+            base,
             path,
-            dir: directory,
+            entries,
+            after,
+            next,
             cut_inode: if let Some(cookie) = cookie {
                 match cookie.value() {
                     "" => None,
@@ -158,12 +341,54 @@ async fn render_directory(
     HttpResponse::Ok().content_type("text/html").body(html)
 }
 
-async fn render_error(data: Arc<ServerData>, error: String) -> HttpResponse {
+async fn render_versions(
+    data: Arc<ServerData>,
+    base: String,
+    path: Vec<String>,
+    file: File,
+) -> HttpResponse {
+    let name = split_entry(&path).map(|(_, name)| name).unwrap_or_default();
+    let props = VersionIndexProps {
+        readonly: data.config.readonly || base != "/files",
+        back: format!("{}/{}", base, path[..path.len() - 1].join("/")),
+        url: format!("{}/{}", base, path.join("/")),
+        name,
+        current: file.metadata.clone(),
+        versions: file
+            .versions
+            .iter()
+            .map(|version| (version.metadata.clone(), version.human_replaced()))
+            .collect(),
+        data,
+    };
+    let renderer: ServerRenderer<_> = ServerRenderer::<VersionIndex>::with_props(|| props);
+    let html = renderer.render().await;
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+// The status an error is answered with, the kind of the innermost error decides
+pub(crate) fn status_code(error: &Error) -> StatusCode {
+    match error.root() {
+        Error::NotFound(_) => StatusCode::NOT_FOUND,
+        Error::AlreadyExists(_) | Error::Conflict(_) => StatusCode::CONFLICT,
+        Error::Invalid(_) => StatusCode::BAD_REQUEST,
+        Error::Forbidden(_) => StatusCode::FORBIDDEN,
+        Error::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        Error::Many(_) if error.is_not_found() => StatusCode::NOT_FOUND,
+        Error::Many(_) if error.is_transient() => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+async fn render_error(data: Arc<ServerData>, error: Error) -> HttpResponse {
+    let status = status_code(&error);
+    let error = error.to_string();
     let renderer: ServerRenderer<_> =
         ServerRenderer::<ErrorPage>::with_props(|| ErrorPageProps { data, error });
     let html = renderer.render().await;
 
-    HttpResponse::ServiceUnavailable()
+    HttpResponse::build(status)
         .content_type("text/html")
         .body(html)
 }
@@ -241,6 +466,7 @@ async fn redirect(data: web::Data<Arc<ServerData>>) -> impl Responder {
 async fn get(
     data: web::Data<Arc<ServerData>>,
     path: web::Path<String>,
+    query: web::Query<FileQuery>,
     req: HttpRequest,
 ) -> impl Responder {
     let arc = data.as_ref().clone();
@@ -248,7 +474,10 @@ async fn get(
     if !data.config.see_root && path.is_empty() {
         return render_error(
             arc,
-            "Unauthorized.\nYou can change the see_root setting in the config file.".to_string(),
+            Error::Forbidden(
+                "Unauthorized.\nYou can change the see_root setting in the config file."
+                    .to_string(),
+            ),
         )
         .await;
     }
@@ -261,7 +490,10 @@ async fn get(
         .collect::<Vec<String>>();
 
     let inode = match path.is_empty() {
-        true => arc.global.get_root().await.to_enum(),
+        true => match arc.global.get_root().await {
+            Ok(root) => root.to_enum(),
+            Err(err) => return render_error(arc, err).await,
+        },
         false => {
             let inode = get_inode(arc.clone(), &path).await;
             match inode {
@@ -271,19 +503,68 @@ async fn get(
         }
     };
 
+    serve_inode(
+        arc,
+        "/files".to_string(),
+        path,
+        inode,
+        &query,
+        req.cookie("cut-inode"),
+    )
+    .await
+}
+
+async fn serve_inode(
+    arc: Arc<ServerData>,
+    base: String,
+    path: Vec<String>,
+    inode: InodeType,
+    query: &FileQuery,
+    cookie: Option<cookie::Cookie<'static>>,
+) -> HttpResponse {
     let directory = match inode {
         InodeType::Directory(dir) => dir,
+        InodeType::Symlink(symlink) => {
+            return match follow_symlink(&arc, &base, &path, &symlink).await {
+                Ok(location) => HttpResponse::Found()
+                    .append_header(("Location", location))
+                    .finish(),
+                Err(err) => render_error(arc, err).await,
+            };
+        }
+        InodeType::File(file) if query.versions.is_some() => {
+            return render_versions(arc, base, path, file).await;
+        }
         InodeType::File(file) => {
+            let version = query.version;
+            let metadata = match version.map(|number| file.version(number)) {
+                Some(Ok(version)) => &version.metadata,
+                Some(Err(err)) => return render_error(arc, err).await,
+                None => &file.metadata,
+            };
+            let name = entry_name(&path).unwrap_or_default();
+            let content_type = mime::content_type(metadata.mime.as_deref(), &name);
+            let size = match metadata.size {
+                Size::Bytes(size) => size,
+                _ => 0,
+            };
+            let disposition = content_disposition(&name, &content_type, query.download.is_some());
             // if the path is a file, stream it
             return HttpResponse::Ok()
-                .content_type("application/octet-stream")
+                .content_type(content_type)
+                .insert_header(disposition)
+                .insert_header(("X-Content-Type-Options", "nosniff"))
+                .no_chunking(size)
                 .streaming(async_stream::stream! {
-                    let mut stream = file.get(arc.global.clone());
+                    let mut stream = match version.and_then(|number| file.version(number).ok()) {
+                        Some(version) => version.get(arc.global.clone()),
+                        None => file.get(arc.global.clone()),
+                    };
 
                     while let Some(chunk) = stream.next().await {
                         match chunk {
                             Ok(chunk) => yield Ok(web::Bytes::from(chunk)),
-                            Err(e) => yield Err(std::io::Error::new(std::io::ErrorKind::Other, e))
+                            Err(e) => yield Err(std::io::Error::other(e))
                         }
                     }
                 });
@@ -291,14 +572,239 @@ async fn get(
     };
 
     // otherwise, render an html index of the directory
-    render_directory(arc, path, directory, req.cookie("cut-inode")).await
+    render_directory(arc, base, path, directory, query.after.as_deref(), cookie).await
+}
+
+// The name the last entry of the path has in its directory, it follows the url after a `$`
+fn entry_name(path: &[String]) -> Option<String> {
+    path.last()?.splitn(3, '$').nth(2).map(str::to_string)
+}
+
+// Types a browser would run scripts from are always saved, they'd run with the drive's origin
+fn content_disposition(name: &str, content_type: &str, download: bool) -> ContentDisposition {
+    let scriptable = matches!(
+        content_type,
+        "text/html" | "application/xhtml+xml" | "image/svg+xml"
+    );
+    let disposition = match download || scriptable {
+        true => DispositionType::Attachment,
+        false => DispositionType::Inline,
+    };
+    let mut parameters = Vec::new();
+    if !name.is_empty() {
+        // plain filenames for ascii names, RFC 5987 for the rest
+        parameters.push(match name.is_ascii() {
+            true => DispositionParam::Filename(name.to_string()),
+            false => DispositionParam::FilenameExt(ExtendedValue {
+                charset: Charset::Ext("UTF-8".to_string()),
+                language_tag: None,
+                value: name.as_bytes().to_vec(),
+            }),
+        });
+    }
+    ContentDisposition {
+        disposition,
+        parameters,
+    }
+}
+
+// The directory an empty path leads to under `base`
+async fn top_directory(arc: &Arc<ServerData>, base: &str) -> Result<Directory, Error> {
+    match base.strip_prefix("/snapshots/") {
+        Some(name) => {
+            let name = urlencoding::decode(name)
+                .map_err(|e| Error::Invalid(format!("Invalid snapshot name: {}", e)))?;
+            arc.global.snapshot_root(&name).await
+        }
+        None if arc.config.see_root => arc.global.get_root().await,
+        None => Err(Error::Forbidden(
+            "The symlink leads outside of the share".to_string(),
+        )),
+    }
+}
+
+// Walks the target by name from the directory the link is in, like a filesystem would
+async fn follow_symlink(
+    arc: &Arc<ServerData>,
+    base: &str,
+    path: &[String],
+    symlink: &Symlink,
+) -> Result<String, Error> {
+    let (absolute, names) = symlink.components();
+    // every segment of the url but the link itself, they are directories
+    let mut segments = match absolute {
+        true => Vec::new(),
+        false => path[..path.len() - 1].to_vec(),
+    };
+    for name in names {
+        if name == ".." {
+            if segments.pop().is_none() {
+                return Err(Error::Invalid(format!(
+                    "{} leads outside of the drive",
+                    symlink.target
+                )));
+            }
+            continue;
+        }
+        let directory = match segments.last() {
+            Some(_) => match get_inode(arc.clone(), &segments).await? {
+                InodeType::Directory(directory) => directory,
+                _ => {
+                    return Err(Error::Invalid(format!(
+                        "{} is not a directory",
+                        symlink.target
+                    )))
+                }
+            },
+            None => top_directory(arc, base).await?,
+        };
+        let stored = directory
+            .get(arc.global.clone(), &name)
+            .await
+            .context(format!("{} does not exist", symlink.target))?;
+        // every entry keeps its name in the url, like links in directory indexes
+        segments.push(entry_segment(&stored, &name));
+    }
+    Ok(format!("{}/{}", base, segments.join("/")))
+}
+
+#[route("/snapshots/", method = "GET")]
+async fn snapshots(data: web::Data<Arc<ServerData>>) -> impl Responder {
+    let arc = data.as_ref().clone();
+
+    // snapshots hold the whole tree
+    if !data.config.see_root {
+        return render_error(
+            arc,
+            Error::Forbidden(
+                "Unauthorized.\nYou can change the see_root setting in the config file."
+                    .to_string(),
+            ),
+        )
+        .await;
+    }
+
+    let snapshots = match arc.global.snapshots() {
+        Ok(snapshots) => snapshots,
+        Err(err) => return render_error(arc, err).await,
+    };
+    let renderer: ServerRenderer<_> =
+        ServerRenderer::<SnapshotIndex>::with_props(|| SnapshotIndexProps {
+            data: arc,
+            snapshots,
+        });
+    let html = renderer.render().await;
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+#[route("/snapshots/{name}/{path:.*}", method = "GET")]
+async fn snapshot(
+    data: web::Data<Arc<ServerData>>,
+    params: web::Path<(String, String)>,
+    query: web::Query<FileQuery>,
+) -> impl Responder {
+    let arc = data.as_ref().clone();
+    let (name, path) = params.into_inner();
+
+    if !data.config.see_root {
+        return render_error(
+            arc,
+            Error::Forbidden(
+                "Unauthorized.\nYou can change the see_root setting in the config file."
+                    .to_string(),
+            ),
+        )
+        .await;
+    }
+
+    let path = path
+        .split('/')
+        .map(|part| part.to_string())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<String>>();
+
+    let inode = match path.is_empty() {
+        true => match arc.global.snapshot_root(&name).await {
+            Ok(root) => root.to_enum(),
+            Err(err) => return render_error(arc, err).await,
+        },
+        false => match get_inode(arc.clone(), &path).await {
+            Ok(inode) => inode,
+            Err(err) => return render_error(arc, err).await,
+        },
+    };
+
+    let base = format!("/snapshots/{}", urlencoding::encode(&name));
+    serve_inode(arc, base, path, inode, &query, None).await
+}
+
+#[route("/trash/", method = "GET")]
+async fn trash(data: web::Data<Arc<ServerData>>) -> impl Responder {
+    let arc = data.as_ref().clone();
+
+    // entries come from the whole tree
+    if !data.config.see_root {
+        return render_error(
+            arc,
+            Error::Forbidden(
+                "Unauthorized.\nYou can change the see_root setting in the config file."
+                    .to_string(),
+            ),
+        )
+        .await;
+    }
+
+    let entries = match arc.global.trash() {
+        Ok(entries) => entries,
+        Err(err) => return render_error(arc, err).await,
+    };
+    let renderer: ServerRenderer<_> =
+        ServerRenderer::<TrashIndex>::with_props(|| TrashIndexProps { data: arc, entries });
+    let html = renderer.render().await;
+
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+#[derive(MultipartForm)]
+pub struct Untrash {
+    id: Text<u64>,
+}
+
+#[route("/trash/", method = "POST")]
+async fn untrash(data: web::Data<Arc<ServerData>>, form: MultipartForm<Untrash>) -> impl Responder {
+    let arc = data.as_ref().clone();
+
+    if data.config.readonly {
+        return render_error(arc, Error::Forbidden("Server is in read-only mode.\nIf you are the server owner, you can disable this in the config file.".to_string())).await;
+    }
+
+    if !data.config.see_root {
+        return render_error(
+            arc,
+            Error::Forbidden(
+                "Unauthorized.\nYou can change the see_root setting in the config file."
+                    .to_string(),
+            ),
+        )
+        .await;
+    }
+
+    match arc.global.restore_from_trash(form.id.0).await {
+        Ok(_) => HttpResponse::Found()
+            .append_header(("Location", format!("{}trash/", arc.config.path)))
+            .finish(),
+        Err(err) => render_error(arc, err).await,
+    }
 }
 
 #[derive(MultipartForm)]
 pub struct Upload {
     file: Option<Bytes>,
+    overwrite: Option<Text<String>>,
     directory_name: Option<Text<String>>,
     request: Option<Text<String>>,
+    version: Option<Text<usize>>,
     paste_name: Option<Text<String>>,
 }
 
@@ -312,13 +818,16 @@ async fn post(
     let arc = data.as_ref().clone();
 
     if data.config.readonly {
-        return render_error(arc, "Server is in read-only mode.\nIf you are the server owner, you can disable this in the config file.".to_string()).await;
+        return render_error(arc, Error::Forbidden("Server is in read-only mode.\nIf you are the server owner, you can disable this in the config file.".to_string())).await;
     }
 
     if data.config.see_root && path.is_empty() {
         return render_error(
             arc,
-            "Unauthorized.\nYou can change the see_root setting in the config file.".to_string(),
+            Error::Forbidden(
+                "Unauthorized.\nYou can change the see_root setting in the config file."
+                    .to_string(),
+            ),
         )
         .await;
     }
@@ -330,28 +839,23 @@ async fn post(
         .filter(|part| !part.is_empty())
         .collect::<Vec<String>>();
 
-    match &form.file {
-        Some(file) => {
-            return match post_got_file(arc.clone(), path, file).await {
-                Ok(response) => response,
-                Err(e) => render_error(arc, e).await,
-            }
-        }
-        None => {}
+    if let Some(file) = &form.file {
+        let overwrite = form.overwrite.is_some();
+        return match post_got_file(arc.clone(), path, file, overwrite).await {
+            Ok(response) => response,
+            Err(e) => render_error(arc, e).await,
+        };
     }
 
-    match &form.directory_name {
-        Some(directory_name) => {
-            return match post_got_directory(arc.clone(), path, &directory_name.0).await {
-                Ok(response) => response,
-                Err(e) => render_error(arc, e).await,
-            }
-        }
-        None => {}
+    if let Some(directory_name) = &form.directory_name {
+        return match post_got_directory(arc.clone(), path, &directory_name.0).await {
+            Ok(response) => response,
+            Err(e) => render_error(arc, e).await,
+        };
     }
 
-    match &form.request {
-        Some(request) => match request.0.as_str() {
+    if let Some(request) = &form.request {
+        match request.0.as_str() {
             "delete" => {
                 return match post_got_delete(arc.clone(), path).await {
                     Ok(response) => response,
@@ -364,37 +868,45 @@ async fn post(
                     Err(e) => render_error(arc, e).await,
                 }
             }
+            "restore" => {
+                let version = match &form.version {
+                    Some(version) => version.0,
+                    None => {
+                        return render_error(arc, Error::Invalid("Invalid version".to_string()))
+                            .await
+                    }
+                };
+                return match post_got_restore(arc.clone(), path, version).await {
+                    Ok(response) => response,
+                    Err(e) => render_error(arc, e).await,
+                };
+            }
             _ => {}
-        },
-        None => {}
+        }
     }
 
-    if form.paste_name.is_some() {
-        let cookie = req.cookie("cut-inode");
-        if cookie.is_none() {
-            return render_error(arc, "Invalid cookie".to_string()).await;
-        }
-        return match post_got_paste(
-            arc.clone(),
-            path,
-            form.paste_name.as_ref().unwrap().0.as_str().to_owned(),
-            cookie.unwrap(),
-        )
-        .await
+    if let Some(paste_name) = &form.paste_name {
+        let cookie = match req.cookie("cut-inode") {
+            Some(cookie) => cookie,
+            None => return render_error(arc, Error::Invalid("Invalid cookie".to_string())).await,
+        };
+        return match post_got_paste(arc.clone(), path, paste_name.0.as_str().to_owned(), cookie)
+            .await
         {
             Ok(response) => response,
             Err(e) => render_error(arc, e).await,
         };
     }
 
-    render_error(arc, "Invalid request".to_string()).await
+    render_error(arc, Error::Invalid("Invalid request".to_string())).await
 }
 
 async fn post_got_file(
     arc: Arc<ServerData>,
     path: Vec<String>,
     file: &Bytes,
-) -> Result<HttpResponse, String> {
+    overwrite: bool,
+) -> Result<HttpResponse, Error> {
     let filename = match file.file_name.clone() {
         Some(name) => name,
         None => {
@@ -416,54 +928,38 @@ async fn post_got_file(
             .finish());
     }
 
-    let mut directory;
-    let stored: Option<Stored>;
-
-    if !path.is_empty() {
-        stored = match get_stored(&path) {
-            Ok(stored) => Some(stored),
-            Err(e) => return Err(e),
-        };
-
-        directory = match stored
-            .as_ref()
-            .unwrap()
-            .get::<InodeType, AsyncGlobal>(arc.global.clone())
-            .await
-        {
-            Ok(InodeType::Directory(dir)) => dir,
-            Ok(_) => Err("Path is not a directory".to_string())?,
-            Err(e) => Err(e)?,
-        };
-    } else {
-        directory = arc.global.get_root().await;
-        stored = None;
-    }
-
     let bytes = file.data.to_vec();
+    let mime = mime::detect(&filename, &bytes);
 
-    let file = match File::create(arc.global.clone(), bytes).await {
+    // the upload happens before locking, so other writers only wait for the directory update
+    let mut file = match File::create(arc.global.clone(), bytes).await {
         Ok(file) => file,
         Err(e) => Err(e)?,
     };
-
-    match directory
-        .add(arc.global.clone(), &filename, file.to_enum())
-        .await
-    {
+    file.metadata.mime = mime;
+
+    let lock = arc.global.lock_directory(None).await?;
+    let (top, mut directory_path) = open_path(&arc, &path).await?;
+    let directory = directory_path.directory();
+    let added = match overwrite {
+        true => {
+            directory
+                .overwrite(arc.global.clone(), &filename, file)
+                .await
+        }
+        false => {
+            directory
+                .add(arc.global.clone(), &filename, file.to_enum())
+                .await
+        }
+    };
+    match added {
         Ok(_) => {}
         Err(e) => Err(e)?,
     };
 
-    match stored {
-        Some(stored) => {
-            match stored.put(arc.global.clone(), directory.to_enum()).await {
-                Ok(_) => {}
-                Err(e) => Err(e)?,
-            };
-        }
-        None => arc.global.save_root(&directory).await,
-    }
+    let path = commit_path(&arc, &top, directory_path).await?;
+    drop(lock);
 
     Ok(HttpResponse::Found()
         .append_header((
@@ -477,32 +973,12 @@ async fn post_got_directory(
     arc: Arc<ServerData>,
     path: Vec<String>,
     directory_name: &String,
-) -> Result<HttpResponse, String> {
-    let mut directory;
-    let stored: Option<Stored>;
-
-    if !path.is_empty() {
-        stored = match get_stored(&path) {
-            Ok(stored) => Some(stored),
-            Err(e) => Err(e)?,
-        };
+) -> Result<HttpResponse, Error> {
+    let lock = arc.global.lock_directory(None).await?;
+    let (top, mut directory_path) = open_path(&arc, &path).await?;
 
-        directory = match stored
-            .as_ref()
-            .unwrap()
-            .get::<InodeType, AsyncGlobal>(arc.global.clone())
-            .await
-        {
-            Ok(InodeType::Directory(dir)) => dir,
-            Ok(_) => Err("Path is not a directory".to_string())?,
-            Err(e) => Err(e)?,
-        };
-    } else {
-        directory = arc.global.get_root().await;
-        stored = None;
-    }
-
-    match directory
+    match directory_path
+        .directory()
         .add(
             arc.global.clone(),
             directory_name,
@@ -514,15 +990,8 @@ async fn post_got_directory(
         Err(e) => Err(e)?,
     };
 
-    match stored {
-        Some(stored) => {
-            match stored.put(arc.global.clone(), directory.to_enum()).await {
-                Ok(_) => {}
-                Err(e) => Err(e)?,
-            };
-        }
-        None => arc.global.save_root(&directory).await,
-    }
+    let path = commit_path(&arc, &top, directory_path).await?;
+    drop(lock);
 
     Ok(HttpResponse::Found()
         .append_header((
@@ -532,92 +1001,37 @@ async fn post_got_directory(
         .finish())
 }
 
-async fn post_got_delete(arc: Arc<ServerData>, path: Vec<String>) -> Result<HttpResponse, String> {
+async fn post_got_delete(arc: Arc<ServerData>, path: Vec<String>) -> Result<HttpResponse, Error> {
     if path.is_empty() {
-        return Err("Invalid path".to_string());
+        return Err(Error::Invalid("Invalid path".to_string()));
     }
 
     let parent_path = path[..path.len() - 1].to_vec();
-    let file = match path.last() {
-        Some(filename) => filename.split('$').collect::<Vec<&str>>(),
-        None => Err("Invalid path".to_string())?,
-    };
+    let (file_stored, filename) = split_entry(&path)?;
 
-    if file.len() != 3 {
-        return Err("Invalid path".to_string());
-    }
+    let lock = arc.global.lock_directory(None).await?;
+    let (top, mut directory_path) = open_path(&arc, &parent_path).await?;
 
-    let filename = file[2].to_string();
-
-    let file_stored = match Stored::from_url(file[0], file[1]) {
-        Ok(stored) => stored,
-        Err(e) => Err(e)?,
-    };
-
-    let mut directory;
-    let stored: Option<Stored>;
-
-    if !parent_path.is_empty() {
-        stored = match get_stored(&parent_path) {
-            Ok(stored) => Some(stored),
-            Err(e) => Err(e)?,
-        };
-
-        directory = match stored
-            .as_ref()
-            .unwrap()
-            .get::<InodeType, AsyncGlobal>(arc.global.clone())
-            .await
-        {
-            Ok(InodeType::Directory(dir)) => dir,
-            Ok(_) => Err("Path is not a directory".to_string())?,
-            Err(e) => Err(e)?,
-        };
-    } else {
-        directory = arc.global.get_root().await;
-        stored = None;
-    }
-
-    let removed = match directory.unlink(&filename) {
+    let removed = match directory_path
+        .directory()
+        .unlink(arc.global.clone(), &filename)
+        .await
+    {
         Ok(removed) => removed,
         Err(e) => Err(e)?,
     };
 
     // Check if we are deleting the correct file
     if removed != file_stored {
-        return Err("File not found".to_string());
-    }
-
-    if stored.is_some() {
-        match stored
-            .unwrap()
-            .put(arc.global.clone(), directory.to_enum())
-            .await
-        {
-            Ok(_) => {}
-            Err(e) => Err(e)?,
-        };
-    } else {
-        arc.global.save_root(&directory).await
+        return Err(Error::Conflict(
+            "File not found, it may have changed since the page was loaded".to_string(),
+        ));
     }
 
-    let mut inode = match removed
-        .get::<InodeType, AsyncGlobal>(arc.global.clone())
-        .await
-    {
-        Ok(inode) => inode,
-        Err(e) => Err(e)?,
-    };
-
-    match inode.delete(arc.global.clone()).await {
-        Ok(_) => {}
-        Err(e) => Err(e)?,
-    }
-
-    match removed.delete(arc.global.clone()).await {
-        Ok(_) => {}
-        Err(e) => Err(e)?,
-    }
+    let names = directory_path.names();
+    let parent_path = commit_path(&arc, &top, directory_path).await?;
+    arc.global.discard(&filename, names, top, removed).await?;
+    drop(lock);
 
     Ok(HttpResponse::Found()
         .append_header((
@@ -627,74 +1041,35 @@ async fn post_got_delete(arc: Arc<ServerData>, path: Vec<String>) -> Result<Http
         .finish())
 }
 
-async fn post_got_cut(arc: Arc<ServerData>, path: Vec<String>) -> Result<HttpResponse, String> {
+async fn post_got_cut(arc: Arc<ServerData>, path: Vec<String>) -> Result<HttpResponse, Error> {
     if path.is_empty() {
-        return Err("Invalid path".to_string());
+        return Err(Error::Invalid("Invalid path".to_string()));
     }
 
     let parent_path = path[..path.len() - 1].to_vec();
-    let file = match path.last() {
-        Some(filename) => filename.split('$').collect::<Vec<&str>>(),
-        None => Err("Invalid path".to_string())?,
-    };
-
-    if file.len() != 3 {
-        return Err("Invalid path".to_string());
-    }
-
-    let filename = file[2].to_string();
+    let (file_stored, filename) = split_entry(&path)?;
 
-    let file_stored = match Stored::from_url(file[0], file[1]) {
-        Ok(stored) => stored,
-        Err(e) => Err(e)?,
-    };
+    let lock = arc.global.lock_directory(None).await?;
+    let (top, mut directory_path) = open_path(&arc, &parent_path).await?;
 
-    let mut directory;
-    let stored: Option<Stored>;
-
-    if !parent_path.is_empty() {
-        stored = match get_stored(&parent_path) {
-            Ok(stored) => Some(stored),
-            Err(e) => Err(e)?,
-        };
-
-        directory = match stored
-            .as_ref()
-            .unwrap()
-            .get::<InodeType, AsyncGlobal>(arc.global.clone())
-            .await
-        {
-            Ok(InodeType::Directory(dir)) => dir,
-            Ok(_) => Err("Path is not a directory".to_string())?,
-            Err(e) => Err(e)?,
-        };
-    } else {
-        directory = arc.global.get_root().await;
-        stored = None;
-    }
-
-    let unlinked = match directory.unlink(&filename) {
+    let unlinked = match directory_path
+        .directory()
+        .unlink(arc.global.clone(), &filename)
+        .await
+    {
         Ok(unlinked) => unlinked,
         Err(e) => Err(e)?,
     };
 
     // Check if we are deleting the correct file
     if unlinked != file_stored {
-        return Err("File not found".to_string());
+        return Err(Error::Conflict(
+            "File not found, it may have changed since the page was loaded".to_string(),
+        ));
     }
 
-    if stored.is_some() {
-        match stored
-            .unwrap()
-            .put(arc.global.clone(), directory.to_enum())
-            .await
-        {
-            Ok(_) => {}
-            Err(e) => Err(e)?,
-        };
-    } else {
-        arc.global.save_root(&directory).await
-    }
+    let parent_path = commit_path(&arc, &top, directory_path).await?;
+    drop(lock);
 
     let cookie = cookie::Cookie::build("cut-inode", unlinked.as_url())
         .path(arc.config.path.clone())
@@ -710,18 +1085,67 @@ async fn post_got_cut(arc: Arc<ServerData>, path: Vec<String>) -> Result<HttpRes
         .finish())
 }
 
+async fn post_got_restore(
+    arc: Arc<ServerData>,
+    path: Vec<String>,
+    version: usize,
+) -> Result<HttpResponse, Error> {
+    if path.is_empty() {
+        return Err(Error::Invalid("Invalid path".to_string()));
+    }
+
+    let parent_path = path[..path.len() - 1].to_vec();
+    let (file_stored, filename) = split_entry(&path)?;
+
+    let lock = arc.global.lock_directory(None).await?;
+    let (top, mut directory_path) = open_path(&arc, &parent_path).await?;
+
+    // Check if we are restoring the file that was shown
+    if directory_path
+        .directory()
+        .get(arc.global.clone(), &filename)
+        .await?
+        != file_stored
+    {
+        return Err(Error::Conflict(
+            "File not found, it may have changed since the page was loaded".to_string(),
+        ));
+    }
+
+    let restored = match directory_path
+        .directory()
+        .restore_version(arc.global.clone(), &filename, version)
+        .await
+    {
+        Ok(restored) => restored.clone(),
+        Err(e) => Err(e)?,
+    };
+
+    let parent_path = commit_path(&arc, &top, directory_path).await?;
+    drop(lock);
+
+    Ok(HttpResponse::Found()
+        .append_header((
+            "Location",
+            format!(
+                "{}files/{}/{}?versions",
+                arc.config.path,
+                parent_path.join("/"),
+                entry_segment(&restored, &filename)
+            ),
+        ))
+        .finish())
+}
+
 async fn post_got_paste(
     arc: Arc<ServerData>,
     path: Vec<String>,
     paste_name: String,
     cookie: cookie::Cookie<'static>,
-) -> Result<HttpResponse, String> {
-    let mut directory;
-    let stored: Option<Stored>;
-
+) -> Result<HttpResponse, Error> {
     let split = cookie.value().split('$').collect::<Vec<&str>>();
     if split.len() != 2 {
-        return Err("Invalid cookie".to_string());
+        return Err(Error::Invalid("Invalid cookie".to_string()));
     }
 
     let paste_stored = match Stored::from_url(split[0], split[1]) {
@@ -729,65 +1153,31 @@ async fn post_got_paste(
         Err(e) => Err(e)?,
     };
 
-    if !path.is_empty() {
-        stored = match get_stored(&path) {
-            Ok(stored) => Some(stored),
-            Err(e) => Err(e)?,
-        };
-
-        directory = match stored
-            .as_ref()
-            .unwrap()
-            .get::<InodeType, AsyncGlobal>(arc.global.clone())
-            .await
-        {
-            Ok(InodeType::Directory(dir)) => dir,
-            Ok(_) => Err("Path is not a directory".to_string())?,
-            Err(e) => Err(e)?,
-        };
-    } else {
-        directory = arc.global.get_root().await;
-        stored = None;
-    }
+    let lock = arc.global.lock_directory(None).await?;
+    let (top, mut directory_path) = open_path(&arc, &path).await?;
 
-    match directory.put(&paste_name, paste_stored) {
+    match directory_path
+        .directory()
+        .put(arc.global.clone(), &paste_name, paste_stored)
+        .await
+    {
         Ok(_) => {}
         Err(e) => Err(e)?,
     };
 
-    match stored.clone() {
-        Some(stored) => {
-            match stored.put(arc.global.clone(), directory.to_enum()).await {
-                Ok(_) => {}
-                Err(e) => Err(e)?,
-            };
-        }
-        None => arc.global.save_root(&directory).await,
-    }
-
-    let directory = match stored {
-        Some(stored) => {
-            match stored
-                .get::<InodeType, AsyncGlobal>(arc.global.clone())
-                .await
-            {
-                Ok(InodeType::Directory(dir)) => dir,
-                Ok(_) => Err("Path is not a directory".to_string())?,
-                Err(e) => Err(e)?,
-            }
-        }
-        None => arc.global.get_root().await,
-    };
+    let directory = directory_path.directory().clone();
+    let path = commit_path(&arc, &top, directory_path).await?;
+    drop(lock);
 
     let c = cookie::Cookie::build("cut-inode", "")
         .path(arc.config.path.clone())
         .max_age(cookie::time::Duration::seconds(1))
         .finish();
 
-    let mut page = render_directory(arc, path, directory, None).await;
+    let mut page = render_directory(arc, "/files".to_string(), path, directory, None, None).await;
     match page.add_removal_cookie(&c) {
         Ok(_) => {}
-        Err(e) => Err(format!("Failed to add cookie: {}", e))?,
+        Err(e) => Err(Error::Internal(format!("Failed to add cookie: {}", e)))?,
     }
 
     Ok(page)
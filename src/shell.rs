@@ -1,22 +1,32 @@
+// commands share the `Command` signature, so some take `&mut Vec` without needing to grow it
+#![allow(clippy::ptr_arg)]
+
 use crate::global::GlobalTrait;
-use futures::StreamExt;
+use futures::{stream::BoxStream, StreamExt};
 use indicatif::{ProgressBar, ProgressStyle};
-use liner::{Completer, Context};
+use liner::{Completer, Context, Prompt};
 use std::{
+    collections::HashMap,
     io::{BufReader, Read, Write},
+    path::{Path, PathBuf},
     sync::Arc,
 };
 use tokio::runtime::Runtime;
 use walkdir::WalkDir;
 
 use crate::{
+    error::Error,
     global::BlockingGlobal,
     inodes::{
         directory::Directory,
         file::File,
         inode::{Inode, InodeType},
-        metadata::Metadata,
+        metadata::{Metadata, Size},
+        mime,
+        path::DirectoryPath,
+        symlink::{resolve_path, Symlink},
     },
+    posix,
     stored::Stored,
 };
 
@@ -93,13 +103,12 @@ pub fn shell(global: Arc<BlockingGlobal>) {
             },
             match path.len() {
                 0 => String::from(""),
-                _ => {
-                    format!("{}", path.join("/"))
-                }
+                _ => path.join("/"),
             }
         );
 
-        let line: String = match context.read_line(&prompt, None, &mut ShellCompleter) {
+        let line: String = match context.read_line(Prompt::from(prompt), None, &mut ShellCompleter)
+        {
             Ok(line) => line,
             Err(_) => break,
         };
@@ -116,13 +125,9 @@ pub fn shell(global: Arc<BlockingGlobal>) {
         match COMMANDS.iter().find(|(name, _, _)| *name == command) {
             Some((_, func, _)) => {
                 match func(&global, args, &mut path, &mut stored_cwd, &mut clipboard) {
+                    Ok(_) if command == "exit" => break,
                     Ok(_) => {}
-                    Err(e) => {
-                        if e == "SIGTERM" {
-                            break;
-                        }
-                        println!("Error: {}", e)
-                    }
+                    Err(e) => println!("Error: {}", e),
                 }
             }
             None => println!("Unknown command: {}", command),
@@ -138,7 +143,7 @@ type Command = (
         &mut Vec<String>,
         &mut Vec<Stored>,
         &mut Option<Stored>,
-    ) -> Result<(), String>,
+    ) -> Result<(), Error>,
     &'static str,
 );
 
@@ -148,16 +153,78 @@ const COMMANDS: &[Command] = &[
     ("ls", ls, "Lists the contents of the current directory."),
     ("mkdir", mkdir, "Creates a new directory."),
     ("cd", cd, "Changes the current working directory."),
-    ("rm", rm, "Removes a file or directory."),
+    ("rm", rm, "Moves a file or directory to the trash."),
     ("cut", cut, "Cuts a file or directory."),
     ("paste", paste, "Pastes a file or directory."),
-    ("up", upload, "Uploads a file to the drive"),
+    (
+        "ln",
+        link,
+        "Adds another name for a file, -s makes a symlink instead.",
+    ),
+    (
+        "up",
+        upload,
+        "Uploads a file, -o overwrites and keeps the old version.",
+    ),
     ("up_tree", upload_tree, "Uploads a tree to the drive"),
-    ("down", download, "Downloads a file from the drive."),
+    (
+        "down",
+        download,
+        "Downloads a file, or an old version of it.",
+    ),
+    (
+        "down_tree",
+        download_tree,
+        "Downloads a directory with its permissions, owners and times.",
+    ),
+    (
+        "write",
+        write_at,
+        "Writes a local file into a file at an offset.",
+    ),
+    ("append", append, "Appends a local file to a file."),
+    (
+        "truncate",
+        truncate,
+        "Cuts a file off at a size, or grows it.",
+    ),
+    ("versions", versions, "Lists the old versions of a file."),
+    ("revert", revert, "Makes an old version of a file current."),
     ("stat", stat, "Prints metadata about a file or directory."),
     ("lsbk", bucket_list, "Lists all buckets."),
     ("bktest", bucket_test, "Tests a bucket."),
     ("dbg", dbg, "Prints debug information about an object."),
+    (
+        "snap",
+        snapshot,
+        "Takes a read-only snapshot of the whole tree.",
+    ),
+    (
+        "lssnap",
+        list_snapshots,
+        "Lists snapshots, or a directory in one.",
+    ),
+    (
+        "restore",
+        restore_snapshot,
+        "Replaces the tree with a snapshot.",
+    ),
+    ("rmsnap", delete_snapshot, "Deletes a snapshot."),
+    (
+        "lstrash",
+        list_trash,
+        "Lists deleted files and directories.",
+    ),
+    (
+        "untrash",
+        untrash,
+        "Puts a deleted entry back where it was.",
+    ),
+    (
+        "purge",
+        purge,
+        "Empties the trash of expired entries, or of all with -a.",
+    ),
     (
         "root",
         |_, _, path, cwd, _| {
@@ -169,7 +236,10 @@ const COMMANDS: &[Command] = &[
     ),
     (
         "cwd",
-        |_, _, path, _, _| Ok(println!("/{}", path.join("/"))),
+        |_, _, path, _, _| {
+            println!("/{}", path.join("/"));
+            Ok(())
+        },
         "Prints the current working directory.",
     ),
 ];
@@ -180,7 +250,7 @@ fn help(
     _path: &mut Vec<String>,
     _cwd: &mut Vec<Stored>,
     _clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     println!("Commands:");
     for (name, _, description) in COMMANDS {
         println!("  {:<10} {}", name, description);
@@ -188,41 +258,62 @@ fn help(
     Ok(())
 }
 
+// Every change moves the directories on its path to new chunks, so the current directory is looked up
+// from the root by name each time and `cwd` only mirrors where it was found
+fn open_cwd(
+    global: &Arc<BlockingGlobal>,
+    path: &[String],
+    cwd: &mut Vec<Stored>,
+) -> Result<DirectoryPath, Error> {
+    let rt = Runtime::new().unwrap();
+    let root = global.get_root()?;
+    let directory_path = rt.block_on(root.open_path(global.clone(), path))?;
+    *cwd = directory_path.stored();
+    Ok(directory_path)
+}
+
+fn current_directory(
+    global: &Arc<BlockingGlobal>,
+    path: &[String],
+    cwd: &mut Vec<Stored>,
+) -> Result<Directory, Error> {
+    Ok(open_cwd(global, path, cwd)?.directory().clone())
+}
+
+// Writes the changed path and saves the new root, hold the root's lock from open_cwd until here
+fn commit_cwd(
+    global: &Arc<BlockingGlobal>,
+    directory_path: DirectoryPath,
+    cwd: &mut Vec<Stored>,
+) -> Result<(), Error> {
+    let rt = Runtime::new().unwrap();
+    let commit = rt.block_on(directory_path.commit(global.clone()))?;
+    global.save_root(&commit.top)?;
+    rt.block_on(commit.cleanup(global.clone()));
+    *cwd = commit.path;
+    Ok(())
+}
+
 fn dbg(
     global: &Arc<BlockingGlobal>,
     args: Vec<String>,
-    _path: &mut Vec<String>,
+    path: &mut Vec<String>,
     cwd: &mut Vec<Stored>,
     _clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if args.len() != 1 {
-        return Err("Usage: dbg <global|.|<path>>".to_string());
+        return Err(Error::Invalid("Usage: dbg <global|.|<path>>".to_string()));
     }
     if args[0] == "global" {
         dbg!(global);
         Ok(())
     } else if args[0] == "." {
-        let rt = Runtime::new().unwrap();
-        if cwd.is_empty() {
-            dbg!(global.get_root());
-        } else {
-            let inode: InodeType = rt.block_on(cwd.last().unwrap().get(global.clone()))?;
-            dbg!(inode);
-        }
+        dbg!(current_directory(global, path, cwd)?);
         Ok(())
     } else {
         let rt = Runtime::new().unwrap();
-        let dir = match cwd.last() {
-            Some(cwd) => {
-                let inode: InodeType = rt.block_on(cwd.get(global.clone()))?;
-                match inode {
-                    InodeType::Directory(dir) => dir,
-                    _ => Err("Not in a directory.".to_string())?,
-                }
-            }
-            None => global.get_root(),
-        };
-        let stored = dir.get(&args[0])?;
+        let dir = current_directory(global, path, cwd)?;
+        let stored = rt.block_on(dir.get(global.clone(), &args[0]))?;
         let inode: InodeType = rt.block_on(stored.get(global.clone()))?;
         dbg!(inode);
         Ok(())
@@ -232,26 +323,17 @@ fn dbg(
 fn ls(
     global: &Arc<BlockingGlobal>,
     _args: Vec<String>,
-    _path: &mut Vec<String>,
+    path: &mut Vec<String>,
     cwd: &mut Vec<Stored>,
     _clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     let rt = Runtime::new().unwrap();
-    let dir = match cwd.last() {
-        Some(cwd) => {
-            let inode: InodeType = rt.block_on(cwd.get(global.clone()))?;
-            match inode {
-                InodeType::Directory(dir) => {
-                    println!("..");
-                    dir
-                }
-                _ => Err("Not in a directory.".to_string())?,
-            }
-        }
-        None => global.get_root(),
-    };
+    let dir = current_directory(global, path, cwd)?;
+    if !path.is_empty() {
+        println!("..");
+    }
 
-    for name in dir.list() {
+    for name in rt.block_on(dir.list(global.clone()))? {
         println!("{}", name);
     }
     Ok(())
@@ -261,7 +343,7 @@ fn mkdir_in_dir(
     global: &Arc<BlockingGlobal>,
     parent_dir: &mut Directory,
     new_dir: &str,
-) -> Result<Stored, String> {
+) -> Result<Stored, Error> {
     let rt = Runtime::new().unwrap();
     rt.block_on(async {
         parent_dir
@@ -272,36 +354,22 @@ fn mkdir_in_dir(
             )
             .await
     })
-    .map(|s| s.clone())
 }
 
 fn mkdir(
     global: &Arc<BlockingGlobal>,
     args: Vec<String>,
-    _path: &mut Vec<String>,
+    path: &mut Vec<String>,
     cwd: &mut Vec<Stored>,
     _clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if args.len() != 1 {
-        return Err("Usage: mkdir <name>".to_string());
-    }
-    if cwd.is_empty() {
-        // root directory
-        let mut root = global.get_root();
-        mkdir_in_dir(global, &mut root, &args[0])?;
-        global.save_root(&root);
-    } else {
-        let rt = Runtime::new().unwrap();
-        let cwd = cwd.last_mut().unwrap();
-        let inode: InodeType = rt.block_on(cwd.get(global.clone()))?;
-        let mut dir = match inode {
-            InodeType::Directory(dir) => dir,
-            _ => Err("Not in a directory.".to_string())?,
-        };
-        mkdir_in_dir(global, &mut dir, &args[0])?;
-        rt.block_on(async { cwd.put(global.clone(), dir.to_enum()).await })?;
+        return Err(Error::Invalid("Usage: mkdir <name>".to_string()));
     }
-    Ok(())
+    let _lock = global.lock_directory(None)?;
+    let mut directory_path = open_cwd(global, path, cwd)?;
+    mkdir_in_dir(global, directory_path.directory(), &args[0])?;
+    commit_cwd(global, directory_path, cwd)
 }
 
 fn cd(
@@ -310,9 +378,9 @@ fn cd(
     path: &mut Vec<String>,
     cwd: &mut Vec<Stored>,
     _clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if args.len() != 1 {
-        return Err("Usage: cd <path>".to_string());
+        return Err(Error::Invalid("Usage: cd <path>".to_string()));
     }
 
     if args[0] == ".." {
@@ -326,93 +394,51 @@ fn cd(
     }
 
     let rt = Runtime::new().unwrap();
-    let dir = match cwd.last() {
-        Some(cwd) => {
-            let inode: InodeType = rt.block_on(cwd.get(global.clone()))?;
-            match inode {
-                InodeType::Directory(dir) => dir,
-                _ => Err("Not in a directory.".to_string())?,
-            }
-        }
-        None => global.get_root(),
-    };
-    let mut found = false;
-    for name in dir.list() {
-        if name == args[0] {
-            found = true;
-            break;
-        }
-    }
-    if !found {
-        return Err("No such directory.".to_string());
-    }
+    let dir = current_directory(global, path, cwd)?;
+    let stored = rt
+        .block_on(dir.get(global.clone(), &args[0]))
+        .map_err(|e| e.context("No such directory."))?;
     path.push(args[0].clone());
-    cwd.push(dir.get(&args[0])?.clone());
+    cwd.push(stored);
     Ok(())
 }
 
 fn rm(
     global: &Arc<BlockingGlobal>,
     args: Vec<String>,
-    _path: &mut Vec<String>,
+    path: &mut Vec<String>,
     cwd: &mut Vec<Stored>,
     _clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if args.len() != 1 {
-        return Err("Usage: rm <name>".to_string());
-    }
-    if cwd.is_empty() {
-        let rt = Runtime::new().unwrap();
-        let mut root = global.get_root();
-        let err = rt.block_on(async { root.remove(global.clone(), &args[0]).await });
-        global.save_root(&root);
-        err?;
-    } else {
-        let rt = Runtime::new().unwrap();
-        let cwd = cwd.last_mut().unwrap();
-        let inode: InodeType = rt.block_on(cwd.get(global.clone()))?;
-        let mut dir = match inode {
-            InodeType::Directory(dir) => dir,
-            _ => Err("Not in a directory.".to_string())?,
-        };
-        let err = rt.block_on(async { dir.remove(global.clone(), &args[0]).await });
-        rt.block_on(async { cwd.put(global.clone(), dir.to_enum()).await })?;
-        err?;
+        return Err(Error::Invalid("Usage: rm <name>".to_string()));
     }
-    Ok(())
+    let _lock = global.lock_directory(None)?;
+    let mut directory_path = open_cwd(global, path, cwd)?;
+    let rt = Runtime::new().unwrap();
+    let stored = rt.block_on(directory_path.directory().unlink(global.clone(), &args[0]))?;
+    commit_cwd(global, directory_path, cwd)?;
+    global.discard(&args[0], path.clone(), None, stored)
 }
 
 fn cut(
     global: &Arc<BlockingGlobal>,
     args: Vec<String>,
-    _path: &mut Vec<String>,
+    path: &mut Vec<String>,
     cwd: &mut Vec<Stored>,
     clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if args.len() != 1 {
-        return Err("Usage: cut <name>".to_string());
+        return Err(Error::Invalid("Usage: cut <name>".to_string()));
     }
     if clipboard.is_some() {
-        return Err("Clipboard is not empty.".to_string());
+        return Err(Error::Conflict("Clipboard is not empty.".to_string()));
     }
+    let _lock = global.lock_directory(None)?;
+    let mut directory_path = open_cwd(global, path, cwd)?;
     let rt = Runtime::new().unwrap();
-    let mut dir = match cwd.last() {
-        Some(cwd) => {
-            let inode: InodeType = rt.block_on(cwd.get(global.clone()))?;
-            match inode {
-                InodeType::Directory(dir) => dir,
-                _ => Err("Not in a directory.".to_string())?,
-            }
-        }
-        None => global.get_root(),
-    };
-    let stored = dir.unlink(&args[0])?;
-    if cwd.is_empty() {
-        global.save_root(&dir);
-    } else {
-        let cwd = cwd.last_mut().unwrap();
-        rt.block_on(async { cwd.put(global.clone(), dir.to_enum()).await })?;
-    }
+    let stored = rt.block_on(directory_path.directory().unlink(global.clone(), &args[0]))?;
+    commit_cwd(global, directory_path, cwd)?;
     let _ = clipboard.insert(stored);
     Ok(())
 }
@@ -420,39 +446,70 @@ fn cut(
 fn paste(
     global: &Arc<BlockingGlobal>,
     args: Vec<String>,
-    _path: &mut Vec<String>,
+    path: &mut Vec<String>,
     cwd: &mut Vec<Stored>,
     clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if args.len() != 1 {
-        return Err("Usage: cut <name>".to_string());
+        return Err(Error::Invalid("Usage: cut <name>".to_string()));
     }
     if clipboard.is_none() {
-        return Err("Clipboard is empty.".to_string());
+        return Err(Error::Conflict("Clipboard is empty.".to_string()));
     }
-    let rt = Runtime::new().unwrap();
-    let mut dir = match cwd.last() {
-        Some(cwd) => {
-            let inode: InodeType = rt.block_on(cwd.get(global.clone()))?;
-            match inode {
-                InodeType::Directory(dir) => dir,
-                _ => Err("Not in a directory.".to_string())?,
-            }
-        }
-        None => global.get_root(),
-    };
+    let _lock = global.lock_directory(None)?;
+    let mut directory_path = open_cwd(global, path, cwd)?;
 
     let stored = clipboard.take().unwrap();
-    dir.put(&args[0], stored)?;
+    let rt = Runtime::new().unwrap();
+    if let Err(err) = rt.block_on(directory_path.directory().put(
+        global.clone(),
+        &args[0],
+        stored.clone(),
+    )) {
+        let _ = clipboard.insert(stored);
+        return Err(err);
+    }
+
+    commit_cwd(global, directory_path, cwd)
+}
 
-    if cwd.is_empty() {
-        global.save_root(&dir);
+fn link(
+    global: &Arc<BlockingGlobal>,
+    args: Vec<String>,
+    path: &mut Vec<String>,
+    cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    let (symbolic, target, name) = match args.as_slice() {
+        [target, name] => (false, target, name),
+        [flag, target, name] if flag == "-s" => (true, target, name),
+        _ => return Err(Error::Invalid("Usage: ln [-s] <target> <name>".to_string())),
+    };
+    let _lock = global.lock_directory(None)?;
+    let rt = Runtime::new().unwrap();
+    let mut directory_path = open_cwd(global, path, cwd)?;
+    if symbolic {
+        let symlink = Symlink::new(target.clone());
+        rt.block_on(
+            directory_path
+                .directory()
+                .add(global.clone(), name, symlink.to_enum()),
+        )?;
     } else {
-        let cwd = cwd.last_mut().unwrap();
-        rt.block_on(async { cwd.put(global.clone(), dir.to_enum()).await })?;
+        // the target is a path like the ones symlinks hold, from the current directory or the root
+        let mut names = resolve_path(path, target);
+        let target_name = names
+            .pop()
+            .ok_or(Error::Invalid("Can't link the root directory.".to_string()))?;
+        let mut target_path = rt.block_on(global.get_root()?.open_path(global.clone(), &names))?;
+        let stored = rt.block_on(target_path.directory().get(global.clone(), &target_name))?;
+        rt.block_on(
+            directory_path
+                .directory()
+                .link(global.clone(), name, stored),
+        )?;
     }
-
-    Ok(())
+    commit_cwd(global, directory_path, cwd)
 }
 
 fn exit(
@@ -461,12 +518,14 @@ fn exit(
     _path: &mut Vec<String>,
     _cwd: &mut Vec<Stored>,
     clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if clipboard.is_some() {
-        return Err("Clipboard is not empty. Paste it somewhere first.".to_string());
+        return Err(Error::Conflict(
+            "Clipboard is not empty. Paste it somewhere first.".to_string(),
+        ));
     }
 
-    Err("SIGTERM".to_string())
+    Ok(())
 }
 
 fn stat_format(metadata: &Metadata) -> String {
@@ -474,52 +533,63 @@ fn stat_format(metadata: &Metadata) -> String {
     s.push_str(&format!("Size: {}\n", metadata.size.human()));
     s.push_str(&format!("Created: {}\n", metadata.human_created()));
     s.push_str(&format!("Modified: {}", metadata.human_modified()));
+    if let Some(mime) = &metadata.mime {
+        s.push_str(&format!("\nMIME type: {}", mime));
+    }
+    if let Some(posix) = &metadata.posix {
+        if let Some(mode) = posix.mode {
+            s.push_str(&format!("\nMode: {:04o}", mode));
+        }
+        if let (Some(uid), Some(gid)) = (posix.uid, posix.gid) {
+            s.push_str(&format!("\nOwner: {}:{}", uid, gid));
+        }
+        if let Some(mtime) = posix.mtime {
+            s.push_str(&format!("\nLocal modified: {}", mtime.human()));
+        }
+        if let Some(atime) = posix.atime {
+            s.push_str(&format!("\nLocal accessed: {}", atime.human()));
+        }
+        for (name, value) in &posix.xattrs {
+            s.push_str(&format!("\nXattr {}: {} bytes", name, value.len()));
+        }
+    }
     s
 }
 
 fn stat(
     global: &Arc<BlockingGlobal>,
     args: Vec<String>,
-    _path: &mut Vec<String>,
+    path: &mut Vec<String>,
     cwd: &mut Vec<Stored>,
     _clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if args.len() != 1 {
-        return Err("Usage: stat <name|.>".to_string());
+        return Err(Error::Invalid("Usage: stat <name|.>".to_string()));
     }
     let rt = Runtime::new().unwrap();
 
     if args[0] == "." {
-        if cwd.is_empty() {
-            let metadata: Metadata = rt.block_on(async {
-                let root = global.get_root();
-                root.metadata().await.clone()
-            });
-            println!("Type: Directory");
-            println!("{}", stat_format(&metadata));
-        } else {
-            let inode: InodeType = rt.block_on(cwd.last().unwrap().get(global.clone()))?;
-            let metadata: &Metadata = rt.block_on(inode.metadata());
-            println!("Type: Directory");
-            println!("{}", stat_format(metadata));
-        }
+        let dir = current_directory(global, path, cwd)?;
+        println!("Type: Directory");
+        println!("{}", stat_format(dir.metadata()));
     } else {
-        let dir = match cwd.last() {
-            Some(cwd) => {
-                let inode: InodeType = rt.block_on(cwd.get(global.clone()))?;
-                match inode {
-                    InodeType::Directory(dir) => dir,
-                    _ => Err("Not in a directory.".to_string())?,
-                }
-            }
-            None => global.get_root(),
-        };
-        let stored = dir.get(&args[0])?;
+        let dir = current_directory(global, path, cwd)?;
+        let stored = rt.block_on(dir.get(global.clone(), &args[0]))?;
         let inode: InodeType = rt.block_on(stored.get(global.clone()))?;
-        let metadata: &Metadata = rt.block_on(inode.metadata());
-        match inode {
+        let metadata: &Metadata = inode.metadata();
+        match &inode {
             InodeType::Directory(_) => println!("Type: Directory"),
-            InodeType::File(_) => println!("Type: File"),
+            InodeType::File(file) => {
+                println!("Type: File");
+                println!("Versions: {}", file.versions.len());
+            }
+            InodeType::Symlink(symlink) => {
+                println!("Type: Symlink");
+                println!("Target: {}", symlink.target);
+            }
+        }
+        if metadata.links > 1 {
+            println!("Links: {}", metadata.links);
         }
         println!("{}", stat_format(metadata));
     }
@@ -530,98 +600,116 @@ fn stat(
 fn upload(
     global: &Arc<BlockingGlobal>,
     args: Vec<String>,
-    _path: &mut Vec<String>,
+    path: &mut Vec<String>,
     cwd: &mut Vec<Stored>,
     _clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
-    if args.len() != 1 {
-        return Err("Usage: up <file>".to_string());
-    }
+) -> Result<(), Error> {
+    let (overwrite, file_path) = match args.as_slice() {
+        [file_path] => (false, file_path),
+        [flag, file_path] if flag == "-o" => (true, file_path),
+        _ => return Err(Error::Invalid("Usage: up [-o] <file>".to_string())),
+    };
 
-    match upload_file(global, cwd, args[0].as_str()) {
+    match upload_file(global, path, cwd, file_path.as_str(), overwrite) {
         Ok(bytes) => {
-            println!("Uploaded {} bytes to {}.", bytes, args[0]);
+            println!("Uploaded {} bytes to {}.", bytes, file_path);
             Ok(())
         }
         Err(err) => Err(err),
     }
 }
 
-fn upload_to_dir(
-    global: &Arc<BlockingGlobal>,
-    file_path: &str,
-    parent: &mut Directory,
-) -> Result<usize, String> {
+// The name to upload `file_path` as and its contents
+fn read_local_file(file_path: &str) -> Result<(String, Vec<u8>), Error> {
     let path = std::path::Path::new(file_path);
-    let file_name = path
-        .file_name()
-        .ok_or(format!("can't upload {}, it has no filename", file_path))?;
+    let file_name = path.file_name().ok_or(Error::Invalid(format!(
+        "can't upload {}, it has no filename",
+        file_path
+    )))?;
     let file = std::fs::File::open(shellexpand::tilde(file_path).as_ref())
-        .map_err(|_| "Failed to open file.")?;
+        .map_err(|e| Error::io("Failed to open file.", e))?;
     let mut reader = BufReader::new(file);
     let mut data = Vec::new();
 
     reader
         .read_to_end(&mut data)
-        .map_err(|_| "Failed to read file.")?;
+        .map_err(|e| Error::io("Failed to read file.", e))?;
 
-    let size = data.len();
+    Ok((file_name.to_string_lossy().as_ref().to_string(), data))
+}
+
+fn upload_to_dir(
+    global: &Arc<BlockingGlobal>,
+    file_path: &str,
+    parent: &mut Directory,
+) -> Result<Stored, Error> {
+    let (name, data) = read_local_file(file_path)?;
 
     let rt = Runtime::new().unwrap();
-    let file = rt.block_on(File::create(global.clone(), data))?;
-    rt.block_on(parent.add(
-        global.clone(),
-        &file_name.to_string_lossy().as_ref().to_string(),
-        file.to_enum(),
-    ))?;
-    Ok(size)
+    let mime = mime::detect(&name, &data);
+    let mut file = rt.block_on(File::create(global.clone(), data))?;
+    file.metadata.mime = mime;
+    file.metadata.posix = posix::capture(Path::new(file_path));
+    rt.block_on(parent.add(global.clone(), &name, file.to_enum()))
+}
+
+// Files with more than one name on disk, so each of them is only uploaded once
+#[cfg(unix)]
+fn hard_link_key(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    match metadata.nlink() > 1 {
+        true => Some((metadata.dev(), metadata.ino())),
+        false => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn hard_link_key(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
 }
 
 fn upload_file(
     global: &Arc<BlockingGlobal>,
+    path: &[String],
     cwd: &mut Vec<Stored>,
     file_path: &str,
-) -> Result<usize, String> {
-    let mut dir = match cwd.last() {
-        Some(cwd) => {
-            let rt = Runtime::new().unwrap();
-            let inode: InodeType = rt.block_on(cwd.get(global.clone()))?;
-            match inode {
-                InodeType::Directory(dir) => dir,
-                _ => Err("Not in a directory.".to_string())?,
-            }
-        }
-        None => global.get_root(),
+    overwrite: bool,
+) -> Result<usize, Error> {
+    // upload before locking, so the lock is only held while adding the entry
+    let (name, data) = read_local_file(file_path)?;
+    let size = data.len();
+    let rt = Runtime::new().unwrap();
+    let mime = mime::detect(&name, &data);
+    let mut file = rt.block_on(File::create(global.clone(), data))?;
+    file.metadata.mime = mime;
+    file.metadata.posix = posix::capture(Path::new(shellexpand::tilde(file_path).as_ref()));
+
+    let _lock = global.lock_directory(None)?;
+    let mut directory_path = open_cwd(global, path, cwd)?;
+    let directory = directory_path.directory();
+    match overwrite {
+        true => rt.block_on(directory.overwrite(global.clone(), &name, file))?,
+        false => rt.block_on(directory.add(global.clone(), &name, file.to_enum()))?,
     };
-    let size = upload_to_dir(global, file_path, &mut dir)?;
-    if cwd.is_empty() {
-        global.save_root(&dir);
-    } else {
-        let cwd = cwd.last_mut().unwrap();
-        let rt = Runtime::new().unwrap();
-        rt.block_on(async { cwd.put(global.clone(), dir.to_enum()).await })?;
-    }
+    commit_cwd(global, directory_path, cwd)?;
     Ok(size)
 }
 
 fn upload_tree(
     global: &Arc<BlockingGlobal>,
     args: Vec<String>,
-    _path: &mut Vec<String>,
+    path: &mut Vec<String>,
     cwd: &mut Vec<Stored>,
     _clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if args.len() != 1 {
-        return Err("Usage: up_tree <path/to/directory>".to_string());
+        return Err(Error::Invalid(
+            "Usage: up_tree <path/to/directory>".to_string(),
+        ));
     }
-    let expanded_path = shellexpand::tilde(&args[0].as_str()).as_ref().to_string();
+    let expanded_path = shellexpand::tilde(args[0].as_str()).as_ref().to_string();
     let parent_path = std::path::Path::new(&expanded_path);
     let count = WalkDir::new(parent_path).into_iter().count();
-    let root_parent: Stored = if cwd.is_empty() {
-        Err("can't up_tree directly in root, need to be in subfolder...".to_string())
-    } else {
-        Ok(cwd.last().unwrap().clone())
-    }?;
 
     let pb = ProgressBar::new(count as u64);
     pb.set_style(
@@ -630,15 +718,16 @@ fn upload_tree(
         )
         .unwrap(),
     );
+    // uploads the contents of fs_cwd into a new directory, nothing is linked into the tree until the end
     fn aux(
-        cwd: &Stored,
         fs_cwd: &std::path::Path,
         global: &Arc<BlockingGlobal>,
         pb: &ProgressBar,
+        hard_links: &mut HashMap<(u64, u64), Stored>,
         failed_files: &mut Vec<(String, String)>,
-    ) -> Result<(), String> {
+    ) -> Result<Directory, Error> {
         let entries = std::fs::read_dir(fs_cwd)
-            .map_err(|err| err.to_string())?
+            .map_err(|err| Error::io(fs_cwd.display(), err))?
             .filter_map(|_entry| {
                 if let Ok(entry) = _entry {
                     let file_name = entry.file_name();
@@ -654,26 +743,60 @@ fn upload_tree(
         let (directories, files): (Vec<_>, Vec<_>) = entries
             .filter(|entry| entry.file_type().is_ok())
             .partition(|entry| entry.file_type().map(|m| m.is_dir()).unwrap_or(false));
+        // symlinks are kept as links instead of uploading what they point to
+        let (symlinks, files): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .partition(|entry| entry.file_type().map(|m| m.is_symlink()).unwrap_or(false));
 
-        let mut parent_dir = {
-            let rt = Runtime::new().unwrap();
-            let inode: InodeType = rt.block_on(cwd.get(global.clone()))?;
-            match inode {
-                InodeType::Directory(dir) => Ok(dir),
-                _ => Err("Not in a directory.".to_string()),
-            }
-        }?;
+        let mut parent_dir = Directory::new();
         for file in files {
             let file_path = file.path();
             let file_name = file.file_name().to_string_lossy().as_ref().to_string();
-            pb.set_message(file_name);
+            pb.set_message(file_name.clone());
             pb.inc(1);
-            if let Err(err) = upload_to_dir(
-                global,
-                file_path.to_string_lossy().as_ref(),
-                &mut parent_dir,
-            ) {
-                failed_files.push((file_path.to_string_lossy().as_ref().to_string(), err));
+            let key = file.metadata().ok().as_ref().and_then(hard_link_key);
+            let uploaded = match key.and_then(|key| hard_links.get(&key)) {
+                Some(stored) => {
+                    let rt = Runtime::new().unwrap();
+                    rt.block_on(parent_dir.link(global.clone(), &file_name, stored.clone()))
+                }
+                None => upload_to_dir(
+                    global,
+                    file_path.to_string_lossy().as_ref(),
+                    &mut parent_dir,
+                )
+                .map(|stored| {
+                    if let Some(key) = key {
+                        hard_links.insert(key, stored);
+                    }
+                }),
+            };
+            if let Err(err) = uploaded {
+                failed_files.push((
+                    file_path.to_string_lossy().as_ref().to_string(),
+                    err.to_string(),
+                ));
+            }
+        }
+        for symlink in symlinks {
+            let link_path = symlink.path();
+            let link_name = symlink.file_name().to_string_lossy().as_ref().to_string();
+            pb.set_message(link_name.clone());
+            pb.inc(1);
+            let uploaded = std::fs::read_link(&link_path)
+                .map_err(|err| Error::io("Failed to read symlink", err))
+                .and_then(|target| {
+                    let mut symlink = Symlink::new(target.to_string_lossy().as_ref().to_string());
+                    symlink.metadata.posix = posix::capture(&link_path);
+                    let rt = Runtime::new().unwrap();
+                    rt.block_on(parent_dir.add(global.clone(), &link_name, symlink.to_enum()))
+                        .map(|_| ())
+                });
+            if let Err(err) = uploaded {
+                failed_files.push((
+                    link_path.to_string_lossy().as_ref().to_string(),
+                    err.to_string(),
+                ));
             }
         }
         for dir in directories {
@@ -681,17 +804,45 @@ fn upload_tree(
             let dir_name = dir.file_name().to_string_lossy().as_ref().to_string();
             pb.set_message(dir_name.clone());
             pb.inc(1);
-            let new_cwd = mkdir_in_dir(global, &mut parent_dir, dir_name.as_str())?;
-            aux(&new_cwd, &dir_path, global, pb, failed_files)?;
-        }
-        {
-            let rt = Runtime::new().unwrap();
-            rt.block_on(async { cwd.put(global.clone(), &parent_dir.to_enum()).await })?;
+            let uploaded =
+                aux(&dir_path, global, pb, hard_links, failed_files).and_then(|mut contents| {
+                    contents.metadata.posix = posix::capture(&dir_path);
+                    let rt = Runtime::new().unwrap();
+                    rt.block_on(parent_dir.add(global.clone(), &dir_name, contents.to_enum()))
+                        .map(|_| ())
+                });
+            if let Err(err) = uploaded {
+                failed_files.push((
+                    dir_path.to_string_lossy().as_ref().to_string(),
+                    err.to_string(),
+                ));
+            }
         }
-        Ok(())
+        Ok(parent_dir)
     }
     let mut failed_files = Vec::new();
-    let res = aux(&root_parent, parent_path, global, &pb, &mut failed_files);
+    let res = aux(
+        parent_path,
+        global,
+        &pb,
+        &mut HashMap::new(),
+        &mut failed_files,
+    )
+    .and_then(|uploaded| {
+        let _lock = global.lock_directory(None)?;
+        let mut directory_path = open_cwd(global, path, cwd)?;
+        let rt = Runtime::new().unwrap();
+        for (name, stored) in rt.block_on(uploaded.list_tuples(global.clone()))? {
+            if let Err(err) = rt.block_on(directory_path.directory().put(
+                global.clone(),
+                &name,
+                stored,
+            )) {
+                failed_files.push((name, err.to_string()));
+            }
+        }
+        commit_cwd(global, directory_path, cwd)
+    });
     if !failed_files.is_empty() {
         println!("Failed to upload: ");
         for (file, err) in failed_files {
@@ -704,46 +855,503 @@ fn upload_tree(
 fn download(
     global: &Arc<BlockingGlobal>,
     args: Vec<String>,
-    _path: &mut Vec<String>,
+    path: &mut Vec<String>,
     cwd: &mut Vec<Stored>,
     _clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
-    if args.len() != 2 {
-        return Err("Usage: down <from> <to>".to_string());
+) -> Result<(), Error> {
+    if args.len() != 2 && args.len() != 3 {
+        return Err(Error::Invalid(
+            "Usage: down <from> <to> [version]".to_string(),
+        ));
     }
 
     let rt = Runtime::new().unwrap();
-    let dir = match cwd.last() {
-        Some(cwd) => {
-            let inode: InodeType = rt.block_on(cwd.get(global.clone()))?;
-            match inode {
-                InodeType::Directory(dir) => dir,
-                _ => Err("Not in a directory.".to_string())?,
-            }
-        }
-        None => global.get_root(),
-    };
+    let dir = current_directory(global, path, cwd)?;
 
-    let stored = dir.get(&args[0])?;
+    let stored = rt.block_on(dir.get(global.clone(), &args[0]))?;
     let inode: InodeType = rt.block_on(stored.get(global.clone()))?;
     let file = match inode {
         InodeType::File(file) => file,
-        _ => Err("Not a file.".to_string())?,
+        _ => Err(Error::Invalid("Not a file.".to_string()))?,
+    };
+    let (metadata, mut stream) = match args.get(2) {
+        Some(number) => {
+            let number = number
+                .parse()
+                .map_err(|_| Error::Invalid("Invalid version.".to_string()))?;
+            let version = file.version(number)?;
+            (&version.metadata, version.get(global.clone()))
+        }
+        None => (file.metadata(), file.get(global.clone())),
     };
-    let metadata = rt.block_on(file.metadata());
     println!("Downloading {}...", metadata.size.human());
+    write_local_file(&rt, &mut stream, Path::new(&args[1]))?;
+    println!("Downloaded to {}.", args[1]);
+
+    Ok(())
+}
+
+fn write_local_file(
+    rt: &Runtime,
+    stream: &mut BoxStream<'_, Result<Vec<u8>, Error>>,
+    file_path: &Path,
+) -> Result<(), Error> {
     let mut buf_writer = std::io::BufWriter::new(
-        std::fs::File::create(&args[1]).map_err(|_| "Failed to create file.")?,
+        std::fs::File::create(file_path).map_err(|e| Error::io("Failed to create file.", e))?,
     );
-    let mut stream = file.get(global.clone());
     while let Some(chunk) = rt.block_on(stream.next()) {
-        let slice = chunk.map_err(|_| "Failed to read file.")?;
+        let slice = chunk.map_err(|e| e.context("Failed to read file."))?;
         buf_writer
             .write_all(&slice)
-            .map_err(|_| "Failed to write file.")?;
+            .map_err(|e| Error::io("Failed to write file.", e))?;
     }
-    println!("Downloaded to {}.", args[1]);
+    buf_writer
+        .flush()
+        .map_err(|e| Error::io("Failed to write file.", e))
+}
+
+#[cfg(unix)]
+fn create_local_symlink(target: &str, link_path: &Path) -> Result<(), Error> {
+    std::os::unix::fs::symlink(target, link_path)
+        .map_err(|err| Error::io("Failed to create symlink", err))
+}
+
+#[cfg(not(unix))]
+fn create_local_symlink(_target: &str, _link_path: &Path) -> Result<(), Error> {
+    Err(Error::Invalid(
+        "symlinks are not supported here".to_string(),
+    ))
+}
+
+fn download_tree(
+    global: &Arc<BlockingGlobal>,
+    args: Vec<String>,
+    path: &mut Vec<String>,
+    cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    if args.len() != 2 {
+        return Err(Error::Invalid(
+            "Usage: down_tree <name|.> <path/to/directory>".to_string(),
+        ));
+    }
+    let rt = Runtime::new().unwrap();
+    let dir = current_directory(global, path, cwd)?;
+    let directory = match args[0].as_str() {
+        "." => dir,
+        _ => match rt.block_on(
+            rt.block_on(dir.get(global.clone(), &args[0]))?
+                .get(global.clone()),
+        )? {
+            InodeType::Directory(directory) => directory,
+            _ => Err(Error::Invalid("Not a directory.".to_string()))?,
+        },
+    };
+    let parent_path = PathBuf::from(shellexpand::tilde(args[1].as_str()).as_ref());
+    std::fs::create_dir_all(&parent_path)
+        .map_err(|err| Error::io("Failed to create directory", err))?;
 
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] {msg} ({pos})").unwrap(),
+    );
+    // writes the contents of `directory` into fs_cwd, which already exists
+    fn aux(
+        directory: &Directory,
+        fs_cwd: &Path,
+        global: &Arc<BlockingGlobal>,
+        rt: &Runtime,
+        pb: &ProgressBar,
+        hard_links: &mut HashMap<String, PathBuf>,
+        failed_files: &mut Vec<(String, String)>,
+    ) {
+        let entries = match rt.block_on(directory.list_tuples(global.clone())) {
+            Ok(entries) => entries,
+            Err(err) => {
+                failed_files.push((
+                    fs_cwd.to_string_lossy().as_ref().to_string(),
+                    err.to_string(),
+                ));
+                return;
+            }
+        };
+        for (name, stored) in entries {
+            let local_path = fs_cwd.join(&name);
+            let display = local_path.to_string_lossy().as_ref().to_string();
+            // names are only ever one component on disk
+            let mut components = Path::new(&name).components();
+            match (components.next(), components.next()) {
+                (Some(std::path::Component::Normal(_)), None) => (),
+                _ => {
+                    failed_files.push((display, "not a valid file name".to_string()));
+                    continue;
+                }
+            }
+            pb.set_message(name.clone());
+            pb.inc(1);
+            // the other names of a hard linked inode link to the first one written
+            if let Some(first) = hard_links.get(&stored.as_url()) {
+                if let Err(err) = std::fs::hard_link(first, &local_path) {
+                    failed_files.push((display, err.to_string()));
+                }
+                continue;
+            }
+            let inode = match rt.block_on(stored.get::<InodeType, _>(global.clone())) {
+                Ok(inode) => inode,
+                Err(err) => {
+                    failed_files.push((display, err.to_string()));
+                    continue;
+                }
+            };
+            let written = match &inode {
+                InodeType::File(file) => {
+                    write_local_file(rt, &mut file.get(global.clone()), &local_path)
+                }
+                InodeType::Symlink(symlink) => create_local_symlink(&symlink.target, &local_path),
+                InodeType::Directory(contents) => std::fs::create_dir_all(&local_path)
+                    .map_err(|err| Error::io("Failed to create directory", err))
+                    .map(|_| {
+                        aux(
+                            contents,
+                            &local_path,
+                            global,
+                            rt,
+                            pb,
+                            hard_links,
+                            failed_files,
+                        )
+                    }),
+            };
+            if let Err(err) = written {
+                failed_files.push((display, err.to_string()));
+                continue;
+            }
+            let metadata = inode.metadata();
+            if metadata.links > 1 {
+                hard_links.insert(stored.as_url(), local_path.clone());
+            }
+            // directories only after their contents, writing those changes them
+            if let Some(posix) = &metadata.posix {
+                let symlink = matches!(inode, InodeType::Symlink(_));
+                for err in posix::apply(&local_path, posix, symlink) {
+                    failed_files.push((display.clone(), err));
+                }
+            }
+        }
+    }
+    let mut failed_files = Vec::new();
+    aux(
+        &directory,
+        &parent_path,
+        global,
+        &rt,
+        &pb,
+        &mut HashMap::new(),
+        &mut failed_files,
+    );
+    if let Some(posix) = &directory.metadata.posix {
+        for err in posix::apply(&parent_path, posix, false) {
+            failed_files.push((parent_path.to_string_lossy().as_ref().to_string(), err));
+        }
+    }
+    pb.finish_and_clear();
+    if !failed_files.is_empty() {
+        println!("Failed to download: ");
+        for (file, err) in failed_files {
+            println!("{} -> {}", file, err);
+        }
+    }
+    println!("Downloaded to {}.", parent_path.display());
+    Ok(())
+}
+
+fn versions(
+    global: &Arc<BlockingGlobal>,
+    args: Vec<String>,
+    path: &mut Vec<String>,
+    cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    if args.len() != 1 {
+        return Err(Error::Invalid("Usage: versions <name>".to_string()));
+    }
+    let rt = Runtime::new().unwrap();
+    let dir = current_directory(global, path, cwd)?;
+    let inode: InodeType = rt.block_on(
+        rt.block_on(dir.get(global.clone(), &args[0]))?
+            .get(global.clone()),
+    )?;
+    let file = match inode {
+        InodeType::File(file) => file,
+        _ => Err(Error::Invalid("Not a file.".to_string()))?,
+    };
+    println!(
+        "  {:<8} {:<20} {:<12} Replaced",
+        "Version", "Modified", "Size"
+    );
+    for (index, version) in file.versions.iter().enumerate() {
+        println!(
+            "  {:<8} {:<20} {:<12} {}",
+            index + 1,
+            version.metadata.human_modified(),
+            version.metadata.size.human(),
+            version.human_replaced()
+        );
+    }
+    Ok(())
+}
+
+// Applies one of File's partial writes to the file `name` in the current directory, returns its new size
+fn edit_file(
+    global: &Arc<BlockingGlobal>,
+    path: &[String],
+    cwd: &mut Vec<Stored>,
+    name: &String,
+    edit: impl FnOnce(&Runtime, &mut File) -> Result<Vec<Stored>, Error>,
+) -> Result<Size, Error> {
+    let _lock = global.lock_directory(None)?;
+    let rt = Runtime::new().unwrap();
+    let mut directory_path = open_cwd(global, path, cwd)?;
+    let stored = rt.block_on(directory_path.directory().get(global.clone(), name))?;
+    let mut file = match rt.block_on(stored.get::<InodeType, _>(global.clone()))? {
+        InodeType::File(file) => file,
+        _ => return Err(Error::Invalid("Not a file.".to_string())),
+    };
+    let replaced = edit(&rt, &mut file)?;
+    let size = file.metadata.size.clone();
+    rt.block_on(
+        directory_path
+            .directory()
+            .update_file(global.clone(), name, file, replaced),
+    )?;
+    commit_cwd(global, directory_path, cwd)?;
+    Ok(size)
+}
+
+fn write_at(
+    global: &Arc<BlockingGlobal>,
+    args: Vec<String>,
+    path: &mut Vec<String>,
+    cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    if args.len() != 3 {
+        return Err(Error::Invalid(
+            "Usage: write <name> <offset> <file>".to_string(),
+        ));
+    }
+    let offset = args[1]
+        .parse()
+        .map_err(|_| Error::Invalid("Invalid offset.".to_string()))?;
+    let (_, data) = read_local_file(&args[2])?;
+    let size = edit_file(global, path, cwd, &args[0], |rt, file| {
+        rt.block_on(file.write_at(global.clone(), offset, data))
+    })?;
+    println!("{} is now {}.", args[0], size.human());
+    Ok(())
+}
+
+fn append(
+    global: &Arc<BlockingGlobal>,
+    args: Vec<String>,
+    path: &mut Vec<String>,
+    cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    if args.len() != 2 {
+        return Err(Error::Invalid("Usage: append <name> <file>".to_string()));
+    }
+    let (_, data) = read_local_file(&args[1])?;
+    let size = edit_file(global, path, cwd, &args[0], |rt, file| {
+        rt.block_on(file.append(global.clone(), data))
+    })?;
+    println!("{} is now {}.", args[0], size.human());
+    Ok(())
+}
+
+fn truncate(
+    global: &Arc<BlockingGlobal>,
+    args: Vec<String>,
+    path: &mut Vec<String>,
+    cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    if args.len() != 2 {
+        return Err(Error::Invalid("Usage: truncate <name> <size>".to_string()));
+    }
+    let size = args[1]
+        .parse()
+        .map_err(|_| Error::Invalid("Invalid size.".to_string()))?;
+    let size = edit_file(global, path, cwd, &args[0], |rt, file| {
+        rt.block_on(file.truncate(global.clone(), size))
+    })?;
+    println!("{} is now {}.", args[0], size.human());
+    Ok(())
+}
+
+fn revert(
+    global: &Arc<BlockingGlobal>,
+    args: Vec<String>,
+    path: &mut Vec<String>,
+    cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    if args.len() != 2 {
+        return Err(Error::Invalid("Usage: revert <name> <version>".to_string()));
+    }
+    let number = args[1]
+        .parse()
+        .map_err(|_| Error::Invalid("Invalid version.".to_string()))?;
+    let _lock = global.lock_directory(None)?;
+    let rt = Runtime::new().unwrap();
+    let mut directory_path = open_cwd(global, path, cwd)?;
+    rt.block_on(
+        directory_path
+            .directory()
+            .restore_version(global.clone(), &args[0], number),
+    )?;
+    commit_cwd(global, directory_path, cwd)?;
+    println!("Restored version {} of {}.", number, args[0]);
+    Ok(())
+}
+
+fn snapshot(
+    global: &Arc<BlockingGlobal>,
+    args: Vec<String>,
+    _path: &mut Vec<String>,
+    _cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    if args.len() != 1 {
+        return Err(Error::Invalid("Usage: snap <name>".to_string()));
+    }
+    let snapshot = global.create_snapshot(&args[0])?;
+    println!("Snapshot {} taken.", snapshot.name);
+    Ok(())
+}
+
+fn list_snapshots(
+    global: &Arc<BlockingGlobal>,
+    args: Vec<String>,
+    _path: &mut Vec<String>,
+    _cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    if args.is_empty() {
+        println!("  {:<20} Created", "Name");
+        for snapshot in global.snapshots()? {
+            println!("  {:<20} {}", snapshot.name, snapshot.human_created());
+        }
+        return Ok(());
+    }
+    if args.len() > 2 {
+        return Err(Error::Invalid(
+            "Usage: lssnap [<name> [path/to/directory]]".to_string(),
+        ));
+    }
+
+    let names = args
+        .get(1)
+        .map(|path| {
+            path.split('/')
+                .filter(|name| !name.is_empty())
+                .map(|name| name.to_string())
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+    let root = global.snapshot_root(&args[0])?;
+    let rt = Runtime::new().unwrap();
+    let mut directory_path = rt.block_on(root.open_path(global.clone(), &names))?;
+    for name in rt.block_on(directory_path.directory().list(global.clone()))? {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+fn restore_snapshot(
+    global: &Arc<BlockingGlobal>,
+    args: Vec<String>,
+    path: &mut Vec<String>,
+    cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    if args.len() != 1 {
+        return Err(Error::Invalid("Usage: restore <name>".to_string()));
+    }
+    global.restore_snapshot(&args[0])?;
+    // the current directory may not exist in the snapshot
+    path.clear();
+    cwd.clear();
+    println!("Restored snapshot {}.", args[0]);
+    Ok(())
+}
+
+fn delete_snapshot(
+    global: &Arc<BlockingGlobal>,
+    args: Vec<String>,
+    _path: &mut Vec<String>,
+    _cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    if args.len() != 1 {
+        return Err(Error::Invalid("Usage: rmsnap <name>".to_string()));
+    }
+    global.delete_snapshot(&args[0])?;
+    println!("Deleted snapshot {}.", args[0]);
+    Ok(())
+}
+
+fn list_trash(
+    global: &Arc<BlockingGlobal>,
+    _args: Vec<String>,
+    _path: &mut Vec<String>,
+    _cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    println!("  {:<6} {:<20} Path", "Id", "Deleted");
+    for entry in global.trash()? {
+        println!(
+            "  {:<6} {:<20} {}",
+            entry.id,
+            entry.human_deleted(),
+            entry.original_path()
+        );
+    }
+    Ok(())
+}
+
+fn untrash(
+    global: &Arc<BlockingGlobal>,
+    args: Vec<String>,
+    _path: &mut Vec<String>,
+    _cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    if args.len() != 1 {
+        return Err(Error::Invalid("Usage: untrash <id>".to_string()));
+    }
+    let id = args[0]
+        .parse()
+        .map_err(|_| Error::Invalid("Invalid id.".to_string()))?;
+    let entry = global.restore_from_trash(id)?;
+    println!("Restored {}.", entry.original_path());
+    Ok(())
+}
+
+fn purge(
+    global: &Arc<BlockingGlobal>,
+    args: Vec<String>,
+    _path: &mut Vec<String>,
+    _cwd: &mut Vec<Stored>,
+    _clipboard: &mut Option<Stored>,
+) -> Result<(), Error> {
+    let all = match args.as_slice() {
+        [] => false,
+        [flag] if flag == "-a" => true,
+        _ => return Err(Error::Invalid("Usage: purge [-a]".to_string())),
+    };
+    let purged = global.purge_trash(all)?;
+    println!("Purged {} entries.", purged);
     Ok(())
 }
 
@@ -753,10 +1361,10 @@ fn bucket_list(
     _path: &mut Vec<String>,
     _cwd: &mut Vec<Stored>,
     _clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     println!(
-        "  {:<20} {:<20} {:<20} {}",
-        "Name", "Source", "Encryption", "Max block size"
+        "  {:<20} {:<20} {:<20} Max block size",
+        "Name", "Source", "Encryption"
     );
     for bucket in global.list_buckets() {
         let b_type = match global.get_bucket(bucket) {
@@ -774,13 +1382,13 @@ fn bucket_test(
     _path: &mut Vec<String>,
     _cwd: &mut Vec<Stored>,
     _clipboard: &mut Option<Stored>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if args.len() != 1 {
-        return Err("Usage: bktest <name>".to_string());
+        return Err(Error::Invalid("Usage: bktest <name>".to_string()));
     }
     let bucket = match global.get_bucket(&args[0]) {
         Some(bucket) => bucket,
-        None => Err("No such bucket.".to_string())?,
+        None => Err(Error::NotFound("No such bucket.".to_string()))?,
     };
 
     let block = vec![0; bucket.max_size()];
@@ -799,14 +1407,14 @@ fn bucket_test(
     println!("Deleted data.");
 
     if block != retrieved {
-        return Err("Data mismatch.".to_string());
+        return Err(Error::Corrupted("Data mismatch.".to_string()));
     } else {
         println!("Data matches.");
     }
 
     let recieved2 = rt.block_on(bucket.get(&descriptor));
     if recieved2.is_ok() {
-        return Err("Data still exists.".to_string());
+        return Err(Error::Internal("Data still exists.".to_string()));
     }
     println!("Deleted data was not found.");
 
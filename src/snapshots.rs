@@ -0,0 +1,262 @@
+/*
+   Snapshots are named, read-only copies of the whole tree.
+   Taking one stores a copy of the root and pins every chunk reachable from it. Deletes skip pinned chunks
+   (GlobalTrait::is_pinned), and directories are copy-on-write, so nothing the live tree does changes a snapshot.
+   Every pin counts the snapshots using the chunk, deleting the last of them frees the chunks the live tree
+   doesn't use either. Snapshots and their pins are kept next to root_path, in `<root_path>.snapshots`.
+*/
+
+use rmp_serde::Deserializer;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    error::Error,
+    global::GlobalTrait,
+    inodes::{
+        directory::Directory,
+        inode::{Inode, InodeType},
+    },
+    root_file::write_atomic,
+    stored::Stored,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    #[serde(rename = "n")]
+    pub name: String,
+    #[serde(rename = "c")]
+    pub created: u64,
+    #[serde(rename = "r")]
+    pub root: Stored, // a copy of the root directory
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Snapshots {
+    #[serde(rename = "s")]
+    snapshots: Vec<Snapshot>,
+    #[serde(rename = "p")]
+    pins: HashMap<String, u32>, // Stored::as_url of every chunk a snapshot uses, and how many do
+}
+
+impl Snapshot {
+    pub fn human_created(&self) -> String {
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(self.created);
+        let datetime: chrono::DateTime<chrono::Utc> = time.into();
+        datetime.format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+}
+
+fn snapshots_path(root_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.snapshots", root_path))
+}
+
+impl Snapshots {
+    pub fn load(root_path: &str) -> Result<Self, Error> {
+        let data = match fs::read(snapshots_path(root_path)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(Error::io("Error reading snapshots", e)),
+        };
+        Self::deserialize(&mut Deserializer::new(&data[..]))
+            .map_err(|e| Error::deserialize("Error parsing snapshots", e))
+    }
+
+    fn save(&self, root_path: &str) -> Result<(), Error> {
+        write_atomic(&snapshots_path(root_path), &Stored::serialize(self)?)
+    }
+
+    pub fn list(&self) -> &[Snapshot] {
+        &self.snapshots
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Snapshot, Error> {
+        self.snapshots
+            .iter()
+            .find(|snapshot| snapshot.name == name)
+            .ok_or_else(|| Error::NotFound(format!("Snapshot {} does not exist", name)))
+    }
+
+    pub fn is_pinned(&self, stored: &Stored) -> bool {
+        self.pins.contains_key(&stored.as_url())
+    }
+
+    fn pin(&mut self, chunks: &[Stored]) {
+        for chunk in chunks {
+            *self.pins.entry(chunk.as_url()).or_default() += 1;
+        }
+    }
+
+    // Returns the chunks no snapshot uses anymore
+    fn unpin(&mut self, chunks: Vec<Stored>) -> Vec<Stored> {
+        let mut unpinned = Vec::new();
+        for chunk in chunks {
+            let url = chunk.as_url();
+            match self.pins.get_mut(&url) {
+                Some(count) if *count > 1 => *count -= 1,
+                Some(_) => {
+                    self.pins.remove(&url);
+                    unpinned.push(chunk);
+                }
+                None => (),
+            }
+        }
+        unpinned
+    }
+}
+
+// Keeps the snapshots file parsed until it changes, deletes ask for every chunk
+#[derive(Debug, Default)]
+pub struct PinCache(Mutex<Option<(SystemTime, u64, Arc<Snapshots>)>>);
+
+impl PinCache {
+    // Unreadable snapshots pin everything, a chunk is better leaked than lost
+    pub fn is_pinned(&self, root_path: &str, stored: &Stored) -> bool {
+        let path = snapshots_path(root_path);
+        let (modified, len) = match fs::metadata(&path) {
+            Ok(metadata) => (metadata.modified().unwrap_or(UNIX_EPOCH), metadata.len()),
+            Err(e) if e.kind() == ErrorKind::NotFound => return false,
+            Err(_) => return true,
+        };
+        let mut cache = self.0.lock().unwrap();
+        let snapshots = match cache.as_ref() {
+            Some((m, l, snapshots)) if *m == modified && *l == len => snapshots.clone(),
+            _ => match Snapshots::load(root_path) {
+                Ok(snapshots) => {
+                    let snapshots = Arc::new(snapshots);
+                    *cache = Some((modified, len, snapshots.clone()));
+                    snapshots
+                }
+                Err(_) => return true,
+            },
+        };
+        snapshots.is_pinned(stored)
+    }
+}
+
+async fn chunk_set<U: GlobalTrait + Send + Sync>(
+    global: Arc<U>,
+    roots: &[&Directory],
+) -> Result<HashSet<String>, Error> {
+    let mut chunks = HashSet::new();
+    for root in roots {
+        for chunk in root.chunks(global.clone()).await? {
+            chunks.insert(chunk.as_url());
+        }
+    }
+    Ok(chunks)
+}
+
+pub async fn create<U: GlobalTrait + Send + Sync>(
+    global: Arc<U>,
+    root_path: &str,
+    name: &str,
+    root: &Directory,
+) -> Result<Snapshot, Error> {
+    let mut snapshots = Snapshots::load(root_path)?;
+    if snapshots.get(name).is_ok() {
+        return Err(Error::AlreadyExists(format!(
+            "Snapshot {} already exists",
+            name
+        )));
+    }
+
+    let mut chunks = root.chunks(global.clone()).await?;
+    let snapshot = Snapshot {
+        name: name.to_string(),
+        created: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        root: Stored::create(global.clone(), root.clone().to_enum()).await?,
+    };
+    chunks.push(snapshot.root.clone());
+    snapshots.pin(&chunks);
+    snapshots.snapshots.push(snapshot.clone());
+    if let Err(e) = snapshots.save(root_path) {
+        let _ = snapshot.root.delete(global).await;
+        return Err(e);
+    }
+    Ok(snapshot)
+}
+
+pub async fn root<U: GlobalTrait + Send + Sync>(
+    global: Arc<U>,
+    snapshot: &Snapshot,
+) -> Result<Directory, Error> {
+    match snapshot.root.get::<InodeType, U>(global).await? {
+        InodeType::Directory(root) => Ok(root),
+        _ => Err(Error::Corrupted(format!(
+            "Snapshot {} is not a directory",
+            snapshot.name
+        ))),
+    }
+}
+
+// `live` is the current root and whatever else still uses chunks (the trash),
+// what they use stays when no snapshot pins it anymore
+pub async fn delete<U: GlobalTrait + Send + Sync>(
+    global: Arc<U>,
+    root_path: &str,
+    name: &str,
+    live: &[&Directory],
+) -> Result<(), Error> {
+    let mut snapshots = Snapshots::load(root_path)?;
+    let snapshot = snapshots.get(name)?.clone();
+    let mut chunks = root(global.clone(), &snapshot)
+        .await?
+        .chunks(global.clone())
+        .await?;
+    chunks.push(snapshot.root.clone());
+    let live = chunk_set(global.clone(), live).await?;
+
+    // the chunks are unpinned before they are deleted, a crash in between only leaks them
+    snapshots.snapshots.retain(|s| s.name != name);
+    let unpinned = snapshots.unpin(chunks);
+    snapshots.save(root_path)?;
+
+    let mut errors = Vec::new();
+    let mut freed = HashSet::new();
+    for chunk in unpinned {
+        if live.contains(&chunk.as_url()) || !freed.insert(chunk.as_url()) {
+            continue;
+        }
+        if let Err(e) = chunk.delete(global.clone()).await {
+            errors.push(e);
+        }
+    }
+    match errors.len() {
+        0 => Ok(()),
+        _ => Err(Error::many(errors)),
+    }
+}
+
+// After the root changed from `old` to `new` wholesale, frees what only `old` used
+pub async fn free_unreachable<U: GlobalTrait + Send + Sync>(
+    global: Arc<U>,
+    old: &Directory,
+    new: &Directory,
+) -> Result<(), Error> {
+    let new = chunk_set(global.clone(), &[new]).await?;
+    let mut errors = Vec::new();
+    let mut freed = HashSet::new();
+    for chunk in old.chunks(global.clone()).await? {
+        if new.contains(&chunk.as_url()) || !freed.insert(chunk.as_url()) {
+            continue;
+        }
+        if let Err(e) = chunk.delete(global.clone()).await {
+            errors.push(e);
+        }
+    }
+    match errors.len() {
+        0 => Ok(()),
+        _ => Err(Error::many(errors)),
+    }
+}
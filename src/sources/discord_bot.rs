@@ -0,0 +1,325 @@
+/*
+   Stores each chunk as an attachment of a message posted by a bot.
+   Messages are spread over the configured channels, descriptors are "<channel id>/<message id>".
+   Attachment urls expire, so refreshed urls are cached together with their expiry time.
+*/
+
+use async_trait::async_trait;
+use reqwest::{header::AUTHORIZATION, Client, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::source::Source;
+use crate::{error::Error, global::Descriptor};
+
+#[derive(Debug, Deserialize)]
+pub struct DiscordBot {
+    token: String,
+    channels: Vec<String>,
+    #[serde(default = "default_api_url")]
+    api_url: String,
+    #[serde(default = "default_max_size")]
+    max_size: usize,
+    #[serde(default = "default_max_retries")]
+    max_retries: usize,
+
+    #[serde(skip)]
+    client: Client,
+    #[serde(skip)]
+    next_channel: AtomicUsize,
+    #[serde(skip)]
+    urls: Mutex<HashMap<Descriptor, (String, u64)>>, // descriptor -> (attachment url, expiry as unix seconds)
+    #[serde(skip)]
+    limits: Mutex<HashMap<String, Instant>>, // route -> instant when the route can be used again
+}
+
+fn default_api_url() -> String {
+    "https://discord.com/api/v10".to_string()
+}
+const fn default_max_size() -> usize {
+    1024 * 1024 * 10 // upload limit of servers without boosts
+}
+const fn default_max_retries() -> usize {
+    5
+}
+
+// urls without an expiry are refreshed after this many seconds
+const DEFAULT_URL_TTL: u64 = 60 * 60;
+
+/* #region discord schema */
+#[derive(Deserialize)]
+struct MessageResponse {
+    id: String,
+    attachments: Vec<MessageAttachment>,
+}
+
+#[derive(Deserialize)]
+struct MessageAttachment {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct RateLimitResponse {
+    retry_after: f64,
+}
+/* #endregion */
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// Discord cdn urls carry their expiry as a hex unix timestamp in the `ex` query parameter
+pub(crate) fn attachment_expiry(url: &str) -> Option<u64> {
+    let query = url.split_once('?')?.1;
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "ex")
+        .and_then(|(_, value)| u64::from_str_radix(value, 16).ok())
+}
+
+// Rate limits are per route, where snowflakes other than the channel (the major parameter) are ignored
+pub(crate) fn route_key(method: &str, path: &str) -> String {
+    let mut parts = path.split('/').collect::<Vec<&str>>();
+    for i in 0..parts.len() {
+        if i > 0 && parts[i - 1] != "channels" && parts[i].chars().all(|c| c.is_ascii_digit()) {
+            parts[i] = ":id";
+        }
+    }
+    format!("{} {}", method, parts.join("/"))
+}
+
+fn parse_descriptor(descriptor: &Descriptor) -> Result<(&str, &str), Error> {
+    std::str::from_utf8(descriptor)
+        .map_err(|e| Error::Invalid(format!("Error parsing descriptor: {}", e)))?
+        .split_once('/')
+        .ok_or(Error::Invalid(
+            "Error parsing descriptor: missing channel".to_string(),
+        ))
+}
+
+impl DiscordBot {
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.api_url.trim_end_matches('/'), path)
+    }
+
+    async fn wait_for_route(&self, route: &str) {
+        let until = self.limits.lock().unwrap().get(route).cloned();
+        if let Some(until) = until {
+            let now = Instant::now();
+            if until > now {
+                tokio::time::sleep(until - now).await;
+            }
+        }
+    }
+
+    fn update_route(&self, route: &str, response: &Response) {
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<f64>().ok())
+        };
+        let mut limits = self.limits.lock().unwrap();
+        match (
+            header("x-ratelimit-remaining"),
+            header("x-ratelimit-reset-after"),
+        ) {
+            (Some(remaining), Some(reset_after)) if remaining < 1.0 => {
+                limits.insert(
+                    route.to_string(),
+                    Instant::now() + Duration::from_secs_f64(reset_after),
+                );
+            }
+            _ => {
+                limits.remove(route);
+            }
+        }
+    }
+
+    // Sends a request built by `build`, waiting for the route's rate limit and retrying when we get limited anyway.
+    // The request has to be rebuilt for every attempt, because multipart bodies can't be cloned.
+    async fn send<F>(&self, method: &str, path: &str, build: F) -> Result<Response, Error>
+    where
+        F: Fn(&Client, String) -> Result<RequestBuilder, Error> + Send + Sync,
+    {
+        let route = route_key(method, path);
+        for _ in 0..=self.max_retries {
+            self.wait_for_route(&route).await;
+            let response = build(&self.client, self.url(path))?
+                .header(AUTHORIZATION, format!("Bot {}", self.token))
+                .send()
+                .await
+                .map_err(|e| Error::http("Error sending request", e))?;
+            self.update_route(&route, &response);
+            if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                let status = response.status();
+                if !status.is_success() {
+                    return Err(Error::status(
+                        status,
+                        format!(
+                            "Discord returned {}: {}",
+                            status,
+                            response.text().await.unwrap_or_default()
+                        ),
+                    ));
+                }
+                return Ok(response);
+            }
+            let retry_after = response
+                .json::<RateLimitResponse>()
+                .await
+                .map(|limit| limit.retry_after)
+                .unwrap_or(1.0);
+            tokio::time::sleep(Duration::from_secs_f64(retry_after)).await;
+        }
+        Err(Error::Unavailable(format!(
+            "Rate limited too many times on {}",
+            route
+        )))
+    }
+
+    fn attachment_form(
+        data: Vec<u8>,
+        payload: serde_json::Value,
+    ) -> Result<reqwest::multipart::Form, Error> {
+        let data_part = reqwest::multipart::Part::bytes(data)
+            .file_name("d")
+            .mime_str("application/octet-stream")
+            .map_err(|e| Error::http("Error creating part", e))?;
+        let payload_part = reqwest::multipart::Part::text(payload.to_string())
+            .mime_str("application/json")
+            .map_err(|e| Error::http("Error creating part", e))?;
+        Ok(reqwest::multipart::Form::new()
+            .part("payload_json", payload_part)
+            .part("files[0]", data_part))
+    }
+
+    async fn attachment_url(&self, descriptor: &Descriptor) -> Result<String, Error> {
+        if let Some((url, expiry)) = self.urls.lock().unwrap().get(descriptor) {
+            if *expiry > now() {
+                return Ok(url.clone());
+            }
+        }
+
+        let (channel, message) = parse_descriptor(descriptor)?;
+        let path = format!("channels/{}/messages/{}", channel, message);
+        let parsed = self
+            .send("GET", &path, |client, url| Ok(client.get(url)))
+            .await?
+            .json::<MessageResponse>()
+            .await
+            .map_err(|e| Error::http("Error parsing response", e))?;
+        let url = parsed
+            .attachments
+            .first()
+            .ok_or(Error::NotFound("No attachments found".to_string()))?
+            .url
+            .clone();
+
+        let expiry = attachment_expiry(&url).unwrap_or(now() + DEFAULT_URL_TTL);
+        self.urls
+            .lock()
+            .unwrap()
+            .insert(descriptor.clone(), (url.clone(), expiry));
+        Ok(url)
+    }
+}
+
+#[async_trait]
+impl Source for DiscordBot {
+    fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
+        let url = self.attachment_url(descriptor).await?;
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| Error::http("Error sending request", e))?;
+        if !response.status().is_success() {
+            // the url might have been revoked before its expiry, so we forget it
+            self.urls.lock().unwrap().remove(descriptor);
+            return Err(Error::status(
+                response.status(),
+                format!("Error downloading attachment: {}", response.status()),
+            ));
+        }
+        Ok(response
+            .bytes()
+            .await
+            .map_err(|e| Error::http("Error reading response", e))?
+            .to_vec())
+    }
+
+    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error> {
+        let (channel, message) = parse_descriptor(descriptor)?;
+        let path = format!("channels/{}/messages/{}", channel, message);
+        self.send("PATCH", &path, |client, url| {
+            let form = Self::attachment_form(
+                data.clone(),
+                json!({
+                    "attachments": [
+                        { "id": 0, "filename": "d" }
+                    ],
+                }),
+            )?;
+            Ok(client.patch(url).multipart(form))
+        })
+        .await?;
+        self.urls.lock().unwrap().remove(descriptor);
+        Ok(())
+    }
+
+    async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error> {
+        let (channel, message) = parse_descriptor(descriptor)?;
+        let path = format!("channels/{}/messages/{}", channel, message);
+        self.send("DELETE", &path, |client, url| Ok(client.delete(url)))
+            .await?;
+        self.urls.lock().unwrap().remove(descriptor);
+        Ok(())
+    }
+
+    async fn create(&self) -> Result<Descriptor, Error> {
+        if self.channels.is_empty() {
+            return Err(Error::Invalid("No channels configured".to_string()));
+        }
+        let channel =
+            &self.channels[self.next_channel.fetch_add(1, Ordering::Relaxed) % self.channels.len()];
+        let path = format!("channels/{}/messages", channel);
+        let response = self
+            .send("POST", &path, |client, url| {
+                let form = Self::attachment_form(
+                    Vec::new(),
+                    json!({
+                        "flags": 1<<12, // suppress notifications (@silent)
+                        "attachments": [
+                            { "id": 0, "filename": "d" }
+                        ],
+                    }),
+                )?;
+                Ok(client.post(url).multipart(form))
+            })
+            .await?;
+        let parsed = response
+            .json::<MessageResponse>()
+            .await
+            .map_err(|e| Error::http("Error parsing response", e))?;
+        Ok(format!("{}/{}", channel, parsed.id).into_bytes())
+    }
+}
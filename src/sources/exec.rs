@@ -0,0 +1,146 @@
+/*
+   Delegates every operation to an external program, so storage backends can be added without recompiling.
+   The program is run as `<command> <args...> <operation> [descriptor]`, chunk bytes travel over stdin
+   and stdout, and the exit code tells how it went: 0 is success, 2 means the chunk does not exist.
+   scripts/exec_source.sh is a reference implementation storing chunks in a folder.
+*/
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::{collections::HashMap, io::ErrorKind, process::Stdio, time::Duration};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use super::source::Source;
+use crate::{error::Error, global::Descriptor};
+
+#[derive(Debug, Deserialize)]
+pub struct Exec {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(default = "default_max_size")]
+    max_size: usize,
+    #[serde(default = "default_timeout")]
+    timeout: u64, // seconds, the program is killed when it takes longer
+}
+
+const fn default_max_size() -> usize {
+    64 * 1024 * 1024
+}
+const fn default_timeout() -> u64 {
+    60
+}
+
+const EXIT_NOT_FOUND: i32 = 2;
+
+impl Exec {
+    async fn run(
+        &self,
+        operation: &str,
+        descriptor: Option<&Descriptor>,
+        input: Option<Vec<u8>>,
+    ) -> Result<Vec<u8>, Error> {
+        let mut command = Command::new(shellexpand::tilde(&self.command).as_ref());
+        command
+            .args(&self.args)
+            .arg(operation)
+            .envs(&self.env)
+            .stdin(match input {
+                Some(_) => Stdio::piped(),
+                None => Stdio::null(),
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(descriptor) = descriptor {
+            command.arg(
+                std::str::from_utf8(descriptor)
+                    .map_err(|e| Error::Invalid(format!("Error parsing descriptor: {}", e)))?,
+            );
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| Error::io(format!("Error starting {}", self.command), e))?;
+        // stdin is written concurrently with reading stdout, or a chatty program could deadlock on a full pipe
+        let writer = match (input, child.stdin.take()) {
+            (Some(data), Some(mut stdin)) => Some(tokio::spawn(async move {
+                let written = stdin.write_all(&data).await;
+                drop(stdin); // closing stdin tells the program the chunk is complete
+                written
+            })),
+            _ => None,
+        };
+
+        let output =
+            tokio::time::timeout(Duration::from_secs(self.timeout), child.wait_with_output())
+                .await
+                .map_err(|_| {
+                    Error::Unavailable(format!("{} {} timed out", self.command, operation))
+                })?
+                .map_err(|e| Error::io(format!("Error running {}", self.command), e))?;
+        if let Some(writer) = writer {
+            let written = writer
+                .await
+                .map_err(|e| Error::Internal(format!("Error joining writer: {}", e)))?;
+            // a program that fails early (like a put to a missing chunk) may exit without reading stdin,
+            // its exit code says more than the broken pipe
+            match written {
+                Err(e) if output.status.success() || e.kind() != ErrorKind::BrokenPipe => {
+                    return Err(Error::io(format!("Error writing to {}", self.command), e))
+                }
+                _ => (),
+            }
+        }
+
+        match output.status.code() {
+            Some(0) => Ok(output.stdout),
+            Some(EXIT_NOT_FOUND) => Err(Error::NotFound("File not found".to_string())),
+            code => Err(Error::Unavailable(format!(
+                "{} {} failed ({}): {}",
+                self.command,
+                operation,
+                code.map(|c| c.to_string()).unwrap_or("killed".to_string()),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))),
+        }
+    }
+}
+
+#[async_trait]
+impl Source for Exec {
+    fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
+        self.run("get", Some(descriptor), None).await
+    }
+
+    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error> {
+        self.run("put", Some(descriptor), Some(data)).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error> {
+        self.run("delete", Some(descriptor), None).await?;
+        Ok(())
+    }
+
+    async fn create(&self) -> Result<Descriptor, Error> {
+        let output = self.run("create", None, None).await?;
+        let descriptor = String::from_utf8(output)
+            .map_err(|e| Error::Invalid(format!("Error parsing descriptor: {}", e)))?
+            .trim()
+            .to_string();
+        match descriptor.is_empty() {
+            true => Err(Error::Invalid(format!(
+                "{} create printed no descriptor",
+                self.command
+            ))),
+            false => Ok(descriptor.into_bytes()),
+        }
+    }
+}
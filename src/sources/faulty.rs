@@ -0,0 +1,120 @@
+/*
+   Wraps another source and misbehaves on purpose: it fails, stalls, corrupts and loses data.
+   Every decision comes from a seeded rng, so a test with the same seed sees the same faults.
+*/
+
+use async_trait::async_trait;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+use std::{sync::Mutex, time::Duration};
+
+use super::source::{Source, SourceType};
+use crate::{error::Error, global::Descriptor};
+
+#[derive(Debug, Deserialize)]
+pub struct FaultySource {
+    source: Box<SourceType>,
+
+    #[serde(default)]
+    seed: u64,
+    #[serde(default = "all_operations")]
+    operations: Vec<Operation>, // which operations the faults apply to
+
+    #[serde(default)]
+    fail_rate: f64, // the operation returns an error without reaching the wrapped source
+    #[serde(default)]
+    latency_ms: u64, // every operation is delayed by this much
+    #[serde(default)]
+    corrupt_rate: f64, // get flips a bit of the returned data
+    #[serde(default)]
+    loss_rate: f64, // put reports success but the data never reaches the wrapped source
+
+    #[serde(skip)]
+    rng: Mutex<Option<Box<StdRng>>>, // boxed, StdRng would make this the largest SourceType variant by far
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub enum Operation {
+    #[serde(rename = "get")]
+    Get,
+    #[serde(rename = "put")]
+    Put,
+    #[serde(rename = "delete")]
+    Delete,
+    #[serde(rename = "create")]
+    Create,
+}
+
+fn all_operations() -> Vec<Operation> {
+    vec![
+        Operation::Get,
+        Operation::Put,
+        Operation::Delete,
+        Operation::Create,
+    ]
+}
+
+impl FaultySource {
+    // The rng is seeded on first use, serde can't run code after deserializing
+    fn with_rng<T>(&self, f: impl FnOnce(&mut StdRng) -> T) -> T {
+        let mut rng = self.rng.lock().unwrap();
+        f(rng.get_or_insert_with(|| Box::new(StdRng::seed_from_u64(self.seed))))
+    }
+
+    // Rolls the dice for a fault, operations that aren't targeted never get one
+    fn roll(&self, operation: Operation, rate: f64) -> bool {
+        if rate <= 0.0 || !self.operations.contains(&operation) {
+            return false;
+        }
+        self.with_rng(|rng| rng.gen_bool(rate.min(1.0)))
+    }
+
+    async fn before(&self, operation: Operation) -> Result<(), Error> {
+        if self.latency_ms > 0 && self.operations.contains(&operation) {
+            tokio::time::sleep(Duration::from_millis(self.latency_ms)).await;
+        }
+        match self.roll(operation, self.fail_rate) {
+            true => Err(Error::Unavailable(format!(
+                "Injected failure in {:?}",
+                operation
+            ))),
+            false => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Source for FaultySource {
+    fn max_size(&self) -> usize {
+        self.source.max_size()
+    }
+
+    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
+        self.before(Operation::Get).await?;
+        let mut data = self.source.get(descriptor).await?;
+        if !data.is_empty() && self.roll(Operation::Get, self.corrupt_rate) {
+            let (index, bit) =
+                self.with_rng(|rng| (rng.gen_range(0..data.len()), rng.gen_range(0..8)));
+            data[index] ^= 1 << bit;
+        }
+        Ok(data)
+    }
+
+    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error> {
+        self.before(Operation::Put).await?;
+        if self.roll(Operation::Put, self.loss_rate) {
+            return Ok(());
+        }
+        self.source.put(descriptor, data).await
+    }
+
+    async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error> {
+        self.before(Operation::Delete).await?;
+        self.source.delete(descriptor).await
+    }
+
+    async fn create(&self) -> Result<Descriptor, Error> {
+        self.before(Operation::Create).await?;
+        self.source.create().await
+    }
+}
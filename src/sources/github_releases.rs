@@ -5,7 +5,7 @@ use serde::Deserialize;
 use serde_json::json;
 
 use super::source::Source;
-use crate::global::Descriptor;
+use crate::{error::Error, global::Descriptor};
 
 #[derive(Debug, Deserialize)]
 pub struct GithubReleases {
@@ -15,6 +15,21 @@ pub struct GithubReleases {
 
     #[serde(default = "default_descriptor_length")]
     descriptor_length: usize,
+
+    #[serde(default)]
+    flavor: ReleasesFlavor,
+    api_url: Option<String>,
+    upload_url: Option<String>,
+}
+
+// GitHub (and GitHub Enterprise) and Gitea/Forgejo share the release API, except for the asset and tag endpoints
+#[derive(Debug, Deserialize, Default)]
+pub enum ReleasesFlavor {
+    #[serde(rename = "github")]
+    #[default]
+    Github,
+    #[serde(rename = "gitea", alias = "forgejo")]
+    Gitea,
 }
 
 const fn default_descriptor_length() -> usize {
@@ -31,9 +46,66 @@ pub struct ReleaseResponse {
 #[derive(Deserialize)]
 pub struct AssetPart {
     id: u64,
+    #[serde(default)]
+    browser_download_url: String,
 }
 
 impl GithubReleases {
+    fn api_url(&self) -> &str {
+        match (&self.api_url, &self.flavor) {
+            (Some(url), _) => url.trim_end_matches('/'),
+            (None, ReleasesFlavor::Github) => "https://api.github.com",
+            (None, ReleasesFlavor::Gitea) => "https://codeberg.org/api/v1",
+        }
+    }
+
+    fn upload_url(&self) -> &str {
+        match (&self.upload_url, &self.flavor) {
+            (Some(url), _) => url.trim_end_matches('/'),
+            (None, ReleasesFlavor::Github) if self.api_url.is_none() => {
+                "https://uploads.github.com"
+            }
+            (None, _) => self.api_url(), // Gitea takes uploads on the api host, GitHub Enterprise needs upload_url set
+        }
+    }
+
+    // Builds an url to a repository endpoint, `path` should start with a slash
+    pub(crate) fn repo_url(&self, path: &str) -> String {
+        format!(
+            "{}/repos/{}/{}{}",
+            self.api_url(),
+            self.owner,
+            self.repo,
+            path
+        )
+    }
+
+    pub(crate) fn asset_url(&self, release_id: u64, asset_id: u64) -> String {
+        match self.flavor {
+            ReleasesFlavor::Github => self.repo_url(&format!("/releases/assets/{}", asset_id)),
+            ReleasesFlavor::Gitea => {
+                self.repo_url(&format!("/releases/{}/assets/{}", release_id, asset_id))
+            }
+        }
+    }
+
+    pub(crate) fn upload_asset_url(&self, release_id: u64) -> String {
+        format!(
+            "{}/repos/{}/{}/releases/{}/assets?name=d.bin",
+            self.upload_url(),
+            self.owner,
+            self.repo,
+            release_id
+        )
+    }
+
+    pub(crate) fn tag_url(&self, tag: &str) -> String {
+        match self.flavor {
+            ReleasesFlavor::Github => self.repo_url(&format!("/git/refs/tags/{}", tag)),
+            ReleasesFlavor::Gitea => self.repo_url(&format!("/tags/{}", tag)),
+        }
+    }
+
     fn make_headers(&self, mime: Option<&str>, accept: Option<&str>) -> HeaderMap {
         let mut headers = HeaderMap::new();
         if let Some(accept) = accept {
@@ -62,196 +134,186 @@ impl Source for GithubReleases {
         1024 * 1024 * 1024 // 1 GB
     }
 
-    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, String> {
+    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
         let tag = std::str::from_utf8(descriptor)
-            .map_err(|e| format!("Error parsing descriptor: {}", e))?;
+            .map_err(|e| Error::Invalid(format!("Error parsing descriptor: {}", e)))?;
 
         // Get release info
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/releases/tags/{}",
-            self.owner, self.repo, tag
-        );
+        let url = self.repo_url(&format!("/releases/tags/{}", tag));
         let client = reqwest::Client::new();
         let parsed = client
             .get(&url)
             .headers(self.make_headers(None, None))
             .send()
             .await
-            .map_err(|e| format!("Error sending request: {}", e))?
+            .map_err(|e| Error::http("Error sending request", e))?
             .json::<ReleaseResponse>()
             .await
-            .map_err(|e| format!("Error parsing response: {}", e))?;
+            .map_err(|e| Error::http("Error parsing response", e))?;
 
-        // Get asset id
-        let id = parsed
+        // Get asset
+        let asset = parsed
             .assets
             .first()
-            .ok_or_else(|| format!("No assets found for release {}", tag))?
-            .id;
+            .ok_or_else(|| Error::NotFound(format!("No assets found for release {}", tag)))?;
 
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/releases/assets/{}",
-            self.owner, self.repo, id
-        );
-        let client = reqwest::Client::new();
+        // Gitea has no octet-stream endpoint for assets, the attachment is served from the download url
+        let url = match self.flavor {
+            ReleasesFlavor::Github => self.asset_url(parsed.id, asset.id),
+            ReleasesFlavor::Gitea => asset.browser_download_url.clone(),
+        };
         let response = client
             .get(&url)
             .headers(self.make_headers(None, Some("application/octet-stream")))
             .send()
             .await
-            .map_err(|e| format!("Error sending request: {}", e))?;
+            .map_err(|e| Error::http("Error sending request", e))?;
         Ok(response
             .bytes()
             .await
-            .map_err(|e| format!("Error reading response: {}", e))?
+            .map_err(|e| Error::http("Error reading response", e))?
             .to_vec())
     }
 
-    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), String> {
+    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error> {
         let tag = std::str::from_utf8(descriptor)
-            .map_err(|e| format!("Error parsing descriptor: {}", e))?;
+            .map_err(|e| Error::Invalid(format!("Error parsing descriptor: {}", e)))?;
 
         // Get release info
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/releases/tags/{}",
-            self.owner, self.repo, tag
-        );
+        let url = self.repo_url(&format!("/releases/tags/{}", tag));
         let client = reqwest::Client::new();
         let parsed = client
             .get(&url)
             .headers(self.make_headers(None, None))
             .send()
             .await
-            .map_err(|e| format!("Error sending request: {}", e))?
+            .map_err(|e| Error::http("Error sending request", e))?
             .json::<ReleaseResponse>()
             .await
-            .map_err(|e| format!("Error parsing response: {}", e))?;
+            .map_err(|e| Error::http("Error parsing response", e))?;
 
         // Delete existing asset
         for asset in parsed.assets {
-            let url = format!(
-                "https://api.github.com/repos/{}/{}/releases/assets/{}",
-                self.owner, self.repo, asset.id
-            );
+            let url = self.asset_url(parsed.id, asset.id);
             client
                 .delete(&url)
                 .headers(self.make_headers(None, None))
                 .send()
                 .await
-                .map_err(|e| format!("Error sending request: {}", e))?;
+                .map_err(|e| Error::http("Error sending request", e))?;
         }
 
         // Upload new asset
-        let url = format!(
-            "https://uploads.github.com/repos/{}/{}/releases/{}/assets?name=d.bin",
-            self.owner, self.repo, parsed.id
-        );
-        let response = client
-            .post(&url)
-            .headers(self.make_headers(Some("application/octet-stream"), None))
-            .body(data)
+        let url = self.upload_asset_url(parsed.id);
+        let request = match self.flavor {
+            ReleasesFlavor::Github => client
+                .post(&url)
+                .headers(self.make_headers(Some("application/octet-stream"), None))
+                .body(data),
+            ReleasesFlavor::Gitea => {
+                let part = reqwest::multipart::Part::bytes(data)
+                    .file_name("d.bin")
+                    .mime_str("application/octet-stream")
+                    .map_err(|e| Error::http("Error creating part", e))?;
+                client
+                    .post(&url)
+                    .headers(self.make_headers(None, None))
+                    .multipart(reqwest::multipart::Form::new().part("attachment", part))
+            }
+        };
+        let response = request
             .send()
             .await
-            .map_err(|e| format!("Error sending request: {}", e))?;
-        if !response.status().is_success() {
-            return Err(format!(
-                "Error uploading asset: {}",
-                response
-                    .text()
-                    .await
-                    .map_err(|e| format!("Error reading response: {}", e))?
+            .map_err(|e| Error::http("Error sending request", e))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::status(
+                status,
+                format!(
+                    "Error uploading asset: {}",
+                    response
+                        .text()
+                        .await
+                        .map_err(|e| Error::http("Error reading response", e))?
+                ),
             ));
         }
         Ok(())
     }
 
-    async fn delete(&self, descriptor: &Descriptor) -> Result<(), String> {
+    async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error> {
         let tag = std::str::from_utf8(descriptor)
-            .map_err(|e| format!("Error parsing descriptor: {}", e))?;
+            .map_err(|e| Error::Invalid(format!("Error parsing descriptor: {}", e)))?;
 
         let mut errors = Vec::new();
 
         // Get release info
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/releases/tags/{}",
-            self.owner, self.repo, tag
-        );
+        let url = self.repo_url(&format!("/releases/tags/{}", tag));
         let client = reqwest::Client::new();
         let response = client
             .get(&url)
             .headers(self.make_headers(None, None))
             .send()
             .await;
-        if response.is_err() {
-            errors.push(format!(
-                "Error sending request: {}",
-                response.err().unwrap()
-            ));
-        } else {
-            let parsed = response
-                .unwrap()
-                .json::<ReleaseResponse>()
-                .await
-                .map_err(|e| format!("Error parsing response: {}", e));
-
-            if let Ok(parsed) = parsed.as_ref() {
-                // Delete existing asset(s)
-                let id = parsed.id;
-                for asset in &parsed.assets {
-                    let url = format!(
-                        "https://api.github.com/repos/{}/{}/releases/assets/{}",
-                        self.owner, self.repo, asset.id
-                    );
-                    match client
-                        .delete(&url)
-                        .headers(self.make_headers(None, None))
-                        .send()
-                        .await
-                    {
-                        Ok(_) => (),
-                        Err(e) => errors.push(format!("Error deleting asset: {}", e)),
-                    }
-                }
-
-                // Delete release
-                let url = format!(
-                    "https://api.github.com/repos/{}/{}/releases/{}",
-                    self.owner, self.repo, id
-                );
-                match client
-                    .delete(&url)
-                    .headers(self.make_headers(None, None))
-                    .send()
+        match response {
+            Err(e) => errors.push(Error::http("Error sending request", e)),
+            Ok(response) => {
+                let parsed = response
+                    .json::<ReleaseResponse>()
                     .await
-                {
-                    Ok(_) => (),
-                    Err(e) => errors.push(format!("Error deleting release: {}", e)),
+                    .map_err(|e| Error::http("Error parsing response", e));
+
+                match parsed {
+                    Ok(parsed) => {
+                        // Delete existing asset(s)
+                        let id = parsed.id;
+                        for asset in &parsed.assets {
+                            let url = self.asset_url(id, asset.id);
+                            match client
+                                .delete(&url)
+                                .headers(self.make_headers(None, None))
+                                .send()
+                                .await
+                            {
+                                Ok(_) => (),
+                                Err(e) => errors.push(Error::http("Error deleting asset", e)),
+                            }
+                        }
+
+                        // Delete release
+                        let url = self.repo_url(&format!("/releases/{}", id));
+                        match client
+                            .delete(&url)
+                            .headers(self.make_headers(None, None))
+                            .send()
+                            .await
+                        {
+                            Ok(_) => (),
+                            Err(e) => errors.push(Error::http("Error deleting release", e)),
+                        }
+                    }
+                    Err(e) => errors.push(e),
                 }
-            } else {
-                errors.push(format!("Error parsing response: {}", parsed.err().unwrap()));
             }
         }
 
         // Delete tag
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/git/refs/tags/{}",
-            self.owner, self.repo, tag
-        );
+        let url = self.tag_url(tag);
         client
             .delete(&url)
             .headers(self.make_headers(None, None))
             .send()
             .await
-            .map_err(|e| format!("Error sending request: {}", e))?;
+            .map_err(|e| Error::http("Error sending request", e))?;
 
         if errors.is_empty() {
             Ok(())
         } else {
-            Err(errors.join(", "))
+            Err(Error::many(errors))
         }
     }
 
-    async fn create(&self) -> Result<Descriptor, String> {
+    async fn create(&self) -> Result<Descriptor, Error> {
         let mut descriptor = thread_rng()
             .sample_iter(&Alphanumeric)
             .take(self.descriptor_length)
@@ -261,26 +323,27 @@ impl Source for GithubReleases {
         let client = reqwest::Client::new();
 
         // Check if the descriptor already exists
-        let mut url = format!(
-            "https://api.github.com/repos/{}/{}/releases/tags/{}",
-            self.owner, self.repo, descriptor
-        );
+        let mut url = self.repo_url(&format!("/releases/tags/{}", descriptor));
         loop {
             let response = client
                 .get(&url)
                 .headers(self.make_headers(None, None))
                 .send()
                 .await
-                .map_err(|e| format!("Error sending request: {}", e))?;
+                .map_err(|e| Error::http("Error sending request", e))?;
             if response.status() == 404 {
                 break;
             } else if !response.status().is_success() {
-                return Err(format!(
-                    "Error checking if release exists: {}",
-                    response
-                        .text()
-                        .await
-                        .map_err(|e| format!("Error reading response: {}", e))?
+                let status = response.status();
+                return Err(Error::status(
+                    status,
+                    format!(
+                        "Error checking if release exists: {}",
+                        response
+                            .text()
+                            .await
+                            .map_err(|e| Error::http("Error reading response", e))?
+                    ),
                 ));
             } else {
                 descriptor = thread_rng()
@@ -288,18 +351,12 @@ impl Source for GithubReleases {
                     .take(self.descriptor_length)
                     .map(char::from)
                     .collect::<String>();
-                url = format!(
-                    "https://api.github.com/repos/{}/{}/releases/tags/{}",
-                    self.owner, self.repo, descriptor
-                );
+                url = self.repo_url(&format!("/releases/tags/{}", descriptor));
             }
         }
 
         // Create release
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/releases",
-            self.owner, self.repo
-        );
+        let url = self.repo_url("/releases");
         let response = client
             .post(&url)
             .headers(self.make_headers(Some("application/json"), None))
@@ -315,14 +372,18 @@ impl Source for GithubReleases {
             )
             .send()
             .await
-            .map_err(|e| format!("Error sending request: {}", e))?;
-        if !response.status().is_success() {
-            return Err(format!(
-                "Error creating release: {}",
-                response
-                    .text()
-                    .await
-                    .map_err(|e| format!("Error reading response: {}", e))?
+            .map_err(|e| Error::http("Error sending request", e))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::status(
+                status,
+                format!(
+                    "Error creating release: {}",
+                    response
+                        .text()
+                        .await
+                        .map_err(|e| Error::http("Error reading response", e))?
+                ),
             ));
         }
 
@@ -1,12 +1,26 @@
+/*
+   Stores each chunk as a file in a local folder.
+   Chunks are spread over sub-directories named after a hash of the descriptor (`ab/cd/<descriptor>`),
+   so no single directory grows to millions of entries. Writes go to a temporary file that is synced
+   and renamed over the chunk, so a crash never leaves half a chunk behind.
+   Folders from before the sharded layout (every chunk directly in `folder`) are migrated on first use.
+*/
+
 use async_trait::async_trait;
+use crypto::{digest::Digest, sha2::Sha256};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::Deserialize;
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
 use tokio::{
-    fs::{remove_file, File, OpenOptions},
+    fs::{create_dir_all, metadata, read_dir, remove_file, rename, File, OpenOptions},
     io::{AsyncReadExt, AsyncWriteExt, BufReader},
+    sync::OnceCell,
 };
 
-use crate::global::Descriptor;
+use crate::{error::Error, global::Descriptor};
 
 use super::source::Source;
 
@@ -17,6 +31,11 @@ pub struct LocalSource {
     max_size: usize,
     #[serde(default = "default_descriptor_length")]
     descriptor_length: usize,
+    #[serde(default = "default_shard_levels")]
+    shard_levels: usize, // 0 keeps every chunk directly in `folder`
+
+    #[serde(skip)]
+    migrated: OnceCell<()>,
 }
 
 const fn default_max_size() -> usize {
@@ -25,6 +44,86 @@ const fn default_max_size() -> usize {
 const fn default_descriptor_length() -> usize {
     24
 }
+const fn default_shard_levels() -> usize {
+    2 // 65536 directories, enough for hundreds of millions of chunks
+}
+
+impl LocalSource {
+    fn chunk_path(&self, name: &str) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.input_str(name);
+        let hash = hasher.result_str();
+
+        let mut path = PathBuf::from(&self.folder);
+        for level in 0..self.shard_levels.min(hash.len() / 2) {
+            path.push(&hash[level * 2..level * 2 + 2]);
+        }
+        path.push(name);
+        path
+    }
+
+    fn descriptor_path(&self, descriptor: &Descriptor) -> Result<PathBuf, Error> {
+        let name = std::str::from_utf8(descriptor)
+            .map_err(|e| Error::Invalid(format!("Error parsing descriptor: {}", e)))?;
+        Ok(self.chunk_path(name))
+    }
+
+    // Moves chunks of a flat folder into their shards, runs once before the first operation.
+    // Only alphanumeric names like the ones ::create() hands out move, whatever descriptor_length was
+    // when they were created. Anything else in the folder isn't ours to touch.
+    async fn migrate(&self) -> Result<(), Error> {
+        self.migrated
+            .get_or_try_init(|| async {
+                if self.shard_levels == 0 {
+                    return Ok(());
+                }
+                let mut entries = match read_dir(&self.folder).await {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+                    Err(e) => return Err(Error::io("Error reading folder", e)),
+                };
+                while let Some(entry) = entries
+                    .next_entry()
+                    .await
+                    .map_err(|e| Error::io("Error reading folder", e))?
+                {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    let is_file = entry
+                        .file_type()
+                        .await
+                        .map(|t| t.is_file())
+                        .unwrap_or(false);
+                    if !is_file || !is_descriptor(&name) {
+                        continue;
+                    }
+                    let path = self.chunk_path(&name);
+                    if let Some(parent) = path.parent() {
+                        create_dir_all(parent)
+                            .await
+                            .map_err(|e| Error::io("Error creating folder", e))?;
+                    }
+                    rename(entry.path(), &path)
+                        .await
+                        .map_err(|e| Error::io(format!("Error migrating {}", name), e))?;
+                }
+                Ok(())
+            })
+            .await
+            .map(|_| ())
+    }
+}
+
+fn is_descriptor(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+// Syncs a directory so a rename or a new file in it survives a crash.
+// Not every platform can open directories, so this is best effort.
+async fn sync_dir(path: &Path) {
+    if let Ok(dir) = File::open(path).await {
+        let _ = dir.sync_all().await;
+    }
+}
 
 #[async_trait]
 impl Source for LocalSource {
@@ -32,77 +131,97 @@ impl Source for LocalSource {
         self.max_size
     }
 
-    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, String> {
-        let descriptor = std::str::from_utf8(descriptor)
-            .map_err(|e| format!("Error parsing descriptor: {}", e))?;
-        let file_path = format!("{}/{}", self.folder, descriptor);
+    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
+        self.migrate().await?;
+        let file_path = self.descriptor_path(descriptor)?;
         let file = match File::open(file_path).await {
             Ok(file) => file,
-            Err(_) => return Err("File not found".to_string()),
+            Err(e) => return Err(Error::io("Error opening file", e)),
         };
         let mut data = Vec::new();
         let mut reader = BufReader::new(file);
         reader
             .read_to_end(&mut data)
             .await
-            .map_err(|e| format!("Error reading file: {}", e))?;
+            .map_err(|e| Error::io("Error reading file", e))?;
         Ok(data)
     }
 
-    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), String> {
-        let descriptor = std::str::from_utf8(descriptor)
-            .map_err(|e| format!("Error parsing descriptor: {}", e))?;
-        let file_path = format!("{}/{}", self.folder, descriptor);
-        let mut file = match OpenOptions::new()
-            .write(true)
-            .create(false) // We don't want to create the file if it doesn't exist, it should already exist,
-            .truncate(true) // as we only should create files with ::create() to ensure safe descriptors
-            .open(file_path)
+    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error> {
+        self.migrate().await?;
+        let file_path = self.descriptor_path(descriptor)?;
+        // The file should already exist, as we only should create files with ::create() to ensure safe descriptors
+        if metadata(&file_path).await.is_err() {
+            return Err(Error::NotFound("File not found".to_string()));
+        }
+
+        // concurrent puts of the same chunk each get their own temporary file
+        let name = file_path.file_name().unwrap_or_default().to_string_lossy();
+        let suffix = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(8)
+            .map(char::from)
+            .collect::<String>();
+        let temp_path = file_path.with_file_name(format!(".{}.{}.tmp", name, suffix));
+        let mut file = File::create(&temp_path)
             .await
-        {
-            Ok(file) => file,
-            Err(e) => return Err(format!("Error opening file: {}", e)),
-        };
-        // Write the data to the file
-        file.write(&data.to_vec())
+            .map_err(|e| Error::io("Error opening file", e))?;
+        let written = async {
+            file.write_all(&data).await?;
+            file.sync_all().await
+        }
+        .await;
+        drop(file);
+        if let Err(e) = written {
+            let _ = remove_file(&temp_path).await;
+            return Err(Error::io("Error writing file", e));
+        }
+
+        rename(&temp_path, &file_path)
             .await
-            .map_err(|e| format!("Error writing file: {}", e))?;
+            .map_err(|e| Error::io("Error replacing file", e))?;
+        if let Some(parent) = file_path.parent() {
+            sync_dir(parent).await;
+        }
         Ok(())
     }
 
-    async fn delete(&self, descriptor: &Descriptor) -> Result<(), String> {
-        let descriptor = std::str::from_utf8(descriptor)
-            .map_err(|e| format!("Error parsing descriptor: {}", e))?;
-        let file_path = format!("{}/{}", self.folder, descriptor);
+    async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error> {
+        self.migrate().await?;
+        let file_path = self.descriptor_path(descriptor)?;
         match remove_file(file_path).await {
             Ok(_) => Ok(()),
-            Err(e) => Err(format!("Error deleting file: {}", e)),
+            Err(e) => Err(Error::io("Error deleting file", e)),
         }
     }
 
-    async fn create(&self) -> Result<Descriptor, String> {
-        let mut descriptor = thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(self.descriptor_length)
-            .map(char::from)
-            .collect::<String>();
-        let mut file_path = format!("{}/{}", self.folder, descriptor);
-        // Ensure that the descriptor is unique
-        while File::open(file_path).await.is_ok() {
-            descriptor = thread_rng()
+    async fn create(&self) -> Result<Descriptor, Error> {
+        self.migrate().await?;
+        loop {
+            let descriptor = thread_rng()
                 .sample_iter(&Alphanumeric)
                 .take(self.descriptor_length)
                 .map(char::from)
                 .collect::<String>();
-            file_path = format!("{}/{}", self.folder, descriptor);
+            let file_path = self.chunk_path(&descriptor);
+            let parent = file_path.parent().unwrap_or(Path::new(&self.folder));
+            create_dir_all(parent)
+                .await
+                .map_err(|e| Error::io("Error creating folder", e))?;
+            // create_new fails if the descriptor is taken, so two writers can't claim the same one
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&file_path)
+                .await
+            {
+                Ok(_) => {
+                    sync_dir(parent).await;
+                    return Ok(descriptor.into_bytes());
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(Error::io("Error creating file", e)),
+            }
         }
-        file_path = format!("{}/{}", self.folder, descriptor); // this is necessary because the file_path variable is moved into the closure below
-        let mut file = File::create(file_path)
-            .await
-            .map_err(|e| format!("Error creating file: {}", e))?;
-        file.write_all(b"")
-            .await
-            .map_err(|e| format!("Error writing file: {}", e))?;
-        Ok(descriptor.into_bytes())
     }
 }
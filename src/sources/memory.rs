@@ -0,0 +1,80 @@
+/*
+   Keeps chunks in a hashmap, nothing survives a restart.
+   Useful for tests and for trying chunkdrive out without touching any storage.
+*/
+
+use async_trait::async_trait;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Mutex};
+
+use super::source::Source;
+use crate::{error::Error, global::Descriptor};
+
+#[derive(Debug, Deserialize)]
+pub struct MemorySource {
+    #[serde(default = "default_max_size")]
+    max_size: usize,
+    #[serde(default = "default_descriptor_length")]
+    descriptor_length: usize,
+
+    #[serde(skip)]
+    chunks: Mutex<HashMap<Descriptor, Vec<u8>>>,
+}
+
+const fn default_max_size() -> usize {
+    16 * 1024 * 1024
+}
+const fn default_descriptor_length() -> usize {
+    16
+}
+
+#[async_trait]
+impl Source for MemorySource {
+    fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
+        self.chunks
+            .lock()
+            .unwrap()
+            .get(descriptor)
+            .cloned()
+            .ok_or(Error::NotFound("File not found".to_string()))
+    }
+
+    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error> {
+        match self.chunks.lock().unwrap().get_mut(descriptor) {
+            Some(chunk) => {
+                *chunk = data;
+                Ok(())
+            }
+            None => Err(Error::NotFound("File not found".to_string())), // only ::create() makes new descriptors
+        }
+    }
+
+    async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error> {
+        self.chunks
+            .lock()
+            .unwrap()
+            .remove(descriptor)
+            .map(|_| ())
+            .ok_or(Error::NotFound("File not found".to_string()))
+    }
+
+    async fn create(&self) -> Result<Descriptor, Error> {
+        let mut chunks = self.chunks.lock().unwrap();
+        loop {
+            // alphanumeric like the other sources, descriptors end up in urls
+            let descriptor = thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(self.descriptor_length)
+                .collect::<Vec<u8>>();
+            if !chunks.contains_key(&descriptor) {
+                chunks.insert(descriptor.clone(), Vec::new());
+                return Ok(descriptor);
+            }
+        }
+    }
+}
@@ -1,4 +1,13 @@
+pub mod discord_bot;
 pub mod discord_webhook;
+pub mod exec;
+pub mod faulty;
 pub mod github_releases;
 pub mod local;
+pub mod memory;
+pub mod rclone;
+pub mod sftp;
 pub mod source;
+pub mod sqlite;
+pub mod telegram;
+pub mod webdav;
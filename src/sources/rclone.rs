@@ -0,0 +1,334 @@
+/*
+   Stores each chunk as a file on any rclone remote.
+   With `url` set, it talks to a running `rclone rcd --rc-serve`, otherwise every operation runs the rclone binary.
+   rclone has no exclusive create, so create checks that a random name is free before claiming it.
+*/
+
+use async_trait::async_trait;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{io::ErrorKind, process::Stdio};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use super::source::Source;
+use crate::{error::Error, global::Descriptor};
+
+#[derive(Debug, Deserialize)]
+pub struct Rclone {
+    remote: String, // "name:", "name:bucket/path" or a local path, as given to rclone
+    #[serde(default)]
+    prefix: String,
+    #[serde(default = "default_max_size")]
+    max_size: usize,
+    #[serde(default = "default_descriptor_length")]
+    descriptor_length: usize,
+
+    // rcd
+    url: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+
+    // cli
+    #[serde(default = "default_binary")]
+    binary: String,
+    config: Option<String>, // passed as --config
+
+    #[serde(skip)]
+    client: Client,
+}
+
+const fn default_max_size() -> usize {
+    512 * 1024 * 1024
+}
+const fn default_descriptor_length() -> usize {
+    24
+}
+fn default_binary() -> String {
+    "rclone".to_string()
+}
+
+// rclone exit codes for a missing directory and a missing file, see `rclone help flags`
+const EXIT_DIR_NOT_FOUND: i32 = 3;
+const EXIT_FILE_NOT_FOUND: i32 = 4;
+
+// Path of a chunk relative to the remote
+pub(crate) fn object_path(prefix: &str, name: &str) -> String {
+    let prefix = prefix.trim_matches('/');
+    match prefix.is_empty() {
+        true => name.to_string(),
+        false => format!("{}/{}", prefix, name),
+    }
+}
+
+// Full path of a chunk for the cli, "name:" is followed directly by the path, "name:bucket" needs a slash
+pub(crate) fn cli_path(remote: &str, prefix: &str, name: &str) -> String {
+    let path = object_path(prefix, name);
+    match remote.ends_with(':') || remote.ends_with('/') {
+        true => format!("{}{}", remote, path),
+        false => format!("{}/{}", remote, path),
+    }
+}
+
+impl Rclone {
+    fn name(descriptor: &Descriptor) -> Result<&str, Error> {
+        std::str::from_utf8(descriptor)
+            .map_err(|e| Error::Invalid(format!("Error parsing descriptor: {}", e)))
+    }
+
+    fn random_name(&self) -> String {
+        thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(self.descriptor_length)
+            .map(char::from)
+            .collect::<String>()
+    }
+
+    /* #region rcd */
+    fn rc_request(&self, url: &str, path: &str) -> RequestBuilder {
+        let request = self
+            .client
+            .post(format!("{}/{}", url.trim_end_matches('/'), path));
+        match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_ref()),
+            None => request,
+        }
+    }
+
+    async fn rc_call(&self, url: &str, method: &str, body: Value) -> Result<Value, Error> {
+        let response = self
+            .rc_request(url, method)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| Error::http("Error sending request", e))?;
+        let status = response.status();
+        let parsed = response
+            .json::<Value>()
+            .await
+            .map_err(|e| Error::http("Error parsing response", e))?;
+        match status.is_success() {
+            true => Ok(parsed),
+            false => Err(Error::status(
+                status,
+                format!(
+                    "rclone returned {}: {}",
+                    status,
+                    parsed["error"].as_str().unwrap_or_default()
+                ),
+            )),
+        }
+    }
+
+    async fn rc_exists(&self, url: &str, name: &str) -> Result<bool, Error> {
+        let stat = self
+            .rc_call(
+                url,
+                "operations/stat",
+                json!({ "fs": self.remote, "remote": object_path(&self.prefix, name) }),
+            )
+            .await?;
+        Ok(!stat["item"].is_null())
+    }
+
+    async fn rc_upload(&self, url: &str, name: &str, data: Vec<u8>) -> Result<(), Error> {
+        let part = reqwest::multipart::Part::bytes(data)
+            .file_name(name.to_string())
+            .mime_str("application/octet-stream")
+            .map_err(|e| Error::http("Error creating part", e))?;
+        let response = self
+            .rc_request(url, "operations/uploadfile")
+            .query(&[
+                ("fs", self.remote.clone()),
+                ("remote", self.prefix.trim_matches('/').to_string()),
+            ])
+            .multipart(reqwest::multipart::Form::new().part("file0", part))
+            .send()
+            .await
+            .map_err(|e| Error::http("Error sending request", e))?;
+        match response.status() {
+            status if !status.is_success() => Err(Error::status(
+                status,
+                format!("Error uploading file: {}", status),
+            )),
+            _ => Ok(()),
+        }
+    }
+    /* #endregion */
+
+    /* #region cli */
+    // Runs rclone with `args`, exit codes for missing files become Error::NotFound
+    async fn cli(&self, args: &[&str], input: Option<Vec<u8>>) -> Result<Vec<u8>, Error> {
+        let mut command = Command::new(shellexpand::tilde(&self.binary).as_ref());
+        if let Some(config) = &self.config {
+            command
+                .arg("--config")
+                .arg(shellexpand::tilde(config).as_ref());
+        }
+        command
+            .args(args)
+            .stdin(match input {
+                Some(_) => Stdio::piped(),
+                None => Stdio::null(),
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| Error::io("Error starting rclone", e))?;
+        let writer = match (input, child.stdin.take()) {
+            (Some(data), Some(mut stdin)) => Some(tokio::spawn(async move {
+                let written = stdin.write_all(&data).await;
+                drop(stdin);
+                written
+            })),
+            _ => None,
+        };
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(|e| Error::io("Error running rclone", e))?;
+        if let Some(writer) = writer {
+            let written = writer
+                .await
+                .map_err(|e| Error::Internal(format!("Error joining writer: {}", e)))?;
+            // rclone may exit before reading stdin, then its exit code says what went wrong
+            match written {
+                Err(e) if output.status.success() || e.kind() != ErrorKind::BrokenPipe => {
+                    return Err(Error::io("Error writing to rclone", e))
+                }
+                _ => (),
+            }
+        }
+
+        match output.status.code() {
+            Some(0) => Ok(output.stdout),
+            Some(EXIT_DIR_NOT_FOUND) | Some(EXIT_FILE_NOT_FOUND) => {
+                Err(Error::NotFound("File not found".to_string()))
+            }
+            _ => Err(Error::Unavailable(format!(
+                "rclone {} failed: {}",
+                args.first().unwrap_or(&""),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))),
+        }
+    }
+
+    async fn cli_exists(&self, path: &str) -> Result<bool, Error> {
+        match self.cli(&["lsjson", "--stat", path], None).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.is_not_found() => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+    /* #endregion */
+}
+
+#[async_trait]
+impl Source for Rclone {
+    fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
+        let name = Self::name(descriptor)?;
+        match &self.url {
+            Some(url) => {
+                // --rc-serve exposes remotes as /[remote:path]/file
+                let response = self.client.get(format!(
+                    "{}/[{}]/{}",
+                    url.trim_end_matches('/'),
+                    self.remote,
+                    object_path(&self.prefix, name)
+                ));
+                let response = match &self.username {
+                    Some(username) => response.basic_auth(username, self.password.as_ref()),
+                    None => response,
+                }
+                .send()
+                .await
+                .map_err(|e| Error::http("Error sending request", e))?;
+                match response.status() {
+                    status if !status.is_success() => Err(Error::status(
+                        status,
+                        format!("Error reading file: {}", status),
+                    )),
+                    _ => Ok(response
+                        .bytes()
+                        .await
+                        .map_err(|e| Error::http("Error reading response", e))?
+                        .to_vec()),
+                }
+            }
+            None => {
+                let path = cli_path(&self.remote, &self.prefix, name);
+                self.cli(&["cat", &path], None).await
+            }
+        }
+    }
+
+    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error> {
+        let name = Self::name(descriptor)?;
+        // the file should already exist, as we only should create files with ::create() to ensure safe descriptors
+        match &self.url {
+            Some(url) => {
+                if !self.rc_exists(url, name).await? {
+                    return Err(Error::NotFound("File not found".to_string()));
+                }
+                self.rc_upload(url, name, data).await
+            }
+            None => {
+                let path = cli_path(&self.remote, &self.prefix, name);
+                if !self.cli_exists(&path).await? {
+                    return Err(Error::NotFound("File not found".to_string()));
+                }
+                self.cli(&["rcat", &path], Some(data)).await?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error> {
+        let name = Self::name(descriptor)?;
+        match &self.url {
+            Some(url) => {
+                self.rc_call(
+                    url,
+                    "operations/deletefile",
+                    json!({ "fs": self.remote, "remote": object_path(&self.prefix, name) }),
+                )
+                .await?;
+            }
+            None => {
+                let path = cli_path(&self.remote, &self.prefix, name);
+                self.cli(&["deletefile", &path], None).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn create(&self) -> Result<Descriptor, Error> {
+        loop {
+            let name = self.random_name();
+            match &self.url {
+                Some(url) => {
+                    if self.rc_exists(url, &name).await? {
+                        continue; // the name is taken, try another one
+                    }
+                    self.rc_upload(url, &name, Vec::new()).await?;
+                }
+                None => {
+                    let path = cli_path(&self.remote, &self.prefix, &name);
+                    if self.cli_exists(&path).await? {
+                        continue;
+                    }
+                    self.cli(&["touch", &path], None).await?;
+                }
+            }
+            return Ok(name.into_bytes());
+        }
+    }
+}
@@ -0,0 +1,295 @@
+/*
+   Stores each chunk as a file in a folder on a remote host over SFTP.
+   ssh2 is blocking, so every operation runs on the blocking thread pool with a pooled connection.
+   Writes go to a temporary file which is then renamed over the chunk, so a dropped connection never leaves half a chunk.
+   Servers that can't rename over an existing file (SFTP v3, like OpenSSH) get the chunk removed first,
+   which leaves a short window in which it is missing.
+*/
+
+use async_trait::async_trait;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::Deserialize;
+use ssh2::{ErrorCode, HashType, OpenFlags, OpenType, RenameFlags, Session};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use super::source::Source;
+use crate::{error::Error, global::Descriptor};
+
+#[derive(Debug, Deserialize)]
+pub struct Sftp {
+    #[serde(flatten)]
+    connect: Connect,
+    folder: String,
+    #[serde(default = "default_max_size")]
+    max_size: usize,
+    #[serde(default = "default_descriptor_length")]
+    descriptor_length: usize,
+    #[serde(default = "default_pool_size")]
+    pool_size: usize,
+
+    #[serde(skip)]
+    pool: Pool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct Connect {
+    host: String,
+    #[serde(default = "default_port")]
+    port: u16,
+    username: String,
+    password: Option<String>,
+    private_key: Option<String>,
+    passphrase: Option<String>,
+    fingerprint: Option<String>, // hex encoded SHA256 of the host key
+}
+
+const fn default_port() -> u16 {
+    22
+}
+const fn default_max_size() -> usize {
+    512 * 1024 * 1024
+}
+const fn default_descriptor_length() -> usize {
+    24
+}
+const fn default_pool_size() -> usize {
+    4
+}
+
+// sftp status codes, see draft-ietf-secsh-filexfer-02
+const SFTP_NO_SUCH_FILE: i32 = 2;
+
+struct Connection {
+    _session: Session, // the sftp channel lives as long as its session
+    sftp: ssh2::Sftp,
+}
+
+#[derive(Default, Clone)]
+struct Pool(Arc<Mutex<Vec<Connection>>>);
+
+impl std::fmt::Debug for Pool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Pool({} idle)",
+            self.0.lock().map(|p| p.len()).unwrap_or(0)
+        )
+    }
+}
+
+impl Connect {
+    fn open(&self, folder: &str) -> Result<Connection, Error> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port))
+            .map_err(|e| Error::io(format!("Error connecting to {}", self.host), e))?;
+        let mut session = Session::new()
+            .map_err(|e| Error::Internal(format!("Error creating session: {}", e)))?;
+        session.set_tcp_stream(tcp);
+        session
+            .handshake()
+            .map_err(|e| Error::Unavailable(format!("Error during handshake: {}", e)))?;
+
+        if let Some(fingerprint) = &self.fingerprint {
+            let hash = session
+                .host_key_hash(HashType::Sha256)
+                .ok_or(Error::Unavailable("Host key is not available".to_string()))?;
+            let hex = hash
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>();
+            if !hex.eq_ignore_ascii_case(&fingerprint.replace(':', "")) {
+                return Err(Error::Internal(format!(
+                    "Host key mismatch for {}: {}",
+                    self.host, hex
+                )));
+            }
+        }
+
+        match (&self.private_key, &self.password) {
+            (Some(key), _) => session.userauth_pubkey_file(
+                &self.username,
+                None,
+                Path::new(shellexpand::tilde(key).as_ref()),
+                self.passphrase.as_deref(),
+            ),
+            (None, Some(password)) => session.userauth_password(&self.username, password),
+            (None, None) => session.userauth_agent(&self.username),
+        }
+        .map_err(|e| Error::Internal(format!("Error authenticating: {}", e)))?;
+
+        let sftp = session
+            .sftp()
+            .map_err(|e| Error::Unavailable(format!("Error starting sftp: {}", e)))?;
+        let _ = sftp.mkdir(Path::new(folder), 0o755); // fails if it already exists, which is fine
+        Ok(Connection {
+            _session: session,
+            sftp,
+        })
+    }
+}
+
+// Errors reported by the sftp server leave the connection usable, anything else means it is broken
+enum Failure {
+    Sftp(ssh2::Error),
+    Connection(String),
+}
+
+impl From<ssh2::Error> for Failure {
+    fn from(e: ssh2::Error) -> Self {
+        match e.code() {
+            ErrorCode::SFTP(_) => Failure::Sftp(e),
+            ErrorCode::Session(_) => Failure::Connection(format!("Ssh error: {}", e)),
+        }
+    }
+}
+
+impl From<std::io::Error> for Failure {
+    fn from(e: std::io::Error) -> Self {
+        Failure::Connection(format!("Error transferring data: {}", e))
+    }
+}
+
+impl From<Failure> for Error {
+    fn from(failure: Failure) -> Self {
+        match failure {
+            Failure::Sftp(e) if e.code() == ErrorCode::SFTP(SFTP_NO_SUCH_FILE) => {
+                Error::NotFound("File not found".to_string())
+            }
+            Failure::Sftp(e) => Error::Internal(format!("Sftp error: {}", e)),
+            Failure::Connection(e) => Error::Unavailable(e),
+        }
+    }
+}
+
+impl Sftp {
+    // Runs `f` with a pooled connection on the blocking thread pool.
+    // Connections are only returned to the pool when the error (if any) came from the sftp layer, not the session.
+    async fn run<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&ssh2::Sftp, &Path) -> Result<T, Failure> + Send + 'static,
+    {
+        let connect = self.connect.clone();
+        let pool = self.pool.clone();
+        let folder = self.folder.clone();
+        let pool_size = self.pool_size;
+        tokio::task::spawn_blocking(move || {
+            let idle = pool.0.lock().unwrap().pop();
+            let connection = match idle {
+                Some(connection) => connection,
+                None => connect.open(&folder)?,
+            };
+            let result = f(&connection.sftp, Path::new(&folder));
+            if !matches!(result, Err(Failure::Connection(_))) {
+                let mut idle = pool.0.lock().unwrap();
+                if idle.len() < pool_size {
+                    idle.push(connection);
+                }
+            }
+            result.map_err(Error::from)
+        })
+        .await
+        .map_err(|e| Error::Internal(format!("Error joining sftp task: {}", e)))?
+    }
+}
+
+fn chunk_path(folder: &Path, descriptor: &Descriptor) -> Result<PathBuf, Error> {
+    let name = std::str::from_utf8(descriptor)
+        .map_err(|e| Error::Invalid(format!("Error parsing descriptor: {}", e)))?;
+    Ok(folder.join(name))
+}
+
+fn write_temp(sftp: &ssh2::Sftp, temp: &Path, data: &[u8]) -> Result<(), Failure> {
+    let mut file = sftp.create(temp)?;
+    file.write_all(data)?;
+    file.fsync()?;
+    Ok(())
+}
+
+#[async_trait]
+impl Source for Sftp {
+    fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
+        let path = chunk_path(Path::new(&self.folder), descriptor)?;
+        self.run(move |sftp, _| {
+            let mut file = sftp.open(&path)?;
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            Ok(data)
+        })
+        .await
+    }
+
+    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error> {
+        let path = chunk_path(Path::new(&self.folder), descriptor)?;
+        self.run(move |sftp, folder| {
+            sftp.stat(&path)?; // the chunk should already exist, as we only should create files with ::create()
+
+            // unique, so concurrent puts of one chunk don't write into each other's file
+            let suffix = thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(8)
+                .map(char::from)
+                .collect::<String>();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let temp = folder.join(format!(".{}.{}.tmp", name, suffix));
+            let written = write_temp(sftp, &temp, &data).and_then(|_| {
+                let flags = RenameFlags::OVERWRITE | RenameFlags::ATOMIC | RenameFlags::NATIVE;
+                match sftp.rename(&temp, &path, Some(flags)) {
+                    // SFTP v3 servers (OpenSSH) refuse to rename over an existing file
+                    Err(e) if matches!(e.code(), ErrorCode::SFTP(_)) => {
+                        sftp.unlink(&path)?;
+                        Ok(sftp.rename(&temp, &path, None)?)
+                    }
+                    result => Ok(result?),
+                }
+            });
+            if written.is_err() {
+                let _ = sftp.unlink(&temp);
+            }
+            written
+        })
+        .await
+    }
+
+    async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error> {
+        let path = chunk_path(Path::new(&self.folder), descriptor)?;
+        self.run(move |sftp, _| Ok(sftp.unlink(&path)?)).await
+    }
+
+    async fn create(&self) -> Result<Descriptor, Error> {
+        let length = self.descriptor_length;
+        self.run(move |sftp, folder| loop {
+            let descriptor = thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(length)
+                .map(char::from)
+                .collect::<String>();
+            let path = folder.join(&descriptor);
+            // EXCLUSIVE makes the server refuse names that are already taken
+            match sftp.open_mode(
+                &path,
+                OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::EXCLUSIVE,
+                0o644,
+                OpenType::File,
+            ) {
+                Ok(_) => return Ok(descriptor.into_bytes()),
+                Err(e) if matches!(e.code(), ErrorCode::SFTP(_)) => {
+                    match sftp.stat(&path) {
+                        Ok(_) => continue, // the name is taken, try another one
+                        Err(_) => return Err(e.into()),
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        })
+        .await
+    }
+}
@@ -1,17 +1,21 @@
 use async_trait::async_trait;
 use serde::Deserialize;
 
-use crate::global::Descriptor;
+use crate::{error::Error, global::Descriptor};
 
-use super::{discord_webhook::DiscordWebhook, github_releases::GithubReleases, local::LocalSource};
+use super::{
+    discord_bot::DiscordBot, discord_webhook::DiscordWebhook, exec::Exec, faulty::FaultySource,
+    github_releases::GithubReleases, local::LocalSource, memory::MemorySource, rclone::Rclone,
+    sftp::Sftp, sqlite::Sqlite, telegram::Telegram, webdav::WebDav,
+};
 
 #[async_trait]
 pub trait Source {
     fn max_size(&self) -> usize;
-    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, String>;
-    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), String>;
-    async fn delete(&self, descriptor: &Descriptor) -> Result<(), String>;
-    async fn create(&self) -> Result<Descriptor, String>;
+    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error>;
+    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error>;
+    async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error>;
+    async fn create(&self) -> Result<Descriptor, Error>;
 }
 
 #[derive(Deserialize, Debug)]
@@ -21,8 +25,26 @@ pub enum SourceType {
     LocalSource(LocalSource),
     #[serde(rename = "discord_webhook")]
     DiscordWebhook(DiscordWebhook),
+    #[serde(rename = "discord_bot")]
+    DiscordBot(DiscordBot),
     #[serde(rename = "github_releases")]
     GithubRelease(GithubReleases),
+    #[serde(rename = "telegram")]
+    Telegram(Telegram),
+    #[serde(rename = "webdav")]
+    WebDav(WebDav),
+    #[serde(rename = "sftp")]
+    Sftp(Sftp),
+    #[serde(rename = "sqlite")]
+    Sqlite(Sqlite),
+    #[serde(rename = "memory")]
+    Memory(MemorySource),
+    #[serde(rename = "faulty")]
+    Faulty(FaultySource),
+    #[serde(rename = "exec")]
+    Exec(Exec),
+    #[serde(rename = "rclone")]
+    Rclone(Rclone),
 }
 
 // This macro removes the need to write out the match statement for each method in the enum
@@ -31,7 +53,16 @@ macro_rules! match_method {
         match $self {
             SourceType::LocalSource(source) => source.$method($($arg),*),
             SourceType::DiscordWebhook(source) => source.$method($($arg),*),
+            SourceType::DiscordBot(source) => source.$method($($arg),*),
             SourceType::GithubRelease(source) => source.$method($($arg),*),
+            SourceType::Telegram(source) => source.$method($($arg),*),
+            SourceType::WebDav(source) => source.$method($($arg),*),
+            SourceType::Sftp(source) => source.$method($($arg),*),
+            SourceType::Sqlite(source) => source.$method($($arg),*),
+            SourceType::Memory(source) => source.$method($($arg),*),
+            SourceType::Faulty(source) => source.$method($($arg),*),
+            SourceType::Exec(source) => source.$method($($arg),*),
+            SourceType::Rclone(source) => source.$method($($arg),*),
         }
     };
 }
@@ -41,7 +72,16 @@ impl SourceType {
         match self {
             SourceType::LocalSource(_) => "local folder",
             SourceType::DiscordWebhook(_) => "discord webhook",
+            SourceType::DiscordBot(_) => "discord bot",
             SourceType::GithubRelease(_) => "github releases",
+            SourceType::Telegram(_) => "telegram",
+            SourceType::WebDav(_) => "webdav",
+            SourceType::Sftp(_) => "sftp",
+            SourceType::Sqlite(_) => "sqlite database",
+            SourceType::Memory(_) => "memory",
+            SourceType::Faulty(_) => "fault injection",
+            SourceType::Exec(_) => "external program",
+            SourceType::Rclone(_) => "rclone remote",
         }
     }
 }
@@ -52,19 +92,19 @@ impl Source for SourceType {
         match_method!(self, max_size,)
     }
 
-    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, String> {
+    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
         match_method!(self, get, descriptor).await
     }
 
-    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), String> {
+    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error> {
         match_method!(self, put, descriptor, data).await
     }
 
-    async fn delete(&self, descriptor: &Descriptor) -> Result<(), String> {
+    async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error> {
         match_method!(self, delete, descriptor).await
     }
 
-    async fn create(&self) -> Result<Descriptor, String> {
+    async fn create(&self) -> Result<Descriptor, Error> {
         match_method!(self, create,).await
     }
 }
@@ -0,0 +1,141 @@
+/*
+   Stores every chunk as a blob row in a single SQLite database file.
+   Descriptors are random alphanumeric primary keys, like the random file names of the local source,
+   so they survive a trip through a url.
+   Keeping millions of tiny chunks in one file avoids running out of inodes and makes backups a single copy.
+*/
+
+use async_trait::async_trait;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension};
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+
+use super::source::Source;
+use crate::{error::Error, global::Descriptor};
+
+#[derive(Debug, Deserialize)]
+pub struct Sqlite {
+    path: String,
+    #[serde(default = "default_max_size")]
+    max_size: usize,
+    #[serde(default = "default_descriptor_length")]
+    descriptor_length: usize,
+
+    #[serde(skip)]
+    connection: Arc<Mutex<Option<Connection>>>,
+}
+
+const fn default_max_size() -> usize {
+    64 * 1024 * 1024 // sqlite caps blobs at 1 GB, but big rows make the file slow to vacuum
+}
+const fn default_descriptor_length() -> usize {
+    16
+}
+
+impl Sqlite {
+    // Runs `f` with the (lazily opened) connection on the blocking thread pool
+    async fn run<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        let path = shellexpand::tilde(&self.path).to_string();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().unwrap();
+            if connection.is_none() {
+                let opened = Connection::open(&path)
+                    .map_err(|e| Error::Unavailable(format!("Error opening database: {}", e)))?;
+                opened
+                    .execute_batch(
+                        "PRAGMA journal_mode = WAL;
+                         CREATE TABLE IF NOT EXISTS chunks (descriptor BLOB PRIMARY KEY, data BLOB NOT NULL) WITHOUT ROWID;",
+                    )
+                    .map_err(|e| Error::Internal(format!("Error preparing database: {}", e)))?;
+                *connection = Some(opened);
+            }
+            f(connection.as_ref().unwrap()).map_err(|e| Error::Internal(format!("Database error: {}", e)))
+        })
+        .await
+        .map_err(|e| Error::Internal(format!("Error joining database task: {}", e)))?
+    }
+}
+
+#[async_trait]
+impl Source for Sqlite {
+    fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
+        let descriptor = descriptor.clone();
+        self.run(move |connection| {
+            connection
+                .query_row(
+                    "SELECT data FROM chunks WHERE descriptor = ?1",
+                    params![descriptor],
+                    |row| row.get(0),
+                )
+                .optional()
+        })
+        .await?
+        .ok_or(Error::NotFound("File not found".to_string()))
+    }
+
+    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error> {
+        let descriptor = descriptor.clone();
+        let updated = self
+            .run(move |connection| {
+                connection.execute(
+                    "UPDATE chunks SET data = ?2 WHERE descriptor = ?1",
+                    params![descriptor, data],
+                )
+            })
+            .await?;
+        // we only should create rows with ::create() to ensure safe descriptors
+        match updated {
+            0 => Err(Error::NotFound("File not found".to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error> {
+        let descriptor = descriptor.clone();
+        let deleted = self
+            .run(move |connection| {
+                connection.execute(
+                    "DELETE FROM chunks WHERE descriptor = ?1",
+                    params![descriptor],
+                )
+            })
+            .await?;
+        match deleted {
+            0 => Err(Error::NotFound("File not found".to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    async fn create(&self) -> Result<Descriptor, Error> {
+        let length = self.descriptor_length;
+        self.run(move |connection| loop {
+            let descriptor = thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(length)
+                .collect::<Vec<u8>>();
+            match connection.execute(
+                "INSERT INTO chunks (descriptor, data) VALUES (?1, x'')",
+                params![descriptor],
+            ) {
+                Ok(_) => return Ok(descriptor),
+                Err(rusqlite::Error::SqliteFailure(e, _))
+                    if e.code == ErrorCode::ConstraintViolation =>
+                {
+                    continue; // the descriptor is taken, try another one
+                }
+                Err(e) => return Err(e),
+            }
+        })
+        .await
+    }
+}
@@ -0,0 +1,272 @@
+/*
+   Stores each chunk as a document message in a telegram chat through the Bot API.
+   The descriptor is the message id. It can't hold the file id, because put replaces the document
+   (and so its file id) while descriptors are fixed at create: buckets use them as the encryption IV,
+   and chunks written in place keep theirs. A file id from create would lead get to the placeholder.
+   File ids are cached instead, and recovered by forwarding the message when the cache is cold.
+*/
+
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use std::{collections::HashMap, sync::Mutex};
+
+use super::source::Source;
+use crate::{error::Error, global::Descriptor};
+
+#[derive(Debug, Deserialize)]
+pub struct Telegram {
+    token: String,
+    chat_id: String,
+    #[serde(default = "default_api_url")]
+    api_url: String,
+    #[serde(default = "default_max_size")]
+    max_size: usize,
+
+    #[serde(skip)]
+    client: Client,
+    #[serde(skip)]
+    file_ids: Mutex<HashMap<Descriptor, String>>,
+}
+
+fn default_api_url() -> String {
+    "https://api.telegram.org".to_string()
+}
+const fn default_max_size() -> usize {
+    // bots can upload 50 MB, but getFile only serves files up to 20 MB
+    1024 * 1024 * 20
+}
+
+/* #region telegram schema */
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+    error_code: Option<u16>, // an http status
+}
+
+#[derive(Deserialize)]
+struct Message {
+    message_id: i64,
+    document: Option<Document>,
+}
+
+#[derive(Deserialize)]
+struct Document {
+    file_id: String,
+}
+
+#[derive(Deserialize)]
+struct FileResponse {
+    file_path: Option<String>,
+}
+/* #endregion */
+
+fn parse_descriptor(descriptor: &Descriptor) -> Result<i64, Error> {
+    std::str::from_utf8(descriptor)
+        .map_err(|e| Error::Invalid(format!("Error parsing descriptor: {}", e)))?
+        .parse::<i64>()
+        .map_err(|e| Error::Invalid(format!("Error parsing descriptor: {}", e)))
+}
+
+impl Telegram {
+    fn method_url(&self, method: &str) -> String {
+        format!(
+            "{}/bot{}/{}",
+            self.api_url.trim_end_matches('/'),
+            self.token,
+            method
+        )
+    }
+
+    async fn parse<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, Error> {
+        let parsed = response
+            .json::<ApiResponse<T>>()
+            .await
+            .map_err(|e| Error::http("Error parsing response", e))?;
+        match (parsed.ok, parsed.result) {
+            (true, Some(result)) => Ok(result),
+            _ => Err(Error::status(
+                parsed
+                    .error_code
+                    .and_then(|code| StatusCode::from_u16(code).ok())
+                    .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                format!(
+                    "Telegram returned an error: {}",
+                    parsed.description.unwrap_or_default()
+                ),
+            )),
+        }
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        body: serde_json::Value,
+    ) -> Result<T, Error> {
+        let response = self
+            .client
+            .post(self.method_url(method))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| Error::http("Error sending request", e))?;
+        Self::parse(response).await
+    }
+
+    fn document_form(&self, data: Vec<u8>) -> Result<reqwest::multipart::Form, Error> {
+        let part = reqwest::multipart::Part::bytes(data)
+            .file_name("d")
+            .mime_str("application/octet-stream")
+            .map_err(|e| Error::http("Error creating part", e))?;
+        Ok(reqwest::multipart::Form::new()
+            .text("chat_id", self.chat_id.clone())
+            .text("disable_notification", "true")
+            .part("document", part))
+    }
+
+    async fn file_id(&self, descriptor: &Descriptor) -> Result<String, Error> {
+        if let Some(file_id) = self.file_ids.lock().unwrap().get(descriptor) {
+            return Ok(file_id.clone());
+        }
+
+        // the Bot API can't fetch a message by id, but forwarding returns the full message
+        let message_id = parse_descriptor(descriptor)?;
+        let forwarded: Message = self
+            .call(
+                "forwardMessage",
+                json!({
+                    "chat_id": self.chat_id,
+                    "from_chat_id": self.chat_id,
+                    "message_id": message_id,
+                    "disable_notification": true,
+                }),
+            )
+            .await?;
+        let _: bool = self
+            .call(
+                "deleteMessage",
+                json!({ "chat_id": self.chat_id, "message_id": forwarded.message_id }),
+            )
+            .await?;
+
+        let file_id = forwarded
+            .document
+            .ok_or(Error::NotFound("Message has no document".to_string()))?
+            .file_id;
+        self.file_ids
+            .lock()
+            .unwrap()
+            .insert(descriptor.clone(), file_id.clone());
+        Ok(file_id)
+    }
+}
+
+#[async_trait]
+impl Source for Telegram {
+    fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
+        let file_id = self.file_id(descriptor).await?;
+        let file: FileResponse = self.call("getFile", json!({ "file_id": file_id })).await?;
+        let path = file
+            .file_path
+            .ok_or(Error::NotFound("File has no path".to_string()))?;
+        let url = format!(
+            "{}/file/bot{}/{}",
+            self.api_url.trim_end_matches('/'),
+            self.token,
+            path
+        );
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| Error::http("Error sending request", e))?;
+        if !response.status().is_success() {
+            return Err(Error::status(
+                response.status(),
+                format!("Error downloading file: {}", response.status()),
+            ));
+        }
+        Ok(response
+            .bytes()
+            .await
+            .map_err(|e| Error::http("Error reading response", e))?
+            .to_vec())
+    }
+
+    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error> {
+        let message_id = parse_descriptor(descriptor)?;
+        let part = reqwest::multipart::Part::bytes(data)
+            .file_name("d")
+            .mime_str("application/octet-stream")
+            .map_err(|e| Error::http("Error creating part", e))?;
+        let form = reqwest::multipart::Form::new()
+            .text("chat_id", self.chat_id.clone())
+            .text("message_id", message_id.to_string())
+            .text(
+                "media",
+                json!({ "type": "document", "media": "attach://d" }).to_string(),
+            )
+            .part("d", part);
+        let response = self
+            .client
+            .post(self.method_url("editMessageMedia"))
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|e| Error::http("Error sending request", e))?;
+        let message: Message = Self::parse(response).await?;
+        match message.document {
+            Some(document) => {
+                self.file_ids
+                    .lock()
+                    .unwrap()
+                    .insert(descriptor.clone(), document.file_id);
+            }
+            None => {
+                self.file_ids.lock().unwrap().remove(descriptor);
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error> {
+        let message_id = parse_descriptor(descriptor)?;
+        let _: bool = self
+            .call(
+                "deleteMessage",
+                json!({ "chat_id": self.chat_id, "message_id": message_id }),
+            )
+            .await?;
+        self.file_ids.lock().unwrap().remove(descriptor);
+        Ok(())
+    }
+
+    async fn create(&self) -> Result<Descriptor, Error> {
+        // telegram rejects empty documents, so the message starts with a single byte placeholder
+        let form = self.document_form(vec![0])?;
+        let response = self
+            .client
+            .post(self.method_url("sendDocument"))
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|e| Error::http("Error sending request", e))?;
+        let message: Message = Self::parse(response).await?;
+        let descriptor = message.message_id.to_string().into_bytes();
+        if let Some(document) = message.document {
+            self.file_ids
+                .lock()
+                .unwrap()
+                .insert(descriptor.clone(), document.file_id);
+        }
+        Ok(descriptor)
+    }
+}
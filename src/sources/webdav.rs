@@ -0,0 +1,183 @@
+/*
+   Stores each chunk as a file on a WebDAV server (Nextcloud, ownCloud, Apache mod_dav, ...).
+   Files get random names, and create relies on `If-None-Match: *` so two clients never claim the same name.
+*/
+
+use async_trait::async_trait;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use reqwest::{
+    header::{IF_MATCH, IF_NONE_MATCH},
+    Client, Method, RequestBuilder, StatusCode,
+};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::source::Source;
+use crate::{error::Error, global::Descriptor};
+
+#[derive(Debug, Deserialize)]
+pub struct WebDav {
+    url: String,
+    #[serde(default)]
+    prefix: String,
+    username: Option<String>,
+    password: Option<String>,
+    token: Option<String>,
+    #[serde(default = "default_max_size")]
+    max_size: usize,
+    #[serde(default = "default_descriptor_length")]
+    descriptor_length: usize,
+
+    #[serde(skip)]
+    client: Client,
+    #[serde(skip)]
+    prefix_created: AtomicBool,
+}
+
+const fn default_max_size() -> usize {
+    512 * 1024 * 1024
+}
+const fn default_descriptor_length() -> usize {
+    24
+}
+
+impl WebDav {
+    fn collection_url(&self) -> String {
+        let prefix = self.prefix.trim_matches('/');
+        match prefix.is_empty() {
+            true => self.url.trim_end_matches('/').to_string(),
+            false => format!("{}/{}", self.url.trim_end_matches('/'), prefix),
+        }
+    }
+
+    fn file_url(&self, descriptor: &Descriptor) -> Result<String, Error> {
+        let name = std::str::from_utf8(descriptor)
+            .map_err(|e| Error::Invalid(format!("Error parsing descriptor: {}", e)))?;
+        Ok(format!("{}/{}", self.collection_url(), name))
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match (&self.token, &self.username) {
+            (Some(token), _) => request.bearer_auth(token),
+            (None, Some(username)) => request.basic_auth(username, self.password.as_ref()),
+            (None, None) => request,
+        }
+    }
+
+    // Creates every collection of the prefix, servers answer 405 for the ones that already exist
+    async fn ensure_prefix(&self) -> Result<(), Error> {
+        if self.prefix_created.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let mkcol = Method::from_bytes(b"MKCOL").unwrap();
+        let mut url = self.url.trim_end_matches('/').to_string();
+        for part in self.prefix.split('/').filter(|part| !part.is_empty()) {
+            url = format!("{}/{}", url, part);
+            let response = self
+                .request(mkcol.clone(), &format!("{}/", url))
+                .send()
+                .await
+                .map_err(|e| Error::http("Error sending request", e))?;
+            if !response.status().is_success()
+                && response.status() != StatusCode::METHOD_NOT_ALLOWED
+            {
+                return Err(Error::status(
+                    response.status(),
+                    format!("Error creating collection {}: {}", url, response.status()),
+                ));
+            }
+        }
+        self.prefix_created.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Source for WebDav {
+    fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    async fn get(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
+        let response = self
+            .request(Method::GET, &self.file_url(descriptor)?)
+            .send()
+            .await
+            .map_err(|e| Error::http("Error sending request", e))?;
+        match response.status() {
+            status if !status.is_success() => Err(Error::status(
+                status,
+                format!("Error reading file: {}", status),
+            )),
+            _ => Ok(response
+                .bytes()
+                .await
+                .map_err(|e| Error::http("Error reading response", e))?
+                .to_vec()),
+        }
+    }
+
+    async fn put(&self, descriptor: &Descriptor, data: Vec<u8>) -> Result<(), Error> {
+        // the file should already exist, as we only should create files with ::create() to ensure safe descriptors
+        let response = self
+            .request(Method::PUT, &self.file_url(descriptor)?)
+            .header(IF_MATCH, "*")
+            .body(data)
+            .send()
+            .await
+            .map_err(|e| Error::http("Error sending request", e))?;
+        match response.status() {
+            StatusCode::PRECONDITION_FAILED => Err(Error::NotFound("File not found".to_string())),
+            status if !status.is_success() => Err(Error::status(
+                status,
+                format!("Error writing file: {}", status),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    async fn delete(&self, descriptor: &Descriptor) -> Result<(), Error> {
+        let response = self
+            .request(Method::DELETE, &self.file_url(descriptor)?)
+            .send()
+            .await
+            .map_err(|e| Error::http("Error sending request", e))?;
+        match response.status() {
+            status if !status.is_success() => Err(Error::status(
+                status,
+                format!("Error deleting file: {}", status),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    async fn create(&self) -> Result<Descriptor, Error> {
+        self.ensure_prefix().await?;
+        loop {
+            let descriptor = thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(self.descriptor_length)
+                .map(char::from)
+                .collect::<String>()
+                .into_bytes();
+            let response = self
+                .request(Method::PUT, &self.file_url(&descriptor)?)
+                .header(IF_NONE_MATCH, "*")
+                .body(Vec::new())
+                .send()
+                .await
+                .map_err(|e| Error::http("Error sending request", e))?;
+            match response.status() {
+                StatusCode::PRECONDITION_FAILED => continue, // the name is taken, try another one
+                status if !status.is_success() => {
+                    return Err(Error::status(
+                        status,
+                        format!("Error creating file: {}", status),
+                    ))
+                }
+                _ => return Ok(descriptor),
+            }
+        }
+    }
+}
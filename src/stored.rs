@@ -4,7 +4,10 @@
    It uses messagepack for serialization for backwards compatibility.
 */
 
-use crate::global::{Descriptor, GlobalTrait};
+use crate::{
+    error::Error,
+    global::{Descriptor, GlobalTrait},
+};
 use rmp_serde::{Deserializer, Serializer};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -24,33 +27,53 @@ impl PartialEq for Stored {
 }
 
 impl Stored {
+    pub fn new(bucket: String, descriptor: Descriptor) -> Self {
+        Stored { bucket, descriptor }
+    }
+
+    // Serializes the same way Stored::put does
+    pub fn serialize<T: Serialize>(data: &T) -> Result<Vec<u8>, Error> {
+        let mut serializer = Serializer::new(Vec::new()).with_struct_map(); // https://github.com/3Hren/msgpack-rust/issues/318
+        data.serialize(&mut serializer)
+            .map_err(|e| Error::Internal(format!("Error serializing: {}", e)))?;
+        Ok(serializer.into_inner())
+    }
+
     pub async fn get<T: Deserialize<'static>, U: GlobalTrait>(
         &self,
         global: Arc<U>,
-    ) -> Result<T, String> {
-        // Get bucket
-        let bucket = global.get_bucket(&self.bucket).ok_or("Bucket not found")?;
-
+    ) -> Result<T, Error> {
         // Get data
-        let data = bucket.get(&self.descriptor).await?;
+        let data = self.get_bytes(global).await?;
 
         // Deserialize data
         let mut deserializer = Deserializer::new(&data[..]);
-        T::deserialize(&mut deserializer).map_err(|e| e.to_string())
+        T::deserialize(&mut deserializer).map_err(|e| Error::deserialize("Error deserializing", e))
+    }
+
+    // Gets the serialized bytes, for callers that need to check them before deserializing
+    pub async fn get_bytes<U: GlobalTrait>(&self, global: Arc<U>) -> Result<Vec<u8>, Error> {
+        // Get bucket
+        let bucket = global
+            .get_bucket(&self.bucket)
+            .ok_or_else(|| Error::NotFound(format!("Bucket {} not found", self.bucket)))?;
+
+        // Get data
+        bucket.get(&self.descriptor).await
     }
 
     pub async fn put<T: Serialize, U: GlobalTrait>(
         &self,
         global: Arc<U>,
         data: T,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         // Serialize data
-        let mut serializer = Serializer::new(Vec::new()).with_struct_map(); // https://github.com/3Hren/msgpack-rust/issues/318
-        data.serialize(&mut serializer).map_err(|e| e.to_string())?;
-        let data = serializer.into_inner();
+        let data = Self::serialize(&data)?;
 
         // Get bucket
-        let bucket = global.get_bucket(&self.bucket).ok_or("Bucket not found")?;
+        let bucket = global
+            .get_bucket(&self.bucket)
+            .ok_or_else(|| Error::NotFound(format!("Bucket {} not found", self.bucket)))?;
 
         // Put data
         bucket.put(&self.descriptor, data).await?;
@@ -61,17 +84,25 @@ impl Stored {
     pub async fn create<T: Serialize, U: GlobalTrait>(
         global: Arc<U>,
         data: T,
-    ) -> Result<Stored, String> {
+    ) -> Result<Stored, Error> {
         // Serialize data
-        let mut serializer = Serializer::new(Vec::new()).with_struct_map(); // https://github.com/3Hren/msgpack-rust/issues/318
-        data.serialize(&mut serializer).map_err(|e| e.to_string())?;
-        let data = serializer.into_inner();
+        let data = Self::serialize(&data)?;
 
+        Self::create_bytes(global, data).await
+    }
+
+    // Stores already serialized bytes, see Stored::serialize
+    pub async fn create_bytes<U: GlobalTrait>(
+        global: Arc<U>,
+        data: Vec<u8>,
+    ) -> Result<Stored, Error> {
         // Find bucket
-        let bucket_name = global
-            .next_bucket(data.len(), &Vec::new())
-            .ok_or(format!("No bucket found for data of size {}", data.len()))?;
-        let bucket = global.get_bucket(bucket_name).ok_or("Bucket not found")?;
+        let bucket_name = global.next_bucket(data.len(), &Vec::new()).ok_or_else(|| {
+            Error::Internal(format!("No bucket found for data of size {}", data.len()))
+        })?;
+        let bucket = global
+            .get_bucket(bucket_name)
+            .ok_or_else(|| Error::NotFound(format!("Bucket {} not found", bucket_name)))?;
 
         // Put data
         let descriptor = bucket.create().await?;
@@ -83,9 +114,16 @@ impl Stored {
         })
     }
 
-    pub async fn delete<U: GlobalTrait>(&self, global: Arc<U>) -> Result<(), String> {
+    pub async fn delete<U: GlobalTrait>(&self, global: Arc<U>) -> Result<(), Error> {
+        // Chunks a snapshot uses are freed with the last of those snapshots
+        if global.is_pinned(self) {
+            return Ok(());
+        }
+
         // Get bucket
-        let bucket = global.get_bucket(&self.bucket).ok_or("Bucket not found")?;
+        let bucket = global
+            .get_bucket(&self.bucket)
+            .ok_or_else(|| Error::NotFound(format!("Bucket {} not found", self.bucket)))?;
 
         // Delete data
         bucket.delete(&self.descriptor).await
@@ -99,9 +137,9 @@ impl Stored {
         )
     }
 
-    pub fn from_url(bucket: &str, descriptor: &str) -> Result<Stored, String> {
+    pub fn from_url(bucket: &str, descriptor: &str) -> Result<Stored, Error> {
         let bucket = urlencoding::decode(bucket)
-            .map_err(|_| "Invalid bucket")?
+            .map_err(|_| Error::Invalid("Invalid bucket".to_string()))?
             .to_string();
 
         let descriptor = urlencoding::decode_binary(descriptor.as_bytes()).to_vec();
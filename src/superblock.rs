@@ -0,0 +1,168 @@
+/*
+   The superblock is a small record in a bucket that points to the current root directory.
+   Roots are written copy-on-write: a new root is stored first, then the superblock is switched over to it
+   and only then the old root is deleted, so a crash at any point leaves a readable drive behind.
+   A save has to start from the version that was last loaded or saved here, so two writers can't switch the
+   superblock over to their own root and delete each other's.
+   The location of the superblock (the bootstrap descriptor) is all another machine needs to open the drive.
+*/
+
+use crypto::{digest::Digest, sha2::Sha256};
+use rmp_serde::Deserializer;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+use crate::{error::Error, global::GlobalTrait, inodes::directory::Directory, stored::Stored};
+
+#[derive(Deserialize, Debug)]
+pub struct SuperblockConfig {
+    bucket: String,            // where a new superblock is created
+    bootstrap: Option<String>, // the superblock to open, in the url form of Stored
+    #[serde(default = "default_bootstrap_path")]
+    bootstrap_path: String, // remembers the bootstrap descriptor when it isn't configured
+
+    #[serde(skip)]
+    loaded: Mutex<Option<u64>>, // the version our root is based on, None before there is a superblock
+}
+
+fn default_bootstrap_path() -> String {
+    "./superblock.url".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Superblock {
+    #[serde(rename = "v")]
+    pub version: u64,
+    #[serde(rename = "r")]
+    pub root: Stored,
+    #[serde(rename = "c")]
+    pub checksum: String, // sha256 of the serialized root
+}
+
+pub fn checksum(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    hasher.result_str()
+}
+
+fn changed(loaded: Option<u64>) -> Error {
+    Error::Conflict(match loaded {
+        Some(version) => format!("The superblock changed since version {} was read", version),
+        None => "The superblock was created or changed since it was read".to_string(),
+    })
+}
+
+impl SuperblockConfig {
+    // Where the superblock lives, None if it was never created
+    pub fn location(&self) -> Result<Option<Stored>, Error> {
+        let url = match &self.bootstrap {
+            Some(url) => url.clone(),
+            None => {
+                match std::fs::read_to_string(shellexpand::tilde(&self.bootstrap_path).as_ref()) {
+                    Ok(url) => url,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                    Err(e) => return Err(Error::io("Error reading bootstrap descriptor", e)),
+                }
+            }
+        };
+        let (bucket, descriptor) = url
+            .trim()
+            .split_once('$')
+            .ok_or(Error::Invalid("Invalid bootstrap descriptor".to_string()))?;
+        Stored::from_url(bucket, descriptor).map(Some)
+    }
+
+    pub async fn load<U: GlobalTrait>(
+        &self,
+        global: Arc<U>,
+    ) -> Result<Option<(Superblock, Directory)>, Error> {
+        let location = match self.location()? {
+            Some(location) => location,
+            None => {
+                *self.loaded.lock().unwrap() = None;
+                return Ok(None);
+            }
+        };
+        let superblock = location.get::<Superblock, U>(global.clone()).await?;
+        let data = superblock.root.get_bytes(global).await?;
+        if checksum(&data) != superblock.checksum {
+            return Err(Error::Corrupted(format!(
+                "Root of superblock version {} fails its checksum",
+                superblock.version
+            )));
+        }
+        let mut deserializer = Deserializer::new(&data[..]);
+        let root = Directory::deserialize(&mut deserializer)
+            .map_err(|e| Error::deserialize("Error parsing root", e))?;
+        *self.loaded.lock().unwrap() = Some(superblock.version);
+        Ok(Some((superblock, root)))
+    }
+
+    // Stores `root` and points the superblock at it, creating the superblock on first use
+    pub async fn save<U: GlobalTrait>(
+        &self,
+        global: Arc<U>,
+        root: &Directory,
+    ) -> Result<Superblock, Error> {
+        let location = self.location()?;
+        let previous = match &location {
+            Some(location) => Some(location.get::<Superblock, U>(global.clone()).await?),
+            None => None,
+        };
+        let loaded = *self.loaded.lock().unwrap();
+        if previous.as_ref().map(|p| p.version) != loaded {
+            return Err(changed(loaded));
+        }
+
+        let data = Stored::serialize(root)?;
+        let superblock = Superblock {
+            version: previous.as_ref().map(|p| p.version + 1).unwrap_or(1),
+            checksum: checksum(&data),
+            root: Stored::create_bytes(global.clone(), data).await?,
+        };
+
+        match location {
+            Some(location) => {
+                // storing the root took a while, somebody may have switched the superblock in the meantime
+                let current = location.get::<Superblock, U>(global.clone()).await;
+                if current.as_ref().ok().map(|c| c.version) != loaded {
+                    if let Err(e) = superblock.root.delete(global.clone()).await {
+                        println!("failed to delete unused root: {}", e);
+                    }
+                    return Err(match current {
+                        Ok(_) => changed(loaded),
+                        Err(e) => e,
+                    });
+                }
+                location.put(global.clone(), &superblock).await?
+            }
+            None => {
+                let bucket = global.get_bucket(&self.bucket).ok_or_else(|| {
+                    Error::NotFound(format!("Superblock bucket {} not found", self.bucket))
+                })?;
+                let location = Stored::new(self.bucket.clone(), bucket.create().await?);
+                location.put(global.clone(), &superblock).await?;
+                if self.bootstrap.is_none() {
+                    std::fs::write(
+                        shellexpand::tilde(&self.bootstrap_path).as_ref(),
+                        location.as_url(),
+                    )
+                    .map_err(|e| Error::io("Error saving bootstrap descriptor", e))?;
+                }
+                println!(
+                    "created superblock, bootstrap descriptor: {}",
+                    location.as_url()
+                );
+            }
+        }
+
+        *self.loaded.lock().unwrap() = Some(superblock.version);
+        // the old root is unreachable now, failing to delete it only leaks a chunk
+        if let Some(previous) = previous {
+            if let Err(e) = previous.root.delete(global).await {
+                println!("failed to delete old root: {}", e);
+            }
+        }
+        Ok(superblock)
+    }
+}
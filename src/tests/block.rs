@@ -10,7 +10,7 @@ use crate::{
 
 async fn shared1(encryption: bool, local_size: usize, data: Vec<u8>) {
     let global = Arc::new(from_str::<Global>(&make_temp_config(encryption, local_size)).unwrap());
-    let range = 0..data.len();
+    let range = 0..data.len() as u64;
     let mut block = BlockType::create(global.clone(), data.clone(), 0)
         .await
         .unwrap();
@@ -35,7 +35,7 @@ async fn shared1(encryption: bool, local_size: usize, data: Vec<u8>) {
             y as u8
         })
         .collect::<Vec<u8>>();
-    let range1 = 0..data1.len();
+    let range1 = 0..data1.len() as u64;
     block
         .put(global.clone(), data1.clone(), range1.clone())
         .await
@@ -53,30 +53,30 @@ async fn shared1(encryption: bool, local_size: usize, data: Vec<u8>) {
 
 #[tokio::test]
 async fn unencrypted_fits_in_one_block() {
-    let data = vec![1u8, 2, 3, 4, 5].repeat(5);
+    let data = [1u8, 2, 3, 4, 5].repeat(5);
     shared1(false, 30, data).await;
 }
 
 #[tokio::test]
 async fn encrypted_fits_in_one_block() {
-    let data = vec![1u8, 2, 3, 4, 5].repeat(3);
+    let data = [1u8, 2, 3, 4, 5].repeat(3);
     shared1(true, 30, data).await;
 }
 
 #[tokio::test]
 async fn unencrypted_fits_direct_blocks() {
-    let data: Vec<u8> = vec![1u8, 2, 3, 4, 5].repeat(10);
+    let data: Vec<u8> = [1u8, 2, 3, 4, 5].repeat(10);
     shared1(false, 30, data).await;
 }
 
 #[tokio::test]
 async fn encrypted_fits_direct_blocks() {
-    let data = vec![1u8, 2, 3, 4, 5].repeat(10);
+    let data = [1u8, 2, 3, 4, 5].repeat(10);
     shared1(true, 30, data).await;
 }
 
 #[tokio::test]
 async fn unencrypted_needs_indirect_blocks() {
-    let data = vec![1u8, 2, 3, 4, 5].repeat(10_000);
+    let data = [1u8, 2, 3, 4, 5].repeat(10_000);
     shared1(false, 700, data).await;
 }
@@ -8,7 +8,7 @@ fn shared_default(encryption: bool) {
     let cfg = make_temp_config(encryption, 25);
     let global = from_str::<Global>(&cfg).unwrap();
 
-    let data = vec![1u8, 2, 3, 4, 5].repeat(5);
+    let data = [1u8, 2, 3, 4, 5].repeat(5);
     let bucket = global
         .get_bucket(global.random_bucket().unwrap().as_str())
         .unwrap();
@@ -21,9 +21,8 @@ fn shared_default(encryption: bool) {
         let data2 = bucket.get(&descriptor).await.unwrap();
         assert_eq!(data, data2);
         bucket.delete(&descriptor).await.unwrap();
-        match bucket.get(&descriptor).await {
-            Ok(_) => panic!("Descriptor should not exist"),
-            Err(_) => (),
+        if bucket.get(&descriptor).await.is_ok() {
+            panic!("Descriptor should not exist");
         }
     });
 }
@@ -0,0 +1,293 @@
+use actix_multipart::form::{bytes::Bytes, json::Json, MultipartForm};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use serde_json::{json, Value};
+use serde_yaml::from_str;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::sources::{
+    discord_bot::{attachment_expiry, route_key, DiscordBot},
+    source::Source,
+};
+
+#[test]
+fn expiry_from_url() {
+    let url = "https://cdn.discordapp.com/attachments/1/2/d?ex=6650c1a2&is=664f7022&hm=abc&";
+    assert_eq!(attachment_expiry(url), Some(0x6650c1a2));
+    assert_eq!(
+        attachment_expiry("https://cdn.discordapp.com/attachments/1/2/d"),
+        None
+    );
+}
+
+#[test]
+fn routes_keep_the_channel() {
+    assert_eq!(
+        route_key("PATCH", "channels/123/messages/456"),
+        "PATCH channels/123/messages/:id"
+    );
+    assert_eq!(
+        route_key("PATCH", "channels/123/messages/789"),
+        route_key("PATCH", "channels/123/messages/456")
+    );
+    assert_ne!(
+        route_key("POST", "channels/123/messages"),
+        route_key("POST", "channels/321/messages")
+    );
+}
+
+// The channels as the mock API keeps them
+#[derive(Default)]
+struct Server {
+    address: String,
+    last_id: u64,
+    messages: HashMap<(String, u64), Vec<u8>>, // (channel, message id) to the attachment
+    calls: Vec<(String, Instant)>,             // method and path of every API call
+    url_ttl: i64,           // seconds until attachment urls expire, may be negative
+    limited: usize,         // how many more calls are answered with 429
+    exhausted: Option<f64>, // answer calls as the last one before a reset this many seconds away
+}
+
+type State = web::Data<Mutex<Server>>;
+
+impl Server {
+    fn message(&self, channel: &str, id: u64) -> Value {
+        let expiry = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64
+            + self.url_ttl;
+        json!({
+            "id": id.to_string(),
+            "attachments": [{
+                "url": format!("http://{}/attachments/{}/{}/d?ex={:x}", self.address, channel, id, expiry),
+            }],
+        })
+    }
+
+    // Records the call, and answers it with a rate limit while `limited` lasts
+    fn call(&mut self, request: &HttpRequest) -> Option<HttpResponse> {
+        self.calls.push((
+            format!("{} {}", request.method(), request.path()),
+            Instant::now(),
+        ));
+        if self.limited > 0 {
+            self.limited -= 1;
+            return Some(
+                HttpResponse::TooManyRequests()
+                    .json(json!({ "retry_after": 0.05, "global": false })),
+            );
+        }
+        None
+    }
+
+    fn respond(&self, mut response: actix_web::HttpResponseBuilder, body: Value) -> HttpResponse {
+        if let Some(reset_after) = self.exhausted {
+            response
+                .insert_header(("x-ratelimit-remaining", "0"))
+                .insert_header(("x-ratelimit-reset-after", reset_after.to_string()));
+        }
+        response.json(body)
+    }
+}
+
+#[derive(MultipartForm)]
+struct Upload {
+    payload_json: Json<Value>,
+    #[multipart(rename = "files[0]")]
+    file: Bytes,
+}
+
+async fn create(
+    state: State,
+    request: HttpRequest,
+    channel: web::Path<String>,
+    form: MultipartForm<Upload>,
+) -> HttpResponse {
+    let mut server = state.lock().unwrap();
+    if let Some(limited) = server.call(&request) {
+        return limited;
+    }
+    assert_eq!(form.payload_json["attachments"][0]["filename"], "d");
+    server.last_id += 1;
+    let id = server.last_id;
+    server
+        .messages
+        .insert((channel.clone(), id), form.file.data.to_vec());
+    let message = server.message(&channel, id);
+    server.respond(HttpResponse::Ok(), message)
+}
+
+async fn edit(
+    state: State,
+    request: HttpRequest,
+    path: web::Path<(String, u64)>,
+    form: MultipartForm<Upload>,
+) -> HttpResponse {
+    let mut server = state.lock().unwrap();
+    if let Some(limited) = server.call(&request) {
+        return limited;
+    }
+    let key = path.into_inner();
+    match server.messages.get_mut(&key) {
+        Some(data) => *data = form.file.data.to_vec(),
+        None => return HttpResponse::NotFound().json(json!({ "message": "Unknown Message" })),
+    }
+    let message = server.message(&key.0, key.1);
+    server.respond(HttpResponse::Ok(), message)
+}
+
+async fn message(
+    state: State,
+    request: HttpRequest,
+    path: web::Path<(String, u64)>,
+) -> HttpResponse {
+    let mut server = state.lock().unwrap();
+    if let Some(limited) = server.call(&request) {
+        return limited;
+    }
+    let key = path.into_inner();
+    match (
+        request.method().as_str(),
+        server.messages.contains_key(&key),
+    ) {
+        (_, false) => HttpResponse::NotFound().json(json!({ "message": "Unknown Message" })),
+        ("DELETE", true) => {
+            server.messages.remove(&key);
+            HttpResponse::NoContent().finish()
+        }
+        _ => {
+            let message = server.message(&key.0, key.1);
+            server.respond(HttpResponse::Ok(), message)
+        }
+    }
+}
+
+async fn attachment(state: State, path: web::Path<(String, u64)>) -> HttpResponse {
+    match state.lock().unwrap().messages.get(&path.into_inner()) {
+        Some(data) => HttpResponse::Ok().body(data.clone()),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+// Serves the mock API on a free port, returns the source's config
+fn serve(state: State) -> String {
+    let data = state.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .route("/channels/{channel}/messages", web::post().to(create))
+            .route("/channels/{channel}/messages/{id}", web::patch().to(edit))
+            .route("/channels/{channel}/messages/{id}", web::get().to(message))
+            .route(
+                "/channels/{channel}/messages/{id}",
+                web::delete().to(message),
+            )
+            .route("/attachments/{channel}/{id}/d", web::get().to(attachment))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let address = server.addrs()[0].to_string();
+    state.lock().unwrap().address = address.clone();
+    tokio::spawn(server.run());
+    format!(
+        "token: secret\nchannels: [\"10\", \"20\"]\napi_url: http://{}",
+        address
+    )
+}
+
+fn state(url_ttl: i64) -> State {
+    web::Data::new(Mutex::new(Server {
+        url_ttl,
+        ..Default::default()
+    }))
+}
+
+fn count(state: &State, call: &str) -> usize {
+    let server = state.lock().unwrap();
+    server.calls.iter().filter(|(c, _)| c == call).count()
+}
+
+#[tokio::test]
+async fn roundtrip() {
+    let state = state(3600);
+    let source = from_str::<DiscordBot>(&serve(state.clone())).unwrap();
+
+    let data = [1u8, 2, 3, 4, 5].repeat(5);
+    let descriptor = source.create().await.unwrap();
+    assert_eq!(descriptor, b"10/1".to_vec());
+    assert_eq!(source.get(&descriptor).await.unwrap(), Vec::<u8>::new());
+    source.put(&descriptor, data.clone()).await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), data);
+    assert_eq!(state.lock().unwrap().messages[&("10".to_string(), 1)], data);
+    // messages are spread over the channels
+    assert_eq!(source.create().await.unwrap(), b"20/2".to_vec());
+
+    source.delete(&descriptor).await.unwrap();
+    assert!(source.get(&descriptor).await.unwrap_err().is_not_found());
+    assert!(source.put(&descriptor, data).await.is_err());
+}
+
+#[tokio::test]
+async fn attachment_urls_are_cached_until_they_expire() {
+    let fresh = state(3600);
+    let source = from_str::<DiscordBot>(&serve(fresh.clone())).unwrap();
+    let descriptor = source.create().await.unwrap();
+    for _ in 0..3 {
+        source.get(&descriptor).await.unwrap();
+    }
+    assert_eq!(count(&fresh, "GET /channels/10/messages/1"), 1);
+
+    // a put changes the attachment, so its url is fetched again
+    source.put(&descriptor, vec![1]).await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), vec![1]);
+    assert_eq!(count(&fresh, "GET /channels/10/messages/1"), 2);
+
+    let expired = state(-60);
+    let source = from_str::<DiscordBot>(&serve(expired.clone())).unwrap();
+    let descriptor = source.create().await.unwrap();
+    for _ in 0..3 {
+        source.get(&descriptor).await.unwrap();
+    }
+    assert_eq!(count(&expired, "GET /channels/10/messages/1"), 3);
+}
+
+#[tokio::test]
+async fn rate_limits_are_waited_out() {
+    let state = state(3600);
+    let source = from_str::<DiscordBot>(&serve(state.clone())).unwrap();
+
+    // 429s are retried after retry_after
+    state.lock().unwrap().limited = 2;
+    let descriptor = source.create().await.unwrap();
+    assert_eq!(count(&state, "POST /channels/10/messages"), 3);
+
+    // a route without remaining requests is left alone until its reset
+    state.lock().unwrap().exhausted = Some(0.3);
+    source.put(&descriptor, vec![1]).await.unwrap();
+    state.lock().unwrap().exhausted = None;
+    source.put(&descriptor, vec![2]).await.unwrap();
+    let puts = state
+        .lock()
+        .unwrap()
+        .calls
+        .iter()
+        .filter(|(call, _)| call == "PATCH /channels/10/messages/1")
+        .map(|(_, at)| *at)
+        .collect::<Vec<_>>();
+    assert_eq!(puts.len(), 2);
+    assert!(puts[1] - puts[0] >= Duration::from_millis(300));
+
+    // with too many 429s in a row it gives up
+    state.lock().unwrap().limited = 100;
+    let source = from_str::<DiscordBot>(&format!(
+        "token: secret\nchannels: [\"10\"]\nmax_retries: 1\napi_url: http://{}",
+        state.lock().unwrap().address
+    ))
+    .unwrap();
+    assert!(source.create().await.unwrap_err().is_transient());
+}
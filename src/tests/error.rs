@@ -0,0 +1,144 @@
+use actix_web::http::StatusCode;
+use serde_yaml::from_str;
+use std::{error::Error as _, sync::Arc};
+
+use super::utils::{make_faulty_config, make_temp_config};
+use crate::{
+    encryption::{aes::Aes, encryption::Encryption},
+    error::{Context, Error},
+    global::Global,
+    services::http::service::status_code,
+    sources::{memory::MemorySource, source::Source},
+    stored::Stored,
+};
+
+#[tokio::test]
+async fn missing_chunks_are_not_found() {
+    let source = from_str::<MemorySource>("{}").unwrap();
+    let descriptor = source.create().await.unwrap();
+    source.delete(&descriptor).await.unwrap();
+    let error = source.get(&descriptor).await.unwrap_err();
+    assert!(error.is_not_found());
+    assert!(!error.is_transient());
+}
+
+#[tokio::test]
+async fn failing_sources_are_transient() {
+    let global = Arc::new(from_str::<Global>(&make_faulty_config(30, "fail_rate: 1.0")).unwrap());
+    let error = Stored::create_bytes(global, vec![1, 2, 3])
+        .await
+        .unwrap_err();
+    assert!(error.is_transient());
+    assert_eq!(status_code(&error), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn missing_buckets_are_not_found() {
+    let global = Arc::new(from_str::<Global>(&make_temp_config(false, 100)).unwrap());
+    let stored = Stored::new("nope".to_string(), vec![1, 2, 3]);
+    let error = stored.get_bytes(global).await.unwrap_err();
+    assert!(matches!(error, Error::NotFound(_)));
+}
+
+#[test]
+fn wrong_keys_fail_to_decrypt() {
+    let aes = from_str::<Aes>("key: right").unwrap();
+    let encrypted = aes.encrypt(vec![5; 100], vec![1; 16]).unwrap();
+    let other = from_str::<Aes>("key: wrong").unwrap();
+    assert!(matches!(
+        other.decrypt(encrypted.clone(), vec![1; 16]),
+        Err(Error::Decryption(_))
+    ));
+    assert!(matches!(
+        aes.decrypt(encrypted[..99].to_vec(), vec![1; 16]),
+        Err(Error::Decryption(_))
+    ));
+}
+
+#[test]
+fn context_keeps_the_kind() {
+    let result: Result<(), Error> = Err(Error::NotFound("chunk abc".to_string()));
+    let error = result
+        .context("Could not get the data")
+        .unwrap_err()
+        .context("Could not read file.txt");
+    assert_eq!(
+        error.to_string(),
+        "Could not read file.txt: Could not get the data: chunk abc"
+    );
+    assert!(error.is_not_found());
+    assert_eq!(status_code(&error), StatusCode::NOT_FOUND);
+
+    // source() walks down to the original error
+    let source = error.source().unwrap().source().unwrap();
+    assert_eq!(source.to_string(), "chunk abc");
+    assert!(source.source().is_none());
+}
+
+#[test]
+fn causes_are_kept() {
+    let io = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+    let error = Error::io("Error opening file", io).context("Could not read chunk");
+    assert_eq!(
+        error.to_string(),
+        "Could not read chunk: Error opening file: no such file"
+    );
+    assert!(error.is_not_found());
+    assert!(matches!(error.root(), Error::NotFound(message) if message == "Error opening file"));
+
+    // source() reaches the io error itself, not just its message
+    let cause = error.source().unwrap().source().unwrap();
+    let io = cause.downcast_ref::<std::io::Error>().unwrap();
+    assert_eq!(io.kind(), std::io::ErrorKind::NotFound);
+
+    let parsed = serde_json::from_str::<u8>("x").unwrap_err();
+    let error = Error::deserialize("Error parsing root", parsed);
+    assert!(matches!(error.root(), Error::Corrupted(_)));
+    assert!(error
+        .source()
+        .unwrap()
+        .downcast_ref::<serde_json::Error>()
+        .is_some());
+}
+
+#[test]
+fn status_codes() {
+    let cases = [
+        (Error::NotFound(String::new()), StatusCode::NOT_FOUND),
+        (Error::AlreadyExists(String::new()), StatusCode::CONFLICT),
+        (Error::Conflict(String::new()), StatusCode::CONFLICT),
+        (Error::Invalid(String::new()), StatusCode::BAD_REQUEST),
+        (Error::Forbidden(String::new()), StatusCode::FORBIDDEN),
+        (
+            Error::Unavailable(String::new()),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        (
+            Error::Corrupted(String::new()),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+    ];
+    for (error, status) in cases {
+        assert_eq!(status_code(&error), status, "{:?}", error);
+    }
+}
+
+#[test]
+fn many_errors() {
+    let not_found = || Error::NotFound("missing".to_string());
+    let unavailable = || Error::Unavailable("down".to_string());
+
+    // a single error isn't wrapped
+    assert!(matches!(Error::many(vec![not_found()]), Error::NotFound(_)));
+
+    let all_missing = Error::many(vec![not_found(), not_found()]);
+    assert_eq!(all_missing.to_string(), "missing, missing");
+    assert_eq!(status_code(&all_missing), StatusCode::NOT_FOUND);
+
+    let some_down = Error::many(vec![not_found(), unavailable()]);
+    assert!(!some_down.is_not_found());
+    assert_eq!(status_code(&some_down), StatusCode::SERVICE_UNAVAILABLE);
+
+    let mixed = Error::many(vec![not_found(), Error::Internal("oops".to_string())]);
+    assert_eq!(status_code(&mixed), StatusCode::INTERNAL_SERVER_ERROR);
+}
@@ -0,0 +1,60 @@
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde_yaml::from_str;
+use std::{env, fs};
+
+use crate::{
+    error::Error,
+    sources::{exec::Exec, source::Source},
+};
+
+#[tokio::test]
+async fn reference_script() {
+    let name = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect::<String>();
+    let folder = env::temp_dir().join(format!("chunkdrive-{}", name));
+    let script = concat!(env!("CARGO_MANIFEST_DIR"), "/scripts/exec_source.sh");
+    let source = from_str::<Exec>(&format!(
+        "command: sh\nargs: [{}, {}]",
+        script,
+        folder.display()
+    ))
+    .unwrap();
+
+    let data = (0..=255u8).collect::<Vec<u8>>().repeat(4);
+    let descriptor = source.create().await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), Vec::<u8>::new());
+    source.put(&descriptor, data.clone()).await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), data);
+
+    source.delete(&descriptor).await.unwrap();
+    assert!(matches!(
+        source.get(&descriptor).await.unwrap_err().root(),
+        Error::NotFound(_)
+    ));
+    assert!(source.put(&descriptor, data).await.is_err());
+
+    let _ = fs::remove_dir_all(&folder);
+}
+
+#[tokio::test]
+async fn failures_carry_stderr() {
+    let source =
+        from_str::<Exec>("command: sh\nargs: [-c, 'echo broken >&2; exit 1', sh]").unwrap();
+    let error = source.get(&b"x".to_vec()).await.unwrap_err();
+    assert!(error.to_string().contains("broken"), "{}", error);
+    assert!(error.is_transient());
+}
+
+#[tokio::test]
+async fn exit_code_wins_over_unread_input() {
+    // exits with EXIT_NOT_FOUND without reading the chunk, which breaks the pipe it is written to
+    let source = from_str::<Exec>("command: sh\nargs: [-c, 'exit 2', sh]").unwrap();
+    let error = source
+        .put(&b"x".to_vec(), vec![0; 4 * 1024 * 1024])
+        .await
+        .unwrap_err();
+    assert!(error.is_not_found(), "{}", error);
+}
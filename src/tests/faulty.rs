@@ -0,0 +1,92 @@
+use futures::StreamExt;
+use serde_yaml::from_str;
+use std::sync::Arc;
+
+use super::utils::make_faulty_config;
+use crate::{
+    blocks::block::{Block, BlockType},
+    error::Error,
+    global::{Global, GlobalTrait},
+};
+
+fn global(faults: &str) -> Arc<Global> {
+    Arc::new(from_str::<Global>(&make_faulty_config(30, faults)).unwrap())
+}
+
+async fn read(global: Arc<Global>, block: &BlockType, len: usize) -> Result<Vec<u8>, Error> {
+    let mut stream = block.get(global, 0..len as u64);
+    let mut data = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        data.extend(chunk?);
+    }
+    Ok(data)
+}
+
+#[tokio::test]
+async fn no_faults() {
+    let global = global("");
+    let data = (0..200).map(|x| x as u8).collect::<Vec<u8>>();
+    let block = BlockType::create(global.clone(), data.clone(), 0)
+        .await
+        .unwrap();
+    assert_eq!(read(global, &block, data.len()).await.unwrap(), data);
+}
+
+#[tokio::test]
+async fn failing_creates() {
+    let global = global("fail_rate: 1.0\n            operations: [create]");
+    let result = BlockType::create(global.clone(), vec![1, 2, 3], 0).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn failing_reads() {
+    let global = global("fail_rate: 1.0\n            operations: [get]");
+    let data = vec![7u8; 100];
+    let block = BlockType::create(global.clone(), data.clone(), 0)
+        .await
+        .unwrap();
+    assert!(read(global, &block, data.len()).await.is_err());
+}
+
+#[tokio::test]
+async fn corrupted_reads() {
+    let global = global("corrupt_rate: 1.0");
+    let data = vec![0u8; 25];
+    let block = BlockType::create(global.clone(), data.clone(), 0)
+        .await
+        .unwrap();
+    let got = read(global, &block, data.len()).await.unwrap();
+    assert_eq!(got.len(), data.len());
+    assert_ne!(got, data);
+}
+
+#[tokio::test]
+async fn lost_writes() {
+    let global = global("loss_rate: 1.0");
+    let bucket = global
+        .get_bucket(global.random_bucket().unwrap().as_str())
+        .unwrap();
+    let descriptor = bucket.create().await.unwrap();
+    bucket.put(&descriptor, vec![1, 2, 3]).await.unwrap();
+    assert_eq!(bucket.get(&descriptor).await.unwrap(), Vec::<u8>::new());
+}
+
+#[tokio::test]
+async fn deterministic_faults() {
+    // the same seed has to fail the same operations
+    let outcomes = || async {
+        let global = global("fail_rate: 0.5\n            operations: [create]");
+        let bucket = global
+            .get_bucket(global.random_bucket().unwrap().as_str())
+            .unwrap();
+        let mut outcomes = Vec::new();
+        for _ in 0..32 {
+            outcomes.push(bucket.create().await.is_ok());
+        }
+        outcomes
+    };
+    let first = outcomes().await;
+    assert!(first.contains(&true) && first.contains(&false));
+    assert_eq!(first, outcomes().await);
+}
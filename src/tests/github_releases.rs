@@ -0,0 +1,69 @@
+use serde_yaml::from_str;
+
+use crate::sources::github_releases::GithubReleases;
+
+fn source(extra: &str) -> GithubReleases {
+    from_str::<GithubReleases>(&format!(
+        r#"
+owner: someone
+repo: storage
+pat: token
+{}
+        "#,
+        extra
+    ))
+    .unwrap()
+}
+
+#[test]
+fn github_defaults() {
+    let source = source("");
+    assert_eq!(
+        source.repo_url("/releases/tags/abc"),
+        "https://api.github.com/repos/someone/storage/releases/tags/abc"
+    );
+    assert_eq!(
+        source.asset_url(1, 2),
+        "https://api.github.com/repos/someone/storage/releases/assets/2"
+    );
+    assert_eq!(
+        source.upload_asset_url(1),
+        "https://uploads.github.com/repos/someone/storage/releases/1/assets?name=d.bin"
+    );
+    assert_eq!(
+        source.tag_url("abc"),
+        "https://api.github.com/repos/someone/storage/git/refs/tags/abc"
+    );
+}
+
+#[test]
+fn github_enterprise() {
+    let source = source(
+        "api_url: https://git.example.com/api/v3/\nupload_url: https://git.example.com/api/uploads",
+    );
+    assert_eq!(
+        source.repo_url("/releases"),
+        "https://git.example.com/api/v3/repos/someone/storage/releases"
+    );
+    assert_eq!(
+        source.upload_asset_url(1),
+        "https://git.example.com/api/uploads/repos/someone/storage/releases/1/assets?name=d.bin"
+    );
+}
+
+#[test]
+fn forgejo() {
+    let source = source("flavor: forgejo\napi_url: http://127.0.0.1:3000/api/v1");
+    assert_eq!(
+        source.asset_url(1, 2),
+        "http://127.0.0.1:3000/api/v1/repos/someone/storage/releases/1/assets/2"
+    );
+    assert_eq!(
+        source.upload_asset_url(1),
+        "http://127.0.0.1:3000/api/v1/repos/someone/storage/releases/1/assets?name=d.bin"
+    );
+    assert_eq!(
+        source.tag_url("abc"),
+        "http://127.0.0.1:3000/api/v1/repos/someone/storage/tags/abc"
+    );
+}
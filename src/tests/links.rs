@@ -0,0 +1,155 @@
+use futures::StreamExt;
+use serde_yaml::from_str;
+use std::sync::Arc;
+
+use crate::{
+    error::Error,
+    global::{AsyncGlobal, Global},
+    inodes::{
+        directory::Directory,
+        file::File,
+        inode::{Inode, InodeType},
+        symlink::{resolve_path, Symlink},
+    },
+    stored::Stored,
+};
+
+fn global() -> Arc<AsyncGlobal> {
+    Arc::new(AsyncGlobal::new(
+        from_str::<Global>("buckets:\n    memory:\n        source:\n            type: memory")
+            .unwrap(),
+    ))
+}
+
+async fn read(global: Arc<AsyncGlobal>, stored: &Stored) -> Result<Vec<u8>, Error> {
+    match stored.get::<InodeType, AsyncGlobal>(global.clone()).await? {
+        InodeType::File(file) => {
+            let mut data = Vec::new();
+            let mut stream = file.get(global.clone());
+            while let Some(chunk) = stream.next().await {
+                data.extend(chunk?);
+            }
+            Ok(data)
+        }
+        _ => Err(Error::Invalid("not a file".to_string())),
+    }
+}
+
+async fn links(global: Arc<AsyncGlobal>, stored: &Stored) -> u32 {
+    let inode: InodeType = stored.get(global).await.unwrap();
+    inode.metadata().links
+}
+
+// a directory with the same file as `a` and `b`
+async fn linked(global: Arc<AsyncGlobal>) -> (Directory, Stored) {
+    let mut directory = Directory::new();
+    let file = File::create(global.clone(), vec![1, 2, 3]).await.unwrap();
+    let stored = directory
+        .add(global.clone(), &"a".to_string(), file.to_enum())
+        .await
+        .unwrap()
+        .clone();
+    directory
+        .link(global.clone(), &"b".to_string(), stored.clone())
+        .await
+        .unwrap();
+    (directory, stored)
+}
+
+#[tokio::test]
+async fn removing_a_link_keeps_the_data() {
+    let global = global();
+    let (mut directory, stored) = linked(global.clone()).await;
+    assert_eq!(
+        directory
+            .get(global.clone(), &"b".to_string())
+            .await
+            .unwrap(),
+        stored
+    );
+    assert_eq!(links(global.clone(), &stored).await, 2);
+
+    directory
+        .remove(global.clone(), &"a".to_string())
+        .await
+        .unwrap();
+    assert_eq!(read(global.clone(), &stored).await.unwrap(), vec![1, 2, 3]);
+    assert_eq!(links(global.clone(), &stored).await, 1);
+
+    directory
+        .remove(global.clone(), &"b".to_string())
+        .await
+        .unwrap();
+    assert!(read(global.clone(), &stored).await.is_err());
+}
+
+#[tokio::test]
+async fn overwrite_changes_every_link() {
+    let global = global();
+    let (mut directory, stored) = linked(global.clone()).await;
+    let file = File::create(global.clone(), vec![4, 5]).await.unwrap();
+    directory
+        .overwrite(global.clone(), &"a".to_string(), file)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        directory
+            .get(global.clone(), &"a".to_string())
+            .await
+            .unwrap(),
+        stored
+    );
+    assert_eq!(read(global.clone(), &stored).await.unwrap(), vec![4, 5]);
+    assert_eq!(links(global.clone(), &stored).await, 2);
+}
+
+#[tokio::test]
+async fn directories_cant_be_linked() {
+    let global = global();
+    let mut directory = Directory::new();
+    let stored = directory
+        .add(
+            global.clone(),
+            &"dir".to_string(),
+            Directory::new().to_enum(),
+        )
+        .await
+        .unwrap()
+        .clone();
+    assert!(directory
+        .link(global.clone(), &"other".to_string(), stored)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn symlinks_leave_their_target() {
+    let global = global();
+    let (mut directory, stored) = linked(global.clone()).await;
+    let symlink = Symlink::new("a".to_string());
+    let link = directory
+        .add(global.clone(), &"c".to_string(), symlink.to_enum())
+        .await
+        .unwrap()
+        .clone();
+    match link.get::<InodeType, AsyncGlobal>(global.clone()).await {
+        Ok(InodeType::Symlink(symlink)) => assert_eq!(symlink.target, "a"),
+        _ => panic!("not a symlink"),
+    }
+
+    directory
+        .remove(global.clone(), &"c".to_string())
+        .await
+        .unwrap();
+    assert_eq!(read(global.clone(), &stored).await.unwrap(), vec![1, 2, 3]);
+}
+
+#[test]
+fn symlink_paths() {
+    let from = vec!["a".to_string(), "b".to_string()];
+    assert_eq!(resolve_path(&from, "c"), vec!["a", "b", "c"]);
+    assert_eq!(resolve_path(&from, "../c/./d"), vec!["a", "c", "d"]);
+    assert_eq!(resolve_path(&from, "/c"), vec!["c"]);
+    assert_eq!(resolve_path(&from, "../../../c"), vec!["c"]);
+}
@@ -0,0 +1,108 @@
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde_yaml::from_str;
+use std::{env, fs, path::PathBuf};
+
+use crate::sources::{local::LocalSource, source::Source};
+
+fn temp_folder() -> PathBuf {
+    let name = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect::<String>();
+    let folder = env::temp_dir().join(format!("chunkdrive-{}", name));
+    fs::create_dir_all(&folder).unwrap();
+    folder
+}
+
+#[tokio::test]
+async fn sharded_roundtrip() {
+    let folder = temp_folder();
+    let source = from_str::<LocalSource>(&format!("folder: {}", folder.display())).unwrap();
+
+    let data = [1u8, 2, 3, 4, 5].repeat(5);
+    let descriptor = source.create().await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), Vec::<u8>::new());
+    source.put(&descriptor, data.clone()).await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), data);
+
+    // only the shard directory lives in the folder, and no temporary files are left over
+    let entries = fs::read_dir(&folder).unwrap().collect::<Vec<_>>();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].as_ref().unwrap().file_type().unwrap().is_dir());
+
+    source.delete(&descriptor).await.unwrap();
+    assert!(source.get(&descriptor).await.is_err());
+    assert!(source.put(&descriptor, data).await.is_err());
+
+    let _ = fs::remove_dir_all(&folder);
+}
+
+#[tokio::test]
+async fn migrates_flat_folder() {
+    let folder = temp_folder();
+    fs::write(folder.join("abc"), b"old chunk").unwrap();
+    fs::write(folder.join(".hidden"), b"not a chunk").unwrap();
+    fs::write(folder.join("notes.txt"), b"not a chunk").unwrap();
+    fs::write(
+        folder.join("abcd"),
+        b"created with a longer descriptor_length",
+    )
+    .unwrap();
+
+    let source = from_str::<LocalSource>(&format!(
+        "folder: {}\ndescriptor_length: 3",
+        folder.display()
+    ))
+    .unwrap();
+    assert_eq!(source.get(&b"abc".to_vec()).await.unwrap(), b"old chunk");
+    assert!(!folder.join("abc").exists());
+    // files that can't be descriptors are left where they are
+    assert!(folder.join(".hidden").exists());
+    assert!(folder.join("notes.txt").exists());
+    assert_eq!(
+        source.get(&b"abcd".to_vec()).await.unwrap(),
+        b"created with a longer descriptor_length"
+    );
+
+    let _ = fs::remove_dir_all(&folder);
+}
+
+#[tokio::test]
+async fn flat_layout() {
+    let folder = temp_folder();
+    let source =
+        from_str::<LocalSource>(&format!("folder: {}\nshard_levels: 0", folder.display())).unwrap();
+
+    let descriptor = source.create().await.unwrap();
+    source.put(&descriptor, vec![1, 2, 3]).await.unwrap();
+    let name = String::from_utf8(descriptor).unwrap();
+    assert_eq!(fs::read(folder.join(name)).unwrap(), vec![1, 2, 3]);
+
+    let _ = fs::remove_dir_all(&folder);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_puts() {
+    let folder = temp_folder();
+    let source = std::sync::Arc::new(
+        from_str::<LocalSource>(&format!("folder: {}\nshard_levels: 0", folder.display())).unwrap(),
+    );
+    let descriptor = source.create().await.unwrap();
+
+    let puts = (0..8u8).map(|i| {
+        let (source, descriptor) = (source.clone(), descriptor.clone());
+        tokio::spawn(async move { source.put(&descriptor, vec![i; 64 * 1024]).await })
+    });
+    for put in futures::future::join_all(puts).await {
+        put.unwrap().unwrap();
+    }
+
+    // one put wins whole, and every temporary file is gone
+    let data = source.get(&descriptor).await.unwrap();
+    assert!(data.iter().all(|byte| *byte == data[0]));
+    assert_eq!(data.len(), 64 * 1024);
+    assert_eq!(fs::read_dir(&folder).unwrap().count(), 1);
+
+    let _ = fs::remove_dir_all(&folder);
+}
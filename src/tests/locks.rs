@@ -0,0 +1,79 @@
+use serde_yaml::from_str;
+use std::{sync::Arc, time::Duration};
+
+use super::utils::{cleanup, temp_root};
+use crate::{
+    global::{AsyncGlobal, Global},
+    stored::Stored,
+};
+
+const SUFFIXES: &[&str] = &[".1", ".2", ".3", ".journal"];
+
+fn global(root_path: &str) -> Arc<AsyncGlobal> {
+    Arc::new(AsyncGlobal::new(
+        from_str::<Global>(&format!(
+            "root_path: {}\nbuckets:\n    memory:\n        source:\n            type: memory",
+            root_path
+        ))
+        .unwrap(),
+    ))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_root_mutations() {
+    let root_path = temp_root();
+    let global = global(&root_path);
+
+    let mut tasks = Vec::new();
+    for i in 0..16u8 {
+        let global = global.clone();
+        tasks.push(tokio::spawn(async move {
+            let _lock = global.lock_directory(None).await.unwrap();
+            let mut root = global.get_root().await.unwrap();
+            tokio::task::yield_now().await; // give the others a chance to interleave
+            root.put(
+                global.clone(),
+                &i.to_string(),
+                Stored::new("memory".to_string(), vec![i]),
+            )
+            .await
+            .unwrap();
+            global.save_root(&root).await.unwrap();
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+    assert_eq!(global.get_root().await.unwrap().count(), 16);
+
+    cleanup(&root_path, SUFFIXES);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn lock_is_shared_between_processes() {
+    // two globals with the same root_path don't share memory, like the shell and the services
+    let root_path = temp_root();
+    let (first, second) = (global(&root_path), global(&root_path));
+
+    let guard = first.lock_directory(None).await.unwrap();
+    let waiting = tokio::spawn(async move { second.lock_directory(None).await.map(|_| ()) });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!waiting.is_finished());
+
+    drop(guard);
+    tokio::time::timeout(Duration::from_secs(5), waiting)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    // other directories aren't affected
+    let _root = first.lock_directory(None).await.unwrap();
+    let other = Stored::new("memory".to_string(), vec![1]);
+    tokio::time::timeout(Duration::from_secs(5), first.lock_directory(Some(&other)))
+        .await
+        .unwrap()
+        .unwrap();
+
+    cleanup(&root_path, SUFFIXES);
+}
@@ -0,0 +1,29 @@
+use crate::inodes::mime::{content_type, detect};
+
+const PNG: &[u8] = &[
+    0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D,
+];
+
+#[test]
+fn contents_win_over_the_name() {
+    assert_eq!(detect("image.png", PNG), Some("image/png".to_string()));
+    assert_eq!(detect("image.txt", PNG), Some("image/png".to_string()));
+    assert_eq!(detect("image", PNG), Some("image/png".to_string()));
+}
+
+#[test]
+fn text_goes_by_extension() {
+    assert_eq!(
+        detect("style.css", b"body { margin: 0; }"),
+        Some("text/css".to_string())
+    );
+    assert_eq!(detect("notes", b"plain words"), None);
+}
+
+#[test]
+fn unknown_types_are_served_as_binary() {
+    assert_eq!(content_type(Some("image/png"), "image.bin"), "image/png");
+    // files uploaded before types were detected still get one from their name
+    assert_eq!(content_type(None, "document.pdf"), "application/pdf");
+    assert_eq!(content_type(None, "data"), "application/octet-stream");
+}
@@ -1,5 +1,29 @@
 pub mod block;
 pub mod bucket;
 pub mod direct_block;
+pub mod discord_bot;
+pub mod error;
+pub mod exec;
+pub mod faulty;
+pub mod github_releases;
+pub mod links;
+pub mod local;
+pub mod locks;
+pub mod mime;
+pub mod path;
+pub mod posix;
+pub mod range;
+pub mod rclone;
+pub mod root_file;
+pub mod sftp;
+pub mod shards;
+pub mod snapshots;
+pub mod sqlite;
 pub mod stored;
+pub mod superblock;
+pub mod telegram;
+pub mod trash;
 pub mod utils;
+pub mod versions;
+pub mod webdav;
+pub mod writes;
@@ -0,0 +1,125 @@
+use serde_yaml::from_str;
+use std::sync::Arc;
+
+use crate::{
+    global::{AsyncGlobal, Global},
+    inodes::{directory::Directory, file::File, inode::InodeType},
+    services::http::service::{entry_segment, split_segment},
+    stored::Stored,
+};
+
+fn global() -> Arc<AsyncGlobal> {
+    Arc::new(AsyncGlobal::new(
+        from_str::<Global>("buckets:\n    memory:\n        source:\n            type: memory")
+            .unwrap(),
+    ))
+}
+
+async fn directory(global: Arc<AsyncGlobal>, root: &Directory, names: &[&str]) -> Directory {
+    let names = names
+        .iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    let mut path = root.clone().open_path(global, &names).await.unwrap();
+    path.directory().clone()
+}
+
+// root/a/b, with every modified time at 0 so the commit's touches show
+async fn tree(global: Arc<AsyncGlobal>) -> Directory {
+    let mut b = Directory::new();
+    b.metadata.modified = 0;
+    let mut a = Directory::new();
+    a.add(global.clone(), &"b".to_string(), b.to_enum())
+        .await
+        .unwrap();
+    a.metadata.modified = 0;
+    let mut root = Directory::new();
+    root.add(global.clone(), &"a".to_string(), a.to_enum())
+        .await
+        .unwrap();
+    root.metadata.modified = 0;
+    root
+}
+
+#[tokio::test]
+async fn commit_rewrites_ancestors() {
+    let global = global();
+    let root = tree(global.clone()).await;
+    let names = vec!["a".to_string(), "b".to_string()];
+
+    let mut path = root
+        .clone()
+        .open_path(global.clone(), &names)
+        .await
+        .unwrap();
+    let old = path.stored();
+    let file = File::create(global.clone(), vec![1, 2, 3]).await.unwrap();
+    path.directory()
+        .add(global.clone(), &"file".to_string(), file.to_enum())
+        .await
+        .unwrap();
+    let commit = path.commit(global.clone()).await.unwrap();
+
+    // the old tree is untouched until the new top is saved
+    assert!(directory(global.clone(), &root, &["a", "b"]).await.count() == 0);
+    assert_eq!(commit.path.len(), 2);
+    assert!(commit.path.iter().all(|stored| !old.contains(stored)));
+    assert_eq!(
+        commit
+            .top
+            .get(global.clone(), &"a".to_string())
+            .await
+            .unwrap(),
+        commit.path[0]
+    );
+
+    let b = directory(global.clone(), &commit.top, &["a", "b"]).await;
+    assert_eq!(
+        b.list(global.clone()).await.unwrap(),
+        vec!["file".to_string()]
+    );
+    let a = directory(global.clone(), &commit.top, &["a"]).await;
+    assert!(a.metadata.modified > 0);
+    assert!(commit.top.metadata.modified > 0);
+
+    commit.cleanup(global.clone()).await;
+    for stored in old {
+        assert!(stored.get::<InodeType, _>(global.clone()).await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn resolve_by_stored() {
+    let global = global();
+    let root = tree(global.clone()).await;
+    let a = root.get(global.clone(), &"a".to_string()).await.unwrap();
+    assert_eq!(
+        root.name_of(global.clone(), &a).await.unwrap(),
+        Some("a".to_string())
+    );
+
+    let file = File::create(global.clone(), vec![1]).await.unwrap();
+    let mut root = root;
+    root.add(global.clone(), &"file".to_string(), file.to_enum())
+        .await
+        .unwrap();
+    let names = vec!["file".to_string()];
+    assert!(root.open_path(global, &names).await.is_err());
+}
+
+#[test]
+fn segments_keep_names() {
+    let stored = Stored::new("bucket".to_string(), b"abc".to_vec());
+    for name in ["plain", "a$b$c", "100%25", "with space"] {
+        // actix hands handlers the decoded path
+        let segment = entry_segment(&stored, name);
+        let decoded = urlencoding::decode(&segment).unwrap();
+        let (parsed, parsed_name) = split_segment(&decoded).unwrap();
+        assert_eq!(parsed, stored);
+        assert_eq!(parsed_name.as_deref(), Some(name));
+    }
+    // links from before segments had names
+    let (parsed, name) = split_segment(&stored.as_url()).unwrap();
+    assert_eq!(parsed, stored);
+    assert!(name.is_none());
+}
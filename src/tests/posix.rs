@@ -0,0 +1,134 @@
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use rmp_serde::Deserializer;
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+    inodes::metadata::{Metadata, Posix, Size, Timestamp},
+    posix,
+    stored::Stored,
+};
+
+fn temp_folder() -> PathBuf {
+    let name = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect::<String>();
+    let folder = env::temp_dir().join(format!("chunkdrive-{}", name));
+    fs::create_dir_all(&folder).unwrap();
+    folder
+}
+
+fn roundtrip(metadata: &Metadata) -> Metadata {
+    let data = Stored::serialize(metadata).unwrap();
+    Metadata::deserialize(&mut Deserializer::new(&data[..])).unwrap()
+}
+
+#[test]
+fn metadata_without_posix_reads_and_writes_as_before() {
+    // what metadata looked like before links and posix
+    #[derive(Serialize)]
+    struct OldMetadata {
+        c: u64,
+        m: u64,
+        s: Size,
+    }
+    let old = OldMetadata {
+        c: 1,
+        m: 2,
+        s: Size::Bytes(3),
+    };
+    let data = Stored::serialize(&old).unwrap();
+    let metadata = Metadata::deserialize(&mut Deserializer::new(&data[..])).unwrap();
+    assert_eq!(metadata.posix, None);
+    assert_eq!(metadata.links, 1);
+
+    // and it is written back without the new fields
+    assert_eq!(Stored::serialize(&metadata).unwrap(), data);
+}
+
+#[test]
+fn posix_roundtrips() {
+    let mut metadata = Metadata::new();
+    metadata.posix = Some(Posix {
+        mode: Some(0o4755),
+        uid: Some(1000),
+        gid: Some(100),
+        mtime: Some(Timestamp(1_700_000_000, 123_456_789)),
+        atime: Some(Timestamp(-1, 500_000_000)),
+        xattrs: [("user.tag".to_string(), vec![0, 255, 7])].into(),
+    });
+    assert_eq!(roundtrip(&metadata), metadata);
+
+    // parts that weren't captured stay unset
+    metadata.posix = Some(Posix {
+        mode: Some(0o644),
+        ..Default::default()
+    });
+    assert_eq!(roundtrip(&metadata), metadata);
+}
+
+#[test]
+fn timestamps_keep_nanoseconds_around_the_epoch() {
+    for time in [
+        UNIX_EPOCH + Duration::new(1_700_000_000, 999_999_999),
+        UNIX_EPOCH - Duration::new(0, 1),
+        UNIX_EPOCH - Duration::new(5, 0),
+    ] {
+        assert_eq!(Timestamp::from_system_time(time).to_system_time(), time);
+    }
+    assert_eq!(
+        Timestamp::from_system_time(UNIX_EPOCH - Duration::new(0, 1)),
+        Timestamp(-1, 999_999_999)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn captured_metadata_applies_to_another_file() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let folder = temp_folder();
+    let source = folder.join("source");
+    fs::write(&source, b"data").unwrap();
+    fs::set_permissions(&source, fs::Permissions::from_mode(0o640)).unwrap();
+    let mtime = UNIX_EPOCH + Duration::new(1_600_000_000, 123_456_789);
+    fs::File::options()
+        .write(true)
+        .open(&source)
+        .unwrap()
+        .set_times(fs::FileTimes::new().set_modified(mtime))
+        .unwrap();
+
+    let captured = posix::capture(&source).unwrap();
+    assert_eq!(captured.mode, Some(0o640));
+    assert_eq!(captured.mtime, Some(Timestamp(1_600_000_000, 123_456_789)));
+
+    let target = folder.join("target");
+    fs::write(&target, b"data").unwrap();
+    assert!(posix::apply(&target, &captured, false).is_empty());
+    let applied = posix::capture(&target).unwrap();
+    assert_eq!(applied.mode, captured.mode);
+    assert_eq!(applied.uid, captured.uid);
+    assert_eq!(applied.gid, captured.gid);
+    assert_eq!(applied.mtime, captured.mtime);
+    assert_eq!(applied.atime, captured.atime);
+
+    // a symlink gets its own times, not its target's
+    let link = folder.join("link");
+    std::os::unix::fs::symlink("target", &link).unwrap();
+    let link_posix = Posix {
+        mtime: Some(Timestamp(1_000_000_000, 1)),
+        ..Default::default()
+    };
+    assert!(posix::apply(&link, &link_posix, true).is_empty());
+    assert_eq!(posix::capture(&link).unwrap().mtime, link_posix.mtime);
+    assert_eq!(posix::capture(&target).unwrap().mtime, captured.mtime);
+
+    fs::remove_dir_all(&folder).unwrap();
+}
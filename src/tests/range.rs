@@ -0,0 +1,73 @@
+use futures::StreamExt;
+use rmp_serde::Deserializer;
+use serde::{Deserialize, Serialize};
+use serde_yaml::from_str;
+use std::{ops::Range, sync::Arc};
+
+use super::utils::make_temp_config;
+use crate::{
+    blocks::block::{Block, BlockType},
+    global::Global,
+    stored::Stored,
+};
+
+// A direct block the way it was written before, with its range as a Range<usize>
+#[derive(Serialize)]
+enum OldBlock {
+    #[serde(rename = "d")]
+    Direct(OldDirectBlock),
+}
+
+#[derive(Serialize)]
+struct OldDirectBlock {
+    #[serde(flatten)]
+    stored: Stored, // the bucket and descriptor have the same names
+    r: Range<usize>,
+}
+
+fn deserialize(data: &[u8]) -> BlockType {
+    BlockType::deserialize(&mut Deserializer::new(data)).unwrap()
+}
+
+async fn read(global: Arc<Global>, block: &BlockType, range: Range<u64>) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut stream = block.get(global, range);
+    while let Some(chunk) = stream.next().await {
+        data.extend(chunk.unwrap());
+    }
+    data
+}
+
+#[tokio::test]
+async fn old_ranges_are_still_read() {
+    let global = Arc::new(from_str::<Global>(&make_temp_config(false, 100)).unwrap());
+    let stored = Stored::create_bytes(global.clone(), b"hello world".to_vec())
+        .await
+        .unwrap();
+    let old = Stored::serialize(&OldBlock::Direct(OldDirectBlock {
+        stored,
+        r: 100..111,
+    }))
+    .unwrap();
+
+    let block = deserialize(&old);
+    assert_eq!(block.range(global.clone()).await.unwrap(), 100..111);
+    assert_eq!(read(global.clone(), &block, 104..111).await, b"o world");
+}
+
+#[tokio::test]
+async fn ranges_are_written_as_u64_pairs() {
+    let global = Arc::new(from_str::<Global>(&make_temp_config(false, 100)).unwrap());
+    let data = vec![7; 250];
+    let block = BlockType::create(global.clone(), data.clone(), 1 << 40)
+        .await
+        .unwrap();
+    let range = (1 << 40)..(1 << 40) + 250;
+    assert_eq!(block.range(global.clone()).await.unwrap(), range);
+
+    let serialized = Stored::serialize(&block).unwrap();
+    assert!(!serialized.windows(5).any(|window| window == b"start"));
+    let block = deserialize(&serialized);
+    assert_eq!(block.range(global.clone()).await.unwrap(), range);
+    assert_eq!(read(global, &block, range).await, data);
+}
@@ -0,0 +1,45 @@
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde_yaml::from_str;
+use std::{env, fs, process::Command};
+
+use crate::sources::{
+    rclone::{cli_path, object_path, Rclone},
+    source::Source,
+};
+
+#[test]
+fn paths() {
+    assert_eq!(object_path("", "abc"), "abc");
+    assert_eq!(object_path("/chunks/", "abc"), "chunks/abc");
+    assert_eq!(cli_path("gdrive:", "chunks", "abc"), "gdrive:chunks/abc");
+    assert_eq!(cli_path("s3:bucket", "", "abc"), "s3:bucket/abc");
+    assert_eq!(cli_path("/tmp/x", "", "abc"), "/tmp/x/abc");
+}
+
+// Uses rclone's local backend, skipped when rclone isn't installed
+#[tokio::test]
+async fn local_backend() {
+    if Command::new("rclone").arg("version").output().is_err() {
+        eprintln!("rclone not found, skipping");
+        return;
+    }
+    let name = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect::<String>();
+    let folder = env::temp_dir().join(format!("chunkdrive-{}", name));
+    let source =
+        from_str::<Rclone>(&format!("remote: {}\nprefix: chunks", folder.display())).unwrap();
+
+    let data = [1u8, 2, 3, 4, 5].repeat(5);
+    let descriptor = source.create().await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), Vec::<u8>::new());
+    source.put(&descriptor, data.clone()).await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), data);
+    source.delete(&descriptor).await.unwrap();
+    assert!(source.get(&descriptor).await.is_err());
+    assert!(source.put(&descriptor, data).await.is_err());
+
+    let _ = fs::remove_dir_all(&folder);
+}
@@ -0,0 +1,177 @@
+use serde_yaml::from_str;
+use std::{fs, io::Write, path::Path, sync::Arc};
+
+use super::utils::{cleanup, temp_root};
+use crate::{
+    global::{AsyncGlobal, Global},
+    inodes::directory::Directory,
+    root_file,
+    stored::Stored,
+};
+
+const SUFFIXES: &[&str] = &[".1", ".2", ".3", ".journal"];
+
+fn with_children(names: &[&str]) -> Directory {
+    Directory::with_children(
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                (
+                    name.to_string(),
+                    Stored::new("b".to_string(), vec![i as u8]),
+                )
+            })
+            .collect(),
+    )
+}
+
+fn sorted(root: &Directory) -> Vec<String> {
+    let mut names = root.children().keys().cloned().collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+#[test]
+fn generations() {
+    let root_path = temp_root();
+    assert!(root_file::load(&root_path, 2).is_none());
+    for names in [&["a"][..], &["a", "b"], &["a", "b", "c"], &["b", "c"]] {
+        root_file::save(&root_path, 2, &with_children(names)).unwrap();
+    }
+
+    let (sequence, root) = root_file::load(&root_path, 2).unwrap();
+    assert_eq!(sequence, 4);
+    assert_eq!(sorted(&root), vec!["b", "c"]);
+    assert!(Path::new(&format!("{}.1", root_path)).exists());
+    assert!(Path::new(&format!("{}.2", root_path)).exists());
+    assert!(!Path::new(&format!("{}.3", root_path)).exists());
+
+    cleanup(&root_path, SUFFIXES);
+}
+
+#[test]
+fn recovers_from_corrupt_root() {
+    let root_path = temp_root();
+    root_file::save(&root_path, 2, &with_children(&["a"])).unwrap();
+    root_file::save(&root_path, 2, &with_children(&["a", "b"])).unwrap();
+    fs::write(&root_path, b"garbage").unwrap();
+
+    // the older generation plus the journal give back the newest root
+    let (sequence, root) = root_file::load(&root_path, 2).unwrap();
+    assert_eq!(sequence, 2);
+    assert_eq!(sorted(&root), vec!["a", "b"]);
+    // and the broken file is left alone
+    assert_eq!(fs::read(&root_path).unwrap(), b"garbage");
+
+    root_file::save(&root_path, 2, &with_children(&["a", "b", "c"])).unwrap();
+    let (_, root) = root_file::load(&root_path, 2).unwrap();
+    assert_eq!(sorted(&root), vec!["a", "b", "c"]);
+
+    cleanup(&root_path, SUFFIXES);
+}
+
+#[test]
+fn recovers_unwritten_root() {
+    let root_path = temp_root();
+    root_file::save(&root_path, 2, &with_children(&["a"])).unwrap();
+    let saved = fs::read(&root_path).unwrap();
+    root_file::save(&root_path, 2, &with_children(&["a", "b"])).unwrap();
+    // as if we crashed after journaling the second save, but before replacing the root
+    fs::write(&root_path, saved).unwrap();
+
+    let (_, root) = root_file::load(&root_path, 2).unwrap();
+    assert_eq!(sorted(&root), vec!["a", "b"]);
+
+    cleanup(&root_path, SUFFIXES);
+}
+
+#[test]
+fn torn_journal() {
+    let root_path = temp_root();
+    root_file::save(&root_path, 2, &with_children(&["a"])).unwrap();
+    fs::OpenOptions::new()
+        .append(true)
+        .open(format!("{}.journal", root_path))
+        .unwrap()
+        .write_all(&[0x82, 0xa1])
+        .unwrap();
+    assert_eq!(
+        sorted(&root_file::load(&root_path, 2).unwrap().1),
+        vec!["a"]
+    );
+
+    root_file::save(&root_path, 2, &with_children(&["a", "b"])).unwrap();
+    fs::write(&root_path, b"garbage").unwrap();
+    assert_eq!(
+        sorted(&root_file::load(&root_path, 2).unwrap().1),
+        vec!["a", "b"]
+    );
+
+    cleanup(&root_path, SUFFIXES);
+}
+
+#[test]
+fn legacy_root() {
+    let root_path = temp_root();
+    fs::write(
+        &root_path,
+        Stored::serialize(&with_children(&["old"])).unwrap(),
+    )
+    .unwrap();
+    let (sequence, root) = root_file::load(&root_path, 2).unwrap();
+    assert_eq!(sequence, 0);
+    assert_eq!(sorted(&root), vec!["old"]);
+
+    cleanup(&root_path, SUFFIXES);
+}
+
+#[tokio::test]
+async fn merge_concurrent_changes() {
+    let global = Arc::new(
+        from_str::<Global>("buckets:\n    memory:\n        source:\n            type: memory\n")
+            .unwrap(),
+    );
+    let base = with_children(&["a", "b", "c"]);
+    let mut ours = base.clone();
+    ours.children_mut().remove("a");
+    ours.children_mut()
+        .insert("d".to_string(), Stored::new("b".to_string(), vec![7]));
+    let mut theirs = base.clone();
+    theirs.children_mut().remove("b");
+    theirs
+        .children_mut()
+        .insert("e".to_string(), Stored::new("b".to_string(), vec![8]));
+
+    let merged = root_file::merge(global.clone(), &base, &ours, &theirs)
+        .await
+        .unwrap();
+    assert_eq!(sorted(&merged), vec!["c", "d", "e"]);
+
+    // both sides replaced the same entry, ours wins
+    let mut ours = base.clone();
+    ours.children_mut()
+        .insert("c".to_string(), Stored::new("b".to_string(), vec![9]));
+    let merged = root_file::merge(global, &base, &ours, &merged)
+        .await
+        .unwrap();
+    assert_eq!(
+        merged.children().get("c"),
+        Some(&Stored::new("b".to_string(), vec![9]))
+    );
+    assert_eq!(sorted(&merged), vec!["c", "d", "e"]);
+}
+
+#[tokio::test]
+async fn failed_saves_are_errors() {
+    // the folder of the root doesn't exist, so the root can't be written
+    let root_path = format!("{}/root.dat", temp_root());
+    let global = AsyncGlobal::new(
+        from_str::<Global>(&format!(
+            "root_path: {}\nbuckets:\n    memory:\n        source:\n            type: memory",
+            root_path
+        ))
+        .unwrap(),
+    );
+    assert!(global.save_root(&with_children(&["a"])).await.is_err());
+}
@@ -0,0 +1,68 @@
+use futures::future::join_all;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde_yaml::from_str;
+use std::env;
+
+use crate::sources::{sftp::Sftp, source::Source};
+
+// Needs a server, CHUNKDRIVE_SFTP holds the source's config without the folder, e.g.
+// "host: localhost\nusername: me\nprivate_key: ~/.ssh/id_ed25519". Skipped when it isn't set.
+fn source() -> Option<Sftp> {
+    let config = match env::var("CHUNKDRIVE_SFTP") {
+        Ok(config) => config,
+        Err(_) => {
+            eprintln!("CHUNKDRIVE_SFTP not set, skipping");
+            return None;
+        }
+    };
+    let name = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect::<String>();
+    let folder = env::temp_dir().join(format!("chunkdrive-{}", name));
+    Some(from_str::<Sftp>(&format!("{}\nfolder: {}", config, folder.display())).unwrap())
+}
+
+#[tokio::test]
+async fn roundtrip() {
+    let source = match source() {
+        Some(source) => source,
+        None => return,
+    };
+
+    let data = [1u8, 2, 3, 4, 5].repeat(5);
+    let descriptor = source.create().await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), Vec::<u8>::new());
+    source.put(&descriptor, data.clone()).await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), data);
+
+    // replacing a chunk that exists, OpenSSH only manages it by removing the chunk first
+    source.put(&descriptor, vec![9; 3]).await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), vec![9; 3]);
+
+    source.delete(&descriptor).await.unwrap();
+    assert!(source.get(&descriptor).await.is_err());
+    assert!(source.put(&descriptor, data).await.is_err());
+}
+
+#[tokio::test]
+async fn concurrent_puts_keep_one_whole_chunk() {
+    let source = match source() {
+        Some(source) => source,
+        None => return,
+    };
+
+    let descriptor = source.create().await.unwrap();
+    let versions = (0..8u8).map(|i| vec![i; 4096]).collect::<Vec<_>>();
+    let puts = versions
+        .iter()
+        .map(|data| source.put(&descriptor, data.clone()));
+    // puts racing between removing and renaming may fail, but never leave a mix or no chunk behind
+    let results = join_all(puts).await;
+    assert!(results.iter().any(Result::is_ok));
+    let data = source.get(&descriptor).await.unwrap();
+    assert!(versions.contains(&data));
+
+    source.delete(&descriptor).await.unwrap();
+}
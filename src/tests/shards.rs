@@ -0,0 +1,276 @@
+use rmp_serde::Deserializer;
+use serde::Deserialize;
+use serde_yaml::from_str;
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{
+    global::{AsyncGlobal, Global},
+    inodes::{
+        directory::Directory,
+        inode::{Inode, InodeType},
+        shards::{hash, Node},
+    },
+    root_file,
+    stored::Stored,
+};
+
+// small limits, so a few entries spill and split
+fn global() -> Arc<AsyncGlobal> {
+    Arc::new(AsyncGlobal::new(
+        from_str::<Global>(
+            "buckets:\n    memory:\n        source:\n            type: memory\ndirectories:\n    inline: 4\n    shard: 4\n",
+        )
+        .unwrap(),
+    ))
+}
+
+fn name(i: usize) -> String {
+    format!("entry {}", i)
+}
+
+// entries only ever point at chunks that aren't loaded, any Stored will do
+async fn with_entries(global: Arc<AsyncGlobal>, count: usize) -> Directory {
+    let mut directory = Directory::new();
+    for i in 0..count {
+        directory
+            .put(
+                global.clone(),
+                &name(i),
+                Stored::new("memory".to_string(), vec![i as u8]),
+            )
+            .await
+            .unwrap();
+    }
+    directory.flush(global).await.unwrap();
+    directory
+}
+
+fn roundtrip(directory: &Directory) -> Directory {
+    let data = Stored::serialize(directory).unwrap();
+    Directory::deserialize(&mut Deserializer::new(&data[..])).unwrap()
+}
+
+#[tokio::test]
+async fn large_directories_spill_into_shards_and_come_back() {
+    let global = global();
+    let directory = with_entries(global.clone(), 100).await;
+    assert!(directory.shards().is_some());
+    assert!(directory.children().is_empty());
+    assert_eq!(directory.count(), 100);
+
+    // only the top level is stored with the directory
+    let mut directory = roundtrip(&directory);
+    for i in 0..100 {
+        assert_eq!(
+            directory.get(global.clone(), &name(i)).await.unwrap(),
+            Stored::new("memory".to_string(), vec![i as u8])
+        );
+    }
+    assert!(directory.get(global.clone(), &name(100)).await.is_err());
+    assert!(directory
+        .put(
+            global.clone(),
+            &name(0),
+            Stored::new("memory".to_string(), vec![])
+        )
+        .await
+        .is_err());
+
+    for i in 2..100 {
+        directory.unlink(global.clone(), &name(i)).await.unwrap();
+    }
+    directory.flush(global.clone()).await.unwrap();
+    assert!(directory.shards().is_none());
+    let mut names = directory.list(global.clone()).await.unwrap();
+    names.sort();
+    assert_eq!(names, vec![name(0), name(1)]);
+    assert!(!directory.take_replaced().is_empty());
+}
+
+#[tokio::test]
+async fn unflushed_changes_cant_be_stored() {
+    let global = global();
+    let mut directory = with_entries(global.clone(), 20).await;
+    directory
+        .put(
+            global.clone(),
+            &name(20),
+            Stored::new("memory".to_string(), vec![]),
+        )
+        .await
+        .unwrap();
+    assert!(Stored::serialize(&directory).is_err());
+
+    directory.flush(global.clone()).await.unwrap();
+    assert_eq!(roundtrip(&directory).count(), 21);
+}
+
+#[tokio::test]
+async fn a_change_only_replaces_the_nodes_leading_to_it() {
+    let global = global();
+    let mut directory = with_entries(global.clone(), 200).await;
+    let (_, nodes) = directory_nodes(global.clone(), &directory).await;
+
+    directory.unlink(global.clone(), &name(7)).await.unwrap();
+    directory.flush(global.clone()).await.unwrap();
+    let replaced = directory.take_replaced();
+    assert!(!replaced.is_empty());
+    assert!(replaced.len() * 4 < nodes.len());
+    assert!(replaced.iter().all(|stored| nodes.contains(stored)));
+
+    // the rest of the nodes are still used
+    let (_, after) = directory_nodes(global.clone(), &directory).await;
+    assert!(nodes
+        .iter()
+        .filter(|stored| !replaced.contains(stored))
+        .all(|stored| after.contains(stored)));
+}
+
+#[tokio::test]
+async fn pages_cover_every_entry_once() {
+    let global = global();
+    let directory = with_entries(global.clone(), 150).await;
+
+    let mut seen = Vec::new();
+    let mut after: Option<String> = None;
+    loop {
+        let page = directory
+            .page(global.clone(), after.as_deref(), 7)
+            .await
+            .unwrap();
+        if page.is_empty() {
+            break;
+        }
+        assert!(page.len() <= 7);
+        after = page.last().map(|(name, _)| name.clone());
+        seen.extend(page.into_iter().map(|(name, _)| name));
+    }
+    assert_eq!(seen.len(), 150);
+    assert_eq!(seen.iter().collect::<HashSet<_>>().len(), 150);
+
+    // the order is the same as in one big page
+    let all = directory.page(global.clone(), None, 1000).await.unwrap();
+    assert_eq!(
+        all.into_iter().map(|(name, _)| name).collect::<Vec<_>>(),
+        seen
+    );
+}
+
+#[tokio::test]
+async fn deleting_a_sharded_directory_frees_its_nodes() {
+    let global = global();
+    let mut directory = Directory::new();
+    for i in 0..30 {
+        directory
+            .add(global.clone(), &name(i), Directory::new().to_enum())
+            .await
+            .unwrap();
+    }
+    directory.flush(global.clone()).await.unwrap();
+    let (entries, nodes) = directory_nodes(global.clone(), &directory).await;
+    assert_eq!(entries.len(), 30);
+    assert!(!nodes.is_empty());
+
+    // shard nodes count as chunks of the directory, so snapshots pin them with it
+    let chunks = directory.chunks(global.clone()).await.unwrap();
+    assert!(nodes.iter().all(|stored| chunks.contains(stored)));
+
+    directory.delete(global.clone()).await.unwrap();
+    for stored in &entries {
+        assert!(stored.get::<InodeType, _>(global.clone()).await.is_err());
+    }
+    for stored in &nodes {
+        assert!(stored.get::<Node, _>(global.clone()).await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn commits_write_sharded_directories_along_the_path() {
+    let global = global();
+    let mut root = Directory::new();
+    root.add(global.clone(), &"a".to_string(), Directory::new().to_enum())
+        .await
+        .unwrap();
+    let names = vec!["a".to_string()];
+    let mut path = root.open_path(global.clone(), &names).await.unwrap();
+    for i in 0..50 {
+        path.directory()
+            .put(
+                global.clone(),
+                &name(i),
+                Stored::new("memory".to_string(), vec![i as u8]),
+            )
+            .await
+            .unwrap();
+    }
+    let commit = path.commit(global.clone()).await.unwrap();
+    commit.cleanup(global.clone()).await;
+
+    let a = match commit.path[0]
+        .get::<InodeType, _>(global.clone())
+        .await
+        .unwrap()
+    {
+        InodeType::Directory(directory) => directory,
+        _ => panic!("not a directory"),
+    };
+    assert!(a.shards().is_some());
+    assert_eq!(a.list(global.clone()).await.unwrap().len(), 50);
+}
+
+#[tokio::test]
+async fn concurrent_changes_to_one_slot_are_merged() {
+    let global = global();
+    let base = with_entries(global.clone(), 100).await;
+    // names past the base entries that land in the same top slot as entry 0
+    let slot = |name: &String| hash(name) >> 58;
+    let same = (100..)
+        .map(name)
+        .filter(|other| slot(other) == slot(&name(0)))
+        .take(2)
+        .collect::<Vec<_>>();
+
+    let mut ours = base.clone();
+    ours.put(
+        global.clone(),
+        &same[0],
+        Stored::new("memory".to_string(), vec![1]),
+    )
+    .await
+    .unwrap();
+    ours.unlink(global.clone(), &name(0)).await.unwrap();
+    ours.flush(global.clone()).await.unwrap();
+    let mut theirs = base.clone();
+    theirs
+        .put(
+            global.clone(),
+            &same[1],
+            Stored::new("memory".to_string(), vec![2]),
+        )
+        .await
+        .unwrap();
+    theirs.flush(global.clone()).await.unwrap();
+
+    let merged = root_file::merge(global.clone(), &base, &ours, &theirs)
+        .await
+        .unwrap();
+    // stored as it is, without flushing
+    let merged = roundtrip(&merged);
+    assert_eq!(merged.count(), 101);
+    let names = merged.list(global.clone()).await.unwrap();
+    assert_eq!(names.len(), 101);
+    assert!(names.contains(&same[0]) && names.contains(&same[1]));
+    assert!(!names.contains(&name(0)));
+}
+
+// The entries and the stored shard nodes of a flushed directory
+async fn directory_nodes(
+    global: Arc<AsyncGlobal>,
+    directory: &Directory,
+) -> (Vec<Stored>, Vec<Stored>) {
+    let (entries, nodes) = directory.shards().unwrap().walk(global).await.unwrap();
+    (
+        entries.into_iter().map(|(_, stored)| stored).collect(),
+        nodes,
+    )
+}
@@ -0,0 +1,151 @@
+use futures::StreamExt;
+use serde_yaml::from_str;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+use super::utils::{cleanup, temp_root};
+use crate::{
+    error::Error,
+    global::{BlockingGlobal, Global},
+    inodes::{
+        directory::Directory,
+        file::File,
+        inode::{Inode, InodeType},
+    },
+    stored::Stored,
+};
+
+const SUFFIXES: &[&str] = &[".1", ".2", ".3", ".journal", ".snapshots"];
+
+fn global(root_path: &str) -> Arc<BlockingGlobal> {
+    Arc::new(BlockingGlobal::new(
+        from_str::<Global>(&format!(
+            "root_path: {}\nbuckets:\n    memory:\n        source:\n            type: memory",
+            root_path
+        ))
+        .unwrap(),
+    ))
+}
+
+fn read(global: &Arc<BlockingGlobal>, directory: &Directory, name: &str) -> Result<Vec<u8>, Error> {
+    let rt = Runtime::new().unwrap();
+    rt.block_on(async {
+        let stored = directory.get(global.clone(), &name.to_string()).await?;
+        let file = match stored.get::<InodeType, _>(global.clone()).await? {
+            InodeType::File(file) => file,
+            _ => return Err(Error::Invalid("not a file".to_string())),
+        };
+        let mut data = Vec::new();
+        let mut stream = file.get(global.clone());
+        while let Some(chunk) = stream.next().await {
+            data.extend(chunk?);
+        }
+        Ok(data)
+    })
+}
+
+// a root with one file, returns where the file is stored
+fn setup(global: &Arc<BlockingGlobal>) -> Stored {
+    let rt = Runtime::new().unwrap();
+    let mut root = Directory::new();
+    let file = rt
+        .block_on(File::create(global.clone(), vec![1, 2, 3]))
+        .unwrap();
+    let stored = rt
+        .block_on(root.add(global.clone(), &"a".to_string(), file.to_enum()))
+        .unwrap()
+        .clone();
+    global.save_root(&root).unwrap();
+    stored
+}
+
+fn remove(global: &Arc<BlockingGlobal>, name: &str) {
+    let rt = Runtime::new().unwrap();
+    let mut root = global.get_root().unwrap();
+    rt.block_on(root.remove(global.clone(), &name.to_string()))
+        .unwrap();
+    global.save_root(&root).unwrap();
+}
+
+#[test]
+fn snapshot_keeps_deleted_data() {
+    let root_path = temp_root();
+    let global = global(&root_path);
+    let stored = setup(&global);
+
+    global.create_snapshot("before").unwrap();
+    assert!(global.create_snapshot("before").is_err());
+    remove(&global, "a");
+    assert_eq!(global.get_root().unwrap().count(), 0);
+
+    let snapshot = global.snapshot_root("before").unwrap();
+    assert_eq!(read(&global, &snapshot, "a").unwrap(), vec![1, 2, 3]);
+
+    // the last snapshot using the file frees it
+    global.delete_snapshot("before").unwrap();
+    assert!(global.snapshots().unwrap().is_empty());
+    let rt = Runtime::new().unwrap();
+    assert!(rt
+        .block_on(stored.get::<InodeType, _>(global.clone()))
+        .is_err());
+    cleanup(&root_path, SUFFIXES);
+}
+
+#[test]
+fn restore_and_delete_keep_live_data() {
+    let root_path = temp_root();
+    let global = global(&root_path);
+    setup(&global);
+
+    global.create_snapshot("one").unwrap();
+    global.create_snapshot("two").unwrap();
+    remove(&global, "a");
+    global.restore_snapshot("one").unwrap();
+    assert_eq!(
+        read(&global, &global.get_root().unwrap(), "a").unwrap(),
+        vec![1, 2, 3]
+    );
+
+    // both snapshots are gone, but the restored tree still uses the file
+    global.delete_snapshot("one").unwrap();
+    global.delete_snapshot("two").unwrap();
+    assert_eq!(
+        read(&global, &global.get_root().unwrap(), "a").unwrap(),
+        vec![1, 2, 3]
+    );
+
+    // and with nothing pinning it anymore, deleting it frees it
+    let rt = Runtime::new().unwrap();
+    let stored = rt
+        .block_on(
+            global
+                .get_root()
+                .unwrap()
+                .get(global.clone(), &"a".to_string()),
+        )
+        .unwrap();
+    remove(&global, "a");
+    let rt = Runtime::new().unwrap();
+    assert!(rt
+        .block_on(stored.get::<InodeType, _>(global.clone()))
+        .is_err());
+    cleanup(&root_path, SUFFIXES);
+}
+
+#[test]
+fn snapshotted_files_cant_be_linked() {
+    let root_path = temp_root();
+    let global = global(&root_path);
+    let stored = setup(&global);
+
+    global.create_snapshot("before").unwrap();
+    let rt = Runtime::new().unwrap();
+    let mut root = global.get_root().unwrap();
+    let linked = rt.block_on(root.link(global.clone(), &"b".to_string(), stored.clone()));
+    assert!(matches!(linked, Err(Error::Conflict(_))));
+    let inode = rt
+        .block_on(stored.get::<InodeType, _>(global.clone()))
+        .unwrap();
+    assert_eq!(inode.metadata().links, 1);
+    cleanup(&root_path, SUFFIXES);
+}
@@ -0,0 +1,37 @@
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde_yaml::from_str;
+use std::env;
+
+use crate::sources::{source::Source, sqlite::Sqlite};
+
+#[tokio::test]
+async fn roundtrip() {
+    let name = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect::<String>();
+    let path = env::temp_dir().join(format!("chunkdrive-{}.db", name));
+    let source = from_str::<Sqlite>(&format!("path: {}", path.display())).unwrap();
+
+    let data = [1u8, 2, 3, 4, 5].repeat(5);
+    let descriptor = source.create().await.unwrap();
+    let other = source.create().await.unwrap();
+    assert_ne!(descriptor, other);
+    // descriptors end up in urls, where only some bytes come back unchanged
+    assert!(descriptor.iter().all(u8::is_ascii_alphanumeric));
+    assert_eq!(source.get(&descriptor).await.unwrap(), Vec::<u8>::new());
+
+    source.put(&descriptor, data.clone()).await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), data);
+    assert_eq!(source.get(&other).await.unwrap(), Vec::<u8>::new());
+
+    source.delete(&descriptor).await.unwrap();
+    assert!(source.get(&descriptor).await.is_err());
+    assert!(source.put(&descriptor, data).await.is_err());
+
+    drop(source);
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(path.with_extension("db-wal"));
+    let _ = std::fs::remove_file(path.with_extension("db-shm"));
+}
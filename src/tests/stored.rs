@@ -12,6 +12,8 @@ async fn stored_with_url() {
         .await
         .unwrap();
     let url = stored.as_url();
+    // actix decodes paths before handlers see them, so nothing in a url may need escaping
+    assert!(url.chars().all(|c| c.is_ascii_alphanumeric() || c == '$'));
     let split = url.split('$').collect::<Vec<&str>>();
     assert!(split.len() == 2);
     let (bucket, descriptor) = (split[0], split[1]);
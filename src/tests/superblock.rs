@@ -0,0 +1,214 @@
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde_yaml::from_str;
+use std::{env, fs, path::PathBuf, sync::Arc};
+
+use crate::{
+    error::Error,
+    global::{AsyncGlobal, Global},
+    inodes::directory::Directory,
+    superblock::{Superblock, SuperblockConfig},
+};
+
+fn temp_path(extension: &str) -> PathBuf {
+    let name = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect::<String>();
+    env::temp_dir().join(format!("chunkdrive-{}.{}", name, extension))
+}
+
+#[tokio::test]
+async fn root_roundtrip() {
+    let root_path = temp_path("dat");
+    let bootstrap_path = temp_path("url");
+    let global = Arc::new(AsyncGlobal::new(
+        from_str::<Global>(&format!(
+            r#"
+root_path: {}
+superblock:
+    bucket: memory
+    bootstrap_path: {}
+buckets:
+    memory:
+        source:
+            type: memory
+        "#,
+            root_path.display(),
+            bootstrap_path.display()
+        ))
+        .unwrap(),
+    ));
+
+    let mut root = global.get_root().await.unwrap();
+    assert_eq!(root.count(), 0);
+    root.add(
+        global.clone(),
+        &"docs".to_string(),
+        Directory::new().to_enum(),
+    )
+    .await
+    .unwrap();
+    global.save_root(&root).await.unwrap();
+    assert!(bootstrap_path.exists());
+
+    // without the local copy, the root has to come from the superblock
+    fs::remove_file(&root_path).unwrap();
+    let mut root = global.get_root().await.unwrap();
+    assert_eq!(
+        root.list(global.clone()).await.unwrap(),
+        vec!["docs".to_string()]
+    );
+
+    root.add(
+        global.clone(),
+        &"music".to_string(),
+        Directory::new().to_enum(),
+    )
+    .await
+    .unwrap();
+    global.save_root(&root).await.unwrap();
+    fs::remove_file(&root_path).unwrap();
+    let mut names = global
+        .get_root()
+        .await
+        .unwrap()
+        .list(global.clone())
+        .await
+        .unwrap();
+    names.sort();
+    assert_eq!(names, vec!["docs".to_string(), "music".to_string()]);
+
+    let _ = fs::remove_file(&bootstrap_path);
+    for suffix in [".1", ".2", ".3", ".journal"] {
+        let _ = fs::remove_file(format!("{}{}", root_path.display(), suffix));
+    }
+}
+
+#[tokio::test]
+async fn versions_and_checksums() {
+    let bootstrap_path = temp_path("url");
+    let global = Arc::new(
+        from_str::<Global>(
+            r#"
+buckets:
+    memory:
+        source:
+            type: memory
+        "#,
+        )
+        .unwrap(),
+    );
+    let config = from_str::<SuperblockConfig>(&format!(
+        "bucket: memory\nbootstrap_path: {}",
+        bootstrap_path.display()
+    ))
+    .unwrap();
+
+    assert!(config.load(global.clone()).await.unwrap().is_none());
+    let first: Superblock = config
+        .save(global.clone(), &Directory::new())
+        .await
+        .unwrap();
+    let second = config
+        .save(global.clone(), &Directory::new())
+        .await
+        .unwrap();
+    assert_eq!(first.version, 1);
+    assert_eq!(second.version, 2);
+    // the previous root is deleted once the superblock points at the new one
+    assert!(first.root.get_bytes(global.clone()).await.is_err());
+
+    // a root that doesn't match its checksum is rejected
+    second
+        .root
+        .put(global.clone(), &Directory::new().to_enum())
+        .await
+        .unwrap();
+    assert!(config.load(global.clone()).await.is_err());
+
+    // a configured bootstrap descriptor opens the same superblock
+    let url = fs::read_to_string(&bootstrap_path).unwrap();
+    let _ = fs::remove_file(&bootstrap_path);
+    let opened = from_str::<SuperblockConfig>(&format!(
+        "bucket: memory\nbootstrap: \"{}\"\nbootstrap_path: {}",
+        url,
+        bootstrap_path.display()
+    ))
+    .unwrap();
+    assert_eq!(opened.location().unwrap().unwrap().as_url(), url);
+}
+
+#[tokio::test]
+async fn unreadable_superblocks_are_not_replaced() {
+    let root_path = temp_path("dat");
+    let bootstrap_path = temp_path("url");
+    let global = Arc::new(AsyncGlobal::new(
+        from_str::<Global>(&format!(
+            "root_path: {}\nsuperblock:\n    bucket: memory\n    bootstrap_path: {}\nbuckets:\n    memory:\n        source:\n            type: memory",
+            root_path.display(),
+            bootstrap_path.display()
+        ))
+        .unwrap(),
+    ));
+    global.save_root(&Directory::new()).await.unwrap();
+
+    let config = from_str::<SuperblockConfig>(&format!(
+        "bucket: memory\nbootstrap_path: {}",
+        bootstrap_path.display()
+    ))
+    .unwrap();
+    let (superblock, _) = config.load(global.clone()).await.unwrap().unwrap();
+    superblock
+        .root
+        .put(global.clone(), &Directory::new().to_enum())
+        .await
+        .unwrap();
+
+    // the local root is still there, but it may be older than the one the superblock points at
+    assert!(root_path.exists());
+    assert!(global.get_root().await.is_err());
+
+    let _ = fs::remove_file(&bootstrap_path);
+    for suffix in ["", ".1", ".2", ".3", ".journal"] {
+        let _ = fs::remove_file(format!("{}{}", root_path.display(), suffix));
+    }
+}
+
+#[tokio::test]
+async fn concurrent_writers_conflict() {
+    let bootstrap_path = temp_path("url");
+    let global = Arc::new(
+        from_str::<Global>("buckets:\n    memory:\n        source:\n            type: memory")
+            .unwrap(),
+    );
+    let writer = || {
+        from_str::<SuperblockConfig>(&format!(
+            "bucket: memory\nbootstrap_path: {}",
+            bootstrap_path.display()
+        ))
+        .unwrap()
+    };
+    let (ours, theirs) = (writer(), writer());
+
+    ours.save(global.clone(), &Directory::new()).await.unwrap();
+    // a writer that never read the superblock can't replace it
+    assert!(theirs
+        .save(global.clone(), &Directory::new())
+        .await
+        .is_err());
+
+    theirs.load(global.clone()).await.unwrap();
+    let saved = ours.save(global.clone(), &Directory::new()).await.unwrap();
+    let error = theirs
+        .save(global.clone(), &Directory::new())
+        .await
+        .unwrap_err();
+    assert!(matches!(error, Error::Conflict(_)));
+    // the root of the newer version is still there
+    assert!(saved.root.get_bytes(global.clone()).await.is_ok());
+    let (loaded, _) = theirs.load(global.clone()).await.unwrap().unwrap();
+    assert_eq!(loaded.version, saved.version);
+
+    let _ = fs::remove_file(&bootstrap_path);
+}
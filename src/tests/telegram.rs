@@ -0,0 +1,179 @@
+use actix_multipart::form::{bytes::Bytes, text::Text, MultipartForm};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use serde_json::{json, Value};
+use serde_yaml::from_str;
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::sources::{source::Source, telegram::Telegram};
+
+// A chat as the mock Bot API keeps it
+#[derive(Default)]
+struct Chat {
+    last_id: i64,
+    messages: HashMap<i64, String>, // message id to the file id of its document
+    files: HashMap<String, Vec<u8>>,
+    calls: Vec<String>,
+}
+
+type State = web::Data<Mutex<Chat>>;
+
+impl Chat {
+    fn send(&mut self, data: Vec<u8>) -> Value {
+        self.last_id += 1;
+        let file_id = format!("file{}", self.files.len());
+        self.files.insert(file_id.clone(), data);
+        self.messages.insert(self.last_id, file_id.clone());
+        message(self.last_id, &file_id)
+    }
+}
+
+fn message(message_id: i64, file_id: &str) -> Value {
+    json!({ "message_id": message_id, "document": { "file_id": file_id } })
+}
+
+fn ok(result: Value) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "ok": true, "result": result }))
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "ok": false,
+        "error_code": 400,
+        "description": "Bad Request: message not found",
+    }))
+}
+
+#[derive(MultipartForm)]
+struct Upload {
+    message_id: Option<Text<i64>>,
+    document: Option<Bytes>,
+    d: Option<Bytes>,
+}
+
+async fn upload(
+    state: State,
+    method: web::Path<(String, String)>,
+    form: MultipartForm<Upload>,
+) -> HttpResponse {
+    let mut chat = state.lock().unwrap();
+    chat.calls.push(method.1.clone());
+    match (method.1.as_str(), form.into_inner()) {
+        (
+            "sendDocument",
+            Upload {
+                document: Some(document),
+                ..
+            },
+        ) => {
+            let message = chat.send(document.data.to_vec());
+            ok(message)
+        }
+        (
+            "editMessageMedia",
+            Upload {
+                message_id: Some(id),
+                d: Some(d),
+                ..
+            },
+        ) => {
+            if !chat.messages.contains_key(&id.0) {
+                return not_found();
+            }
+            let file_id = format!("file{}", chat.files.len());
+            chat.files.insert(file_id.clone(), d.data.to_vec());
+            chat.messages.insert(id.0, file_id.clone());
+            ok(message(id.0, &file_id))
+        }
+        _ => HttpResponse::BadRequest().finish(),
+    }
+}
+
+async fn call(
+    state: State,
+    method: web::Path<(String, String)>,
+    body: web::Json<Value>,
+) -> HttpResponse {
+    let mut chat = state.lock().unwrap();
+    chat.calls.push(method.1.clone());
+    let id = body["message_id"].as_i64().unwrap_or_default();
+    match method.1.as_str() {
+        "forwardMessage" => match chat.messages.get(&id).cloned() {
+            Some(file_id) => {
+                chat.last_id += 1;
+                let forwarded = chat.last_id;
+                chat.messages.insert(forwarded, file_id.clone());
+                ok(message(forwarded, &file_id))
+            }
+            None => not_found(),
+        },
+        "deleteMessage" => match chat.messages.remove(&id) {
+            Some(_) => ok(json!(true)),
+            None => not_found(),
+        },
+        "getFile" => ok(json!({ "file_path": body["file_id"] })),
+        _ => HttpResponse::BadRequest().finish(),
+    }
+}
+
+async fn download(state: State, path: web::Path<(String, String)>) -> HttpResponse {
+    match state.lock().unwrap().files.get(&path.1) {
+        Some(data) => HttpResponse::Ok().body(data.clone()),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+// Serves the mock Bot API on a free port, returns the source's config
+fn serve(state: State) -> String {
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .route(
+                "/bot{token}/{method:sendDocument|editMessageMedia}",
+                web::post().to(upload),
+            )
+            .route("/bot{token}/{method}", web::post().to(call))
+            .route("/file/bot{token}/{path}", web::get().to(download))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let address = server.addrs()[0];
+    tokio::spawn(server.run());
+    format!(
+        "token: secret\nchat_id: \"-100\"\napi_url: http://{}",
+        address
+    )
+}
+
+#[tokio::test]
+async fn roundtrip() {
+    let state = web::Data::new(Mutex::new(Chat::default()));
+    let config = serve(state.clone());
+    let source = from_str::<Telegram>(&config).unwrap();
+
+    let data = [1u8, 2, 3, 4, 5].repeat(5);
+    let descriptor = source.create().await.unwrap();
+    source.put(&descriptor, data.clone()).await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), data);
+    // the file id of the new document is cached
+    assert!(!state
+        .lock()
+        .unwrap()
+        .calls
+        .contains(&"forwardMessage".to_string()));
+
+    // after a restart it is recovered from the message, which leaves no forwarded copy behind
+    let restarted = from_str::<Telegram>(&config).unwrap();
+    assert_eq!(restarted.get(&descriptor).await.unwrap(), data);
+    assert_eq!(restarted.get(&descriptor).await.unwrap(), data);
+    {
+        let chat = state.lock().unwrap();
+        let forwards = chat.calls.iter().filter(|call| *call == "forwardMessage");
+        assert_eq!(forwards.count(), 1);
+        assert_eq!(chat.messages.len(), 1);
+    }
+
+    source.delete(&descriptor).await.unwrap();
+    assert!(source.get(&descriptor).await.is_err());
+    assert!(source.put(&descriptor, data).await.is_err());
+}
@@ -0,0 +1,128 @@
+use serde_yaml::from_str;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+use super::utils::{cleanup, temp_root};
+use crate::{
+    global::{BlockingGlobal, Global},
+    inodes::{directory::Directory, file::File, inode::InodeType},
+    stored::Stored,
+};
+
+const SUFFIXES: &[&str] = &[".1", ".2", ".3", ".journal", ".trash"];
+
+fn global(root_path: &str, retention: u64) -> Arc<BlockingGlobal> {
+    Arc::new(BlockingGlobal::new(
+        from_str::<Global>(&format!(
+            "root_path: {}\ntrash:\n    retention: {}\nbuckets:\n    memory:\n        source:\n            type: memory",
+            root_path, retention
+        ))
+        .unwrap(),
+    ))
+}
+
+// root/dir/file, returns where the file is stored
+fn setup(global: &Arc<BlockingGlobal>) -> Stored {
+    let rt = Runtime::new().unwrap();
+    let mut dir = Directory::new();
+    let file = rt
+        .block_on(File::create(global.clone(), vec![1, 2, 3]))
+        .unwrap();
+    let stored = rt
+        .block_on(dir.add(global.clone(), &"file".to_string(), file.to_enum()))
+        .unwrap()
+        .clone();
+    let mut root = Directory::new();
+    rt.block_on(root.add(global.clone(), &"dir".to_string(), dir.to_enum()))
+        .unwrap();
+    global.save_root(&root).unwrap();
+    stored
+}
+
+// what the shell's rm does
+fn delete(global: &Arc<BlockingGlobal>) {
+    let rt = Runtime::new().unwrap();
+    let path = vec!["dir".to_string()];
+    let mut directory_path = rt
+        .block_on(global.get_root().unwrap().open_path(global.clone(), &path))
+        .unwrap();
+    let stored = rt
+        .block_on(
+            directory_path
+                .directory()
+                .unlink(global.clone(), &"file".to_string()),
+        )
+        .unwrap();
+    let commit = rt.block_on(directory_path.commit(global.clone())).unwrap();
+    global.save_root(&commit.top).unwrap();
+    rt.block_on(commit.cleanup(global.clone()));
+    global.discard("file", path, None, stored).unwrap();
+}
+
+fn exists(global: &Arc<BlockingGlobal>, stored: &Stored) -> bool {
+    let rt = Runtime::new().unwrap();
+    rt.block_on(stored.get::<InodeType, _>(global.clone()))
+        .is_ok()
+}
+
+fn contains_file(global: &Arc<BlockingGlobal>) -> bool {
+    let rt = Runtime::new().unwrap();
+    let path = vec!["dir".to_string()];
+    let mut directory_path = rt
+        .block_on(global.get_root().unwrap().open_path(global.clone(), &path))
+        .unwrap();
+    rt.block_on(
+        directory_path
+            .directory()
+            .get(global.clone(), &"file".to_string()),
+    )
+    .is_ok()
+}
+
+#[test]
+fn deleted_entries_can_be_restored() {
+    let root_path = temp_root();
+    let global = global(&root_path, 3600);
+    let stored = setup(&global);
+
+    delete(&global);
+    assert!(!contains_file(&global));
+    assert!(exists(&global, &stored));
+    let entries = global.trash().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].original_path(), "/dir/file");
+
+    global.restore_from_trash(entries[0].id).unwrap();
+    assert!(contains_file(&global));
+    assert!(global.trash().unwrap().is_empty());
+    assert!(global.restore_from_trash(entries[0].id).is_err());
+    cleanup(&root_path, SUFFIXES);
+}
+
+#[test]
+fn purge_destroys_entries() {
+    let root_path = temp_root();
+    let global = global(&root_path, 3600);
+    let stored = setup(&global);
+
+    delete(&global);
+    // still within the retention
+    assert_eq!(global.purge_trash(false).unwrap(), 0);
+    assert!(exists(&global, &stored));
+    assert_eq!(global.purge_trash(true).unwrap(), 1);
+    assert!(!exists(&global, &stored));
+    assert!(global.trash().unwrap().is_empty());
+    cleanup(&root_path, SUFFIXES);
+}
+
+#[test]
+fn no_retention_deletes_right_away() {
+    let root_path = temp_root();
+    let global = global(&root_path, 0);
+    let stored = setup(&global);
+
+    delete(&global);
+    assert!(!exists(&global, &stored));
+    assert!(global.trash().unwrap().is_empty());
+    cleanup(&root_path, SUFFIXES);
+}
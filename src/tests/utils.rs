@@ -1,37 +1,75 @@
-use std::env;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::{env, fs};
 
-// This function is used to create a temporary config file for testing purposes
+// This function is used to create a temporary config file for testing purposes.
+// Chunks live in memory, so tests never touch the disk or see each other's data.
 pub fn make_temp_config(encryption: bool, size: usize) -> String {
     if encryption {
-        return format!(
+        format!(
             r#"
 buckets:
-    local1:
+    memory1:
         source:
-            type: local
-            folder: {}
+            type: memory
             max_size: {}
             descriptor_length: 3  # just in case we set extremely small block size for testing
         encryption:
             type: aes
             key: "12345678901234567890123456789012"
         "#,
-            env::temp_dir().display(),
             size
-        );
+        )
     } else {
-        return format!(
+        format!(
             r#"
 buckets:
-    local2:
+    memory2:
         source:
-            type: local
-            folder: {}
+            type: memory
             max_size: {}
             descriptor_length: 3  # just in case we set extremely small block size for testing
         "#,
-            env::temp_dir().display(),
             size
-        );
+        )
+    }
+}
+
+// Same as make_temp_config, but the memory source is wrapped in a faulty one with the given options
+pub fn make_faulty_config(size: usize, faults: &str) -> String {
+    format!(
+        r#"
+buckets:
+    faulty:
+        source:
+            type: faulty
+            seed: 42
+            {}
+            source:
+                type: memory
+                max_size: {}
+                descriptor_length: 3
+        "#,
+        faults, size
+    )
+}
+
+// A root_path in the temporary folder no other test uses
+pub fn temp_root() -> String {
+    let name = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect::<String>();
+    env::temp_dir()
+        .join(format!("chunkdrive-{}.dat", name))
+        .display()
+        .to_string()
+}
+
+// Removes the root, the files next to it ending in `suffixes` and its lock folder
+pub fn cleanup(root_path: &str, suffixes: &[&str]) {
+    let _ = fs::remove_dir_all(format!("{}.locks", root_path));
+    for suffix in [""].iter().chain(suffixes) {
+        let _ = fs::remove_file(format!("{}{}", root_path, suffix));
     }
 }
@@ -0,0 +1,158 @@
+use futures::StreamExt;
+use serde_yaml::from_str;
+use std::sync::Arc;
+
+use crate::{
+    error::Error,
+    global::{AsyncGlobal, Global},
+    inodes::{
+        directory::Directory,
+        file::{File, VersionRetention},
+        inode::InodeType,
+        path::DirectoryPath,
+    },
+    stored::Stored,
+};
+
+fn global(versions: &str) -> Arc<AsyncGlobal> {
+    Arc::new(AsyncGlobal::new(
+        from_str::<Global>(&format!(
+            "buckets:\n    memory:\n        source:\n            type: memory\n{}",
+            versions
+        ))
+        .unwrap(),
+    ))
+}
+
+async fn file(global: Arc<AsyncGlobal>, directory: &Directory) -> File {
+    match directory
+        .get(global.clone(), &"file".to_string())
+        .await
+        .unwrap()
+        .get::<InodeType, AsyncGlobal>(global)
+        .await
+        .unwrap()
+    {
+        InodeType::File(file) => file,
+        _ => panic!("not a file"),
+    }
+}
+
+async fn read(stream: futures::stream::BoxStream<'_, Result<Vec<u8>, Error>>) -> Vec<u8> {
+    stream.map(|chunk| chunk.unwrap()).concat().await
+}
+
+// Overwrites the file and commits the directory, which deletes what the overwrite replaced
+async fn write(global: Arc<AsyncGlobal>, directory: &mut Directory, data: Vec<u8>) -> Stored {
+    let file = File::create(global.clone(), data).await.unwrap();
+    let stored = directory
+        .overwrite(global.clone(), &"file".to_string(), file)
+        .await
+        .unwrap();
+    commit(global, directory).await;
+    stored
+}
+
+async fn commit(global: Arc<AsyncGlobal>, directory: &mut Directory) {
+    let path = DirectoryPath::new(std::mem::replace(directory, Directory::new()));
+    let commit = path.commit(global.clone()).await.unwrap();
+    commit.cleanup(global).await;
+    *directory = commit.top;
+}
+
+#[tokio::test]
+async fn overwrite_keeps_versions() {
+    let global = global("versions:\n    keep: 2");
+    let mut directory = Directory::new();
+    let first = write(global.clone(), &mut directory, vec![1]).await;
+    write(global.clone(), &mut directory, vec![2]).await;
+    write(global.clone(), &mut directory, vec![3]).await;
+
+    let current = file(global.clone(), &directory).await;
+    assert_eq!(read(current.get(global.clone())).await, vec![3]);
+    assert_eq!(current.versions.len(), 2);
+    let newest = current.version(1).unwrap().get(global.clone());
+    assert_eq!(read(newest).await, vec![2]);
+    assert!(current.version(3).is_err());
+
+    // the replaced inodes and the version beyond `keep` are gone
+    assert!(first
+        .get::<InodeType, AsyncGlobal>(global.clone())
+        .await
+        .is_err());
+    write(global.clone(), &mut directory, vec![4]).await;
+    let oldest = file(global.clone(), &directory).await;
+    assert_eq!(
+        read(oldest.version(2).unwrap().get(global.clone())).await,
+        vec![2]
+    );
+}
+
+#[tokio::test]
+async fn replaced_files_outlive_the_commit() {
+    let global = global("versions:\n    keep: 0");
+    let mut directory = Directory::new();
+    let first = write(global.clone(), &mut directory, vec![1]).await;
+    let file = File::create(global.clone(), vec![2]).await.unwrap();
+    directory
+        .overwrite(global.clone(), &"file".to_string(), file)
+        .await
+        .unwrap();
+
+    // the saved tree still points at the old inode until the directory is committed
+    assert!(first
+        .get::<InodeType, AsyncGlobal>(global.clone())
+        .await
+        .is_ok());
+    commit(global.clone(), &mut directory).await;
+    assert!(first
+        .get::<InodeType, AsyncGlobal>(global.clone())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn restore_swaps_versions() {
+    let global = global("");
+    let mut directory = Directory::new();
+    write(global.clone(), &mut directory, vec![1]).await;
+    write(global.clone(), &mut directory, vec![2]).await;
+
+    directory
+        .restore_version(global.clone(), &"file".to_string(), 1)
+        .await
+        .unwrap();
+    let restored = file(global.clone(), &directory).await;
+    assert_eq!(read(restored.get(global.clone())).await, vec![1]);
+    assert_eq!(
+        read(restored.version(1).unwrap().get(global.clone())).await,
+        vec![2]
+    );
+    assert!(directory
+        .restore_version(global.clone(), &"file".to_string(), 2)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn age_limits_versions() {
+    let global = global("");
+    let retention = VersionRetention {
+        keep: 10,
+        max_age: Some(60),
+    };
+    let mut file = File::create(global.clone(), vec![2]).await.unwrap();
+    let old = File::create(global.clone(), vec![1]).await.unwrap();
+    assert!(file.supersede(old, &retention).is_empty());
+    file.versions[0].replaced -= 120;
+
+    // the version replaced two minutes ago is dropped, the one replaced just now stays
+    let mut newest = File::create(global.clone(), vec![3]).await.unwrap();
+    let dropped = newest.supersede(file, &retention);
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(newest.versions.len(), 1);
+    assert_eq!(
+        read(newest.version(1).unwrap().get(global.clone())).await,
+        vec![2]
+    );
+}
@@ -0,0 +1,168 @@
+use actix_web::{
+    http::header::{AUTHORIZATION, IF_MATCH, IF_NONE_MATCH},
+    web, App, HttpRequest, HttpResponse, HttpServer,
+};
+use serde_yaml::from_str;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use crate::sources::{source::Source, webdav::WebDav};
+
+// The files and collections the mock server keeps
+#[derive(Default)]
+struct Dav {
+    files: HashMap<String, Vec<u8>>,
+    collections: HashSet<String>,
+    taken: usize, // how many more creates answer as if the name was taken
+    authorization: Vec<String>,
+}
+
+type State = web::Data<Mutex<Dav>>;
+
+fn parent(path: &str) -> &str {
+    path.trim_end_matches('/')
+        .rsplit_once('/')
+        .map(|(parent, _)| parent)
+        .unwrap_or_default()
+}
+
+async fn dav(state: State, request: HttpRequest, body: web::Bytes) -> HttpResponse {
+    let mut dav = state.lock().unwrap();
+    if let Some(value) = request.headers().get(AUTHORIZATION) {
+        let value = value.to_str().unwrap().to_string();
+        dav.authorization.push(value);
+    }
+    let path = request.path().to_string();
+    let exists = dav.files.contains_key(&path);
+    match request.method().as_str() {
+        "MKCOL" => {
+            let path = path.trim_end_matches('/').to_string();
+            let missing_parent =
+                !parent(&path).is_empty() && !dav.collections.contains(parent(&path));
+            if missing_parent {
+                return HttpResponse::Conflict().finish();
+            }
+            match dav.collections.insert(path) {
+                true => HttpResponse::Created().finish(),
+                false => HttpResponse::MethodNotAllowed().finish(),
+            }
+        }
+        "PUT" => {
+            if !parent(&path).is_empty() && !dav.collections.contains(parent(&path)) {
+                return HttpResponse::Conflict().finish();
+            }
+            if request.headers().contains_key(IF_NONE_MATCH) {
+                if exists || dav.taken > 0 {
+                    dav.taken = dav.taken.saturating_sub(1);
+                    return HttpResponse::PreconditionFailed().finish();
+                }
+            } else if request.headers().contains_key(IF_MATCH) && !exists {
+                return HttpResponse::PreconditionFailed().finish();
+            }
+            dav.files.insert(path, body.to_vec());
+            match exists {
+                true => HttpResponse::NoContent().finish(),
+                false => HttpResponse::Created().finish(),
+            }
+        }
+        "GET" => match dav.files.get(&path) {
+            Some(data) => HttpResponse::Ok().body(data.clone()),
+            None => HttpResponse::NotFound().finish(),
+        },
+        "DELETE" => match dav.files.remove(&path) {
+            Some(_) => HttpResponse::NoContent().finish(),
+            None => HttpResponse::NotFound().finish(),
+        },
+        _ => HttpResponse::MethodNotAllowed().finish(),
+    }
+}
+
+// Serves the mock WebDAV server on a free port, returns its url
+fn serve(state: State) -> String {
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .default_service(web::to(dav))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let address = server.addrs()[0];
+    tokio::spawn(server.run());
+    format!("http://{}/dav", address)
+}
+
+fn state() -> State {
+    let mut dav = Dav::default();
+    dav.collections.insert("/dav".to_string());
+    web::Data::new(Mutex::new(dav))
+}
+
+#[tokio::test]
+async fn roundtrip() {
+    let state = state();
+    let url = serve(state.clone());
+    let source = from_str::<WebDav>(&format!("url: {}\nprefix: a/b", url)).unwrap();
+
+    let data = [1u8, 2, 3, 4, 5].repeat(5);
+    let descriptor = source.create().await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), Vec::<u8>::new());
+    source.put(&descriptor, data.clone()).await.unwrap();
+    assert_eq!(source.get(&descriptor).await.unwrap(), data);
+
+    // the prefix collections were created, and the chunk lives in the innermost one
+    {
+        let dav = state.lock().unwrap();
+        assert!(dav.collections.contains("/dav/a/b"));
+        let name = String::from_utf8(descriptor.clone()).unwrap();
+        assert!(dav.files.contains_key(&format!("/dav/a/b/{}", name)));
+    }
+
+    source.delete(&descriptor).await.unwrap();
+    assert!(source.get(&descriptor).await.is_err());
+    // If-Match keeps put from bringing a deleted chunk back
+    assert!(source.put(&descriptor, data).await.is_err());
+    assert!(state.lock().unwrap().files.is_empty());
+}
+
+#[tokio::test]
+async fn create_retries_taken_names() {
+    let state = state();
+    let url = serve(state.clone());
+    state.lock().unwrap().taken = 2;
+    let source = from_str::<WebDav>(&format!("url: {}", url)).unwrap();
+
+    let descriptor = source.create().await.unwrap();
+    let dav = state.lock().unwrap();
+    assert_eq!(dav.taken, 0);
+    assert_eq!(dav.files.len(), 1);
+    let name = String::from_utf8(descriptor).unwrap();
+    assert!(dav.files.contains_key(&format!("/dav/{}", name)));
+}
+
+#[tokio::test]
+async fn sends_credentials() {
+    let state = state();
+    let url = serve(state.clone());
+
+    let basic =
+        from_str::<WebDav>(&format!("url: {}\nusername: user\npassword: pass", url)).unwrap();
+    basic.create().await.unwrap();
+    // base64 of "user:pass"
+    assert_eq!(
+        state.lock().unwrap().authorization,
+        vec!["Basic dXNlcjpwYXNz".to_string()]
+    );
+
+    // the token wins over a username
+    let bearer =
+        from_str::<WebDav>(&format!("url: {}\nusername: user\ntoken: secret", url)).unwrap();
+    state.lock().unwrap().authorization.clear();
+    bearer.create().await.unwrap();
+    assert_eq!(
+        state.lock().unwrap().authorization,
+        vec!["Bearer secret".to_string()]
+    );
+}
@@ -0,0 +1,187 @@
+use futures::StreamExt;
+use serde_yaml::from_str;
+use std::sync::Arc;
+
+use super::utils::make_temp_config;
+use crate::{
+    global::Global,
+    inodes::{
+        directory::Directory,
+        file::File,
+        inode::{Inode, InodeType},
+        metadata::Size,
+        path::DirectoryPath,
+    },
+    stored::Stored,
+};
+
+// small chunks and two direct blocks, so a few hundred bytes are several stored blocks deep
+fn global(encryption: bool) -> Arc<Global> {
+    let config = format!(
+        "direct_block_count: 2\n{}",
+        make_temp_config(encryption, 160)
+    );
+    Arc::new(from_str::<Global>(&config).unwrap())
+}
+
+async fn read(global: Arc<Global>, file: &File) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut stream = file.get(global);
+    while let Some(chunk) = stream.next().await {
+        data.extend(chunk.unwrap());
+    }
+    data
+}
+
+enum Edit {
+    WriteAt(u64, Vec<u8>),
+    Append(Vec<u8>),
+    Truncate(u64),
+}
+
+async fn edits_match_a_vec(encryption: bool) {
+    let global = global(encryption);
+    let mut expected = (0..1000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+    let mut file = File::create(global.clone(), expected.clone())
+        .await
+        .unwrap();
+
+    let edits = vec![
+        Edit::WriteAt(5, vec![1; 3]),     // inside one chunk
+        Edit::WriteAt(50, vec![2; 100]),  // across direct chunks
+        Edit::WriteAt(280, vec![3; 300]), // from the direct chunks into the stored ones
+        Edit::WriteAt(990, vec![4; 25]),  // over the end
+        Edit::WriteAt(1100, vec![5; 10]), // after a gap
+        Edit::Append(vec![6; 7]),         // into the last chunk
+        Edit::Append(vec![7; 100]),       // past it
+        Edit::Truncate(700),              // inside the stored blocks
+        Edit::Truncate(295),              // inside the direct blocks
+        Edit::Truncate(400),              // growing
+        Edit::Truncate(0),
+        Edit::Append(vec![8; 45]),
+    ];
+    for edit in edits {
+        let before = read(global.clone(), &file).await;
+        let old = file.data.clone();
+        let replaced = match edit {
+            Edit::WriteAt(offset, data) => {
+                let start = offset as usize;
+                if expected.len() < start + data.len() {
+                    expected.resize(start + data.len(), 0);
+                }
+                expected[start..start + data.len()].copy_from_slice(&data);
+                file.write_at(global.clone(), offset, data).await.unwrap()
+            }
+            Edit::Append(data) => {
+                expected.extend(&data);
+                file.append(global.clone(), data).await.unwrap()
+            }
+            Edit::Truncate(size) => {
+                expected.resize(size as usize, 0);
+                file.truncate(global.clone(), size).await.unwrap()
+            }
+        };
+        assert_eq!(file.metadata.size, Size::Bytes(expected.len() as u64));
+
+        // the old data is untouched until the replaced chunks are deleted
+        let old = File {
+            data: old,
+            metadata: file.metadata.clone(),
+            versions: Vec::new(),
+        };
+        assert_eq!(read(global.clone(), &old).await, before);
+
+        let chunks = file.chunks(global.clone()).await.unwrap();
+        assert!(replaced.iter().all(|stored| !chunks.contains(stored)));
+        for stored in replaced {
+            stored.delete(global.clone()).await.unwrap();
+        }
+        assert_eq!(read(global.clone(), &file).await, expected);
+    }
+}
+
+#[tokio::test]
+async fn unencrypted_edits_match_a_vec() {
+    edits_match_a_vec(false).await;
+}
+
+#[tokio::test]
+async fn encrypted_edits_match_a_vec() {
+    edits_match_a_vec(true).await;
+}
+
+#[tokio::test]
+async fn writes_only_replace_the_chunks_they_touch() {
+    let global = global(false);
+    let mut file = File::create(global.clone(), vec![0; 200]).await.unwrap();
+    let chunks = file.chunks(global.clone()).await.unwrap();
+
+    let replaced = file
+        .write_at(global.clone(), 35, vec![1; 10])
+        .await
+        .unwrap();
+    assert_eq!(replaced.len(), 1);
+    let after = file.chunks(global.clone()).await.unwrap();
+    assert_eq!(after.len(), chunks.len());
+    assert_eq!(
+        after
+            .iter()
+            .filter(|stored| !chunks.contains(stored))
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn updated_files_are_saved_in_their_directory() {
+    let global = global(false);
+    let mut directory = Directory::new();
+    let name = "log".to_string();
+    let file = File::create(global.clone(), b"first line\n".to_vec())
+        .await
+        .unwrap();
+    directory
+        .add(global.clone(), &name, file.to_enum())
+        .await
+        .unwrap();
+
+    let stored = directory.get(global.clone(), &name).await.unwrap();
+    let mut file = match stored.get::<InodeType, _>(global.clone()).await.unwrap() {
+        InodeType::File(file) => file,
+        _ => panic!("not a file"),
+    };
+    let old_chunks = file.chunks(global.clone()).await.unwrap();
+    let replaced = file
+        .append(global.clone(), b"second line\n".to_vec())
+        .await
+        .unwrap();
+    let updated = directory
+        .update_file(global.clone(), &name, file, replaced)
+        .await
+        .unwrap();
+    assert_ne!(updated, stored);
+
+    let file = match updated.get::<InodeType, _>(global.clone()).await.unwrap() {
+        InodeType::File(file) => file,
+        _ => panic!("not a file"),
+    };
+    assert_eq!(
+        read(global.clone(), &file).await,
+        b"first line\nsecond line\n"
+    );
+    // the old inode and the chunks only it used stay until the directory is committed
+    assert!(stored.get::<InodeType, _>(global.clone()).await.is_ok());
+    for chunk in &old_chunks {
+        assert!(Stored::get_bytes(chunk, global.clone()).await.is_ok());
+    }
+    let commit = DirectoryPath::new(directory)
+        .commit(global.clone())
+        .await
+        .unwrap();
+    commit.cleanup(global.clone()).await;
+    assert!(stored.get::<InodeType, _>(global.clone()).await.is_err());
+    let chunks = file.chunks(global.clone()).await.unwrap();
+    for chunk in old_chunks.iter().filter(|chunk| !chunks.contains(chunk)) {
+        assert!(Stored::get_bytes(chunk, global.clone()).await.is_err());
+    }
+}
@@ -0,0 +1,266 @@
+/*
+   Deleting an entry moves it to the trash instead of destroying its chunks right away.
+   The trash remembers where every entry was deleted from and when, so it can be put back. Entries older than
+   the retention are purged, in the background while the services run or with `purge` in the shell.
+   Like snapshots, the trash is kept next to root_path, in `<root_path>.trash`. Changes to it hold the root's lock.
+*/
+
+use rmp_serde::Deserializer;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    error::Error,
+    global::GlobalTrait,
+    inodes::{
+        directory::Directory,
+        inode::{drop_link, Inode, InodeType},
+        path::Commit,
+    },
+    root_file::write_atomic,
+    stored::Stored,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct TrashConfig {
+    #[serde(default = "default_retention")]
+    pub retention: u64, // in seconds, 0 deletes entries right away
+    #[serde(default = "default_purge_interval")]
+    pub purge_interval: u64, // in seconds
+}
+
+const fn default_retention() -> u64 {
+    30 * 24 * 60 * 60
+}
+const fn default_purge_interval() -> u64 {
+    60 * 60
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            retention: default_retention(),
+            purge_interval: default_purge_interval(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrashEntry {
+    #[serde(rename = "i")]
+    pub id: u64,
+    #[serde(rename = "n")]
+    pub name: String,
+    #[serde(rename = "p")]
+    pub path: Vec<String>, // names of the directories it was in, from the top
+    #[serde(rename = "t")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top: Option<Stored>, // the shared directory it was deleted through, None for the root
+    #[serde(rename = "d")]
+    pub deleted: u64,
+    #[serde(rename = "s")]
+    pub stored: Stored,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Trash {
+    #[serde(rename = "e")]
+    entries: Vec<TrashEntry>,
+    #[serde(rename = "n")]
+    next: u64, // ids are never reused, so a stale page can't restore the wrong entry
+}
+
+fn trash_path(root_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.trash", root_path))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl TrashEntry {
+    pub fn original_path(&self) -> String {
+        let mut parts = self.path.clone();
+        parts.push(self.name.clone());
+        match &self.top {
+            Some(top) => format!("{}/{}", top.as_url(), parts.join("/")),
+            None => format!("/{}", parts.join("/")),
+        }
+    }
+
+    pub fn human_deleted(&self) -> String {
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(self.deleted);
+        let datetime: chrono::DateTime<chrono::Utc> = time.into();
+        datetime.format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+}
+
+impl Trash {
+    pub fn load(root_path: &str) -> Result<Self, Error> {
+        let data = match fs::read(trash_path(root_path)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(Error::io("Error reading trash", e)),
+        };
+        Self::deserialize(&mut Deserializer::new(&data[..]))
+            .map_err(|e| Error::deserialize("Error parsing trash", e))
+    }
+
+    fn save(&self, root_path: &str) -> Result<(), Error> {
+        write_atomic(&trash_path(root_path), &Stored::serialize(self)?)
+    }
+
+    pub fn list(&self) -> &[TrashEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, id: u64) -> Result<&TrashEntry, Error> {
+        self.entries
+            .iter()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| Error::NotFound(format!("Trash entry {} does not exist", id)))
+    }
+
+    // Every trashed entry in one directory, for walking their chunks
+    pub fn as_directory(&self) -> Directory {
+        Directory::with_children(
+            self.entries
+                .iter()
+                .map(|entry| (entry.id.to_string(), entry.stored.clone()))
+                .collect(),
+        )
+    }
+}
+
+// Records an entry that was just unlinked from the tree and saved without it
+pub fn add(
+    root_path: &str,
+    name: &str,
+    path: Vec<String>,
+    top: Option<Stored>,
+    stored: Stored,
+) -> Result<TrashEntry, Error> {
+    let mut trash = Trash::load(root_path)?;
+    let entry = TrashEntry {
+        id: trash.next,
+        name: name.to_string(),
+        path,
+        top,
+        deleted: now(),
+        stored,
+    };
+    trash.next += 1;
+    trash.entries.push(entry.clone());
+    trash.save(root_path)?;
+    Ok(entry)
+}
+
+// Links the entry back where it was deleted from and forgets it. The caller saves the new top, in place when
+// the entry has one and as the root otherwise, then calls Commit::cleanup.
+pub async fn restore<U: GlobalTrait + Send + Sync>(
+    global: Arc<U>,
+    root_path: &str,
+    id: u64,
+    root: Directory,
+) -> Result<(TrashEntry, Commit), Error> {
+    let mut trash = Trash::load(root_path)?;
+    let entry = trash.get(id)?.clone();
+    let top = match &entry.top {
+        Some(stored) if global.is_pinned(stored) => {
+            return Err(Error::Conflict(
+                "The directory it was in is part of a snapshot".to_string(),
+            ))
+        }
+        Some(stored) => match stored.get::<InodeType, U>(global.clone()).await? {
+            InodeType::Directory(directory) => directory,
+            _ => {
+                return Err(Error::Conflict(
+                    "The directory it was in is not a directory".to_string(),
+                ))
+            }
+        },
+        None => root,
+    };
+    let mut path = top.open_path(global.clone(), &entry.path).await?;
+    path.directory()
+        .put(global.clone(), &entry.name, entry.stored.clone())
+        .await?;
+    let commit = path.commit(global.clone()).await?;
+
+    // forgotten before the tree links it again, a crash in between only leaks it
+    trash.entries.retain(|entry| entry.id != id);
+    if let Err(e) = trash.save(root_path) {
+        for stored in &commit.path {
+            let _ = stored.delete(global.clone()).await;
+        }
+        return Err(e);
+    }
+    Ok((entry, commit))
+}
+
+// Forgets the entries deleted before `before`, they still have to be destroyed
+pub fn take_expired(root_path: &str, before: u64) -> Result<Vec<TrashEntry>, Error> {
+    let mut trash = Trash::load(root_path)?;
+    let (expired, kept) = trash
+        .entries
+        .drain(..)
+        .partition::<Vec<_>, _>(|entry| entry.deleted < before);
+    trash.entries = kept;
+    if !expired.is_empty() {
+        trash.save(root_path)?;
+    }
+    Ok(expired)
+}
+
+// Forgets the entries a restored snapshot linked back into the tree, purging them would break it
+pub async fn forget_linked<U: GlobalTrait + Send + Sync>(
+    global: Arc<U>,
+    root_path: &str,
+    root: &Directory,
+) -> Result<(), Error> {
+    let mut trash = Trash::load(root_path)?;
+    if trash.entries.is_empty() {
+        return Ok(());
+    }
+    let linked = root
+        .chunks(global)
+        .await?
+        .iter()
+        .map(|chunk| chunk.as_url())
+        .collect::<HashSet<_>>();
+    let count = trash.entries.len();
+    trash
+        .entries
+        .retain(|entry| !linked.contains(&entry.stored.as_url()));
+    match trash.entries.len() == count {
+        true => Ok(()),
+        false => trash.save(root_path),
+    }
+}
+
+// Deletes the entries and all of their chunks, hard linked ones only lose a link
+pub async fn destroy<U: GlobalTrait + Send + Sync>(
+    global: Arc<U>,
+    entries: Vec<Stored>,
+) -> Result<(), Error> {
+    let mut errors = Vec::new();
+    for stored in entries {
+        if let Err(e) = drop_link(global.clone(), &stored).await {
+            errors.push(e);
+        }
+    }
+    match errors.len() {
+        0 => Ok(()),
+        _ => Err(Error::many(errors)),
+    }
+}